@@ -0,0 +1,28 @@
+; 键值存储合约：set <键> <值> / get <键>
+; set 发出主题为 "set" 和键、数据为值的事件
+    METHOD
+    PUSHSTR "set"
+    EQ
+    JUMPI set
+    METHOD
+    PUSHSTR "get"
+    EQ
+    JUMPI get
+    PUSHSTR "Unknown method"
+    REVERT
+
+set:
+    ARG 1           ; 值
+    ARG 0           ; 键
+    SSTORE
+    PUSHSTR "set"
+    ARG 0
+    ARG 1
+    LOG 2
+    PUSHSTR "Value set successfully"
+    RETURN
+
+get:
+    ARG 0
+    SLOAD
+    RETURN
@@ -0,0 +1,62 @@
+;; WebAssembly 键值存储合约
+;;   set <键> <值>  写入并发出 "set" 事件（数据为键）
+;;   get <键>       读取值，不存在时回滚
+;;   owner          返回第一次调用 set 的地址
+;; 内存布局：0..256 键，256..1280 值，1280..1536 调用者地址，2048.. 常量字符串
+(module
+  (import "env" "storage_read" (func $storage_read (param i32 i32 i32 i32) (result i32)))
+  (import "env" "storage_write" (func $storage_write (param i32 i32 i32 i32)))
+  (import "env" "caller" (func $caller (param i32 i32) (result i32)))
+  (import "env" "arg" (func $arg (param i32 i32 i32) (result i32)))
+  (import "env" "emit_event" (func $emit_event (param i32 i32 i32 i32)))
+  (import "env" "set_return" (func $set_return (param i32 i32)))
+  (import "env" "revert" (func $revert (param i32 i32)))
+  (memory (export "memory") 1 1)
+  (data (i32.const 2048) "set")
+  (data (i32.const 2056) "owner")
+  (data (i32.const 2064) "Missing argument")
+  (data (i32.const 2096) "Key not found")
+
+  ;; 读取第 index 个参数到 ptr，缺失时回滚
+  (func $require_arg (param $index i32) (param $ptr i32) (param $cap i32) (result i32)
+    (local $len i32)
+    (local.set $len (call $arg (local.get $index) (local.get $ptr) (local.get $cap)))
+    (if (i32.lt_s (local.get $len) (i32.const 0))
+      (then (call $revert (i32.const 2064) (i32.const 16))))
+    (if (i32.gt_s (local.get $len) (local.get $cap))
+      (then (local.set $len (local.get $cap))))
+    (local.get $len))
+
+  (func (export "set")
+    (local $key_len i32)
+    (local $val_len i32)
+    (local $caller_len i32)
+    (local.set $key_len (call $require_arg (i32.const 0) (i32.const 0) (i32.const 256)))
+    (local.set $val_len (call $require_arg (i32.const 1) (i32.const 256) (i32.const 1024)))
+    (call $storage_write (i32.const 0) (local.get $key_len) (i32.const 256) (local.get $val_len))
+    ;; 第一次写入时记录 owner
+    (if (i32.lt_s (call $storage_read (i32.const 2056) (i32.const 5) (i32.const 1280) (i32.const 0)) (i32.const 0))
+      (then
+        (local.set $caller_len (call $caller (i32.const 1280) (i32.const 256)))
+        (call $storage_write (i32.const 2056) (i32.const 5) (i32.const 1280) (local.get $caller_len))))
+    (call $emit_event (i32.const 2048) (i32.const 3) (i32.const 0) (local.get $key_len)))
+
+  (func (export "get")
+    (local $key_len i32)
+    (local $val_len i32)
+    (local.set $key_len (call $require_arg (i32.const 0) (i32.const 0) (i32.const 256)))
+    (local.set $val_len (call $storage_read (i32.const 0) (local.get $key_len) (i32.const 256) (i32.const 1024)))
+    (if (i32.lt_s (local.get $val_len) (i32.const 0))
+      (then (call $revert (i32.const 2096) (i32.const 13))))
+    (call $set_return (i32.const 256) (local.get $val_len)))
+
+  (func (export "owner")
+    (local $len i32)
+    (local.set $len (call $storage_read (i32.const 2056) (i32.const 5) (i32.const 1280) (i32.const 256)))
+    (if (i32.lt_s (local.get $len) (i32.const 0))
+      (then (local.set $len (i32.const 0))))
+    (call $set_return (i32.const 1280) (local.get $len)))
+
+  ;; 死循环，用于演示燃料耗尽
+  (func (export "spin")
+    (loop $forever (br $forever))))
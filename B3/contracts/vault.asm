@@ -0,0 +1,66 @@
+; 金库合约：记录每个调用者的份额，withdraw 时从合约余额向调用者转账
+;   credit <地址> <金额>  增加地址的份额
+;   share <地址>          查询份额
+;   withdraw <金额>       调用者取回不超过份额的金额，并发出 withdraw 事件
+    METHOD
+    PUSHSTR "credit"
+    EQ
+    JUMPI credit
+    METHOD
+    PUSHSTR "share"
+    EQ
+    JUMPI share
+    METHOD
+    PUSHSTR "withdraw"
+    EQ
+    JUMPI withdraw
+    PUSHSTR "Unknown method"
+    REVERT
+
+credit:
+    PUSHSTR "share:"
+    ARG 0
+    CONCAT          ; 键 share:<地址>
+    DUP 0
+    SLOAD
+    ARG 1
+    ADD             ; 新份额
+    SWAP 0          ; 栈：新份额, 键
+    SSTORE
+    PUSHSTR "ok"
+    RETURN
+
+share:
+    PUSHSTR "share:"
+    ARG 0
+    CONCAT
+    SLOAD
+    RETURN
+
+withdraw:
+    PUSHSTR "share:"
+    CALLER
+    CONCAT          ; 键
+    DUP 0
+    SLOAD           ; 栈：键, 份额
+    DUP 0
+    ARG 0
+    LT              ; 份额 < 金额 ?
+    JUMPI insufficient
+    ARG 0
+    SUB             ; 栈：键, 剩余份额
+    SWAP 0
+    SSTORE
+    ARG 0
+    CALLER
+    TRANSFER
+    PUSHSTR "withdraw"
+    CALLER
+    ARG 0
+    LOG 2           ; 主题 "withdraw" 和调用者，数据为金额
+    PUSHSTR "withdrawn"
+    RETURN
+
+insufficient:
+    PUSHSTR "Insufficient share"
+    REVERT
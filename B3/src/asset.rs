@@ -0,0 +1,153 @@
+use serde::{Serialize, Deserialize};
+use crate::transaction::{Transaction, TxPayload};
+
+// 原生资产：交易的 asset 为空时表示原生币
+pub const NATIVE_ASSET: &str = "";
+
+// 链上原生资产：ID 为发行它的交易 ID
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Asset {
+    pub issuer: String, // 唯一有权增发的地址
+    pub name: String,
+    pub symbol: String,
+    #[serde(default)]
+    pub decimals: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_supply: Option<u64>, // 供应量上限，None 表示不限
+    pub supply: u64,             // 已发行总量
+}
+
+impl Asset {
+    // 发行者可以增发，增发后总量不能超过上限
+    pub fn check_mint(&self, tx: &Transaction) -> Result<(), String> {
+        if tx.sender != self.issuer {
+            return Err("Only the asset issuer can mint".to_string());
+        }
+        if tx.amount == 0 {
+            return Err("Mint amount must be positive".to_string());
+        }
+        let supply = self.supply.checked_add(tx.amount).ok_or("Asset supply overflow")?;
+        if self.max_supply.is_some_and(|max_supply| supply > max_supply) {
+            return Err("Mint would exceed the maximum supply".to_string());
+        }
+        Ok(())
+    }
+
+    // 距离供应量上限还可以增发的数量
+    pub fn remaining_supply(&self) -> Option<u64> {
+        self.max_supply.map(|max_supply| max_supply.saturating_sub(self.supply))
+    }
+}
+
+// 检查发行交易：名称和符号不能为空，初始发行量不能超过上限
+pub fn check_issue(tx: &Transaction, name: &str, symbol: &str, max_supply: Option<u64>) -> Result<(), String> {
+    if name.is_empty() || symbol.is_empty() {
+        return Err("Asset name and symbol must not be empty".to_string());
+    }
+    if tx.receiver.is_empty() {
+        return Err("Issue transactions must have a receiver for the initial supply".to_string());
+    }
+    if max_supply.is_some_and(|max_supply| tx.amount > max_supply) {
+        return Err("Initial supply exceeds the maximum supply".to_string());
+    }
+    Ok(())
+}
+
+// 创建发行资产的交易（未签名）：amount 为发给 receiver 的初始发行量，资产 ID 即交易 ID
+pub fn issue_transaction(issuer: &str, receiver: &str, amount: u64, name: &str, symbol: &str, decimals: u8, max_supply: Option<u64>) -> Transaction {
+    let mut tx = Transaction::new_unsigned(issuer.to_string(), receiver.to_string(), amount);
+    tx.payload = TxPayload::IssueAsset {
+        name: name.to_string(),
+        symbol: symbol.to_string(),
+        decimals,
+        max_supply,
+    };
+    tx
+}
+
+// 创建增发资产的交易（未签名）
+pub fn mint_transaction(issuer: &str, asset_id: &str, receiver: &str, amount: u64) -> Transaction {
+    let mut tx = Transaction::new_unsigned(issuer.to_string(), receiver.to_string(), amount);
+    tx.asset = asset_id.to_string();
+    tx.payload = TxPayload::MintAsset;
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::wallet::Wallet;
+
+    fn signed(mut tx: Transaction, wallet: &Wallet) -> Transaction {
+        tx.sign(&wallet.key_pair);
+        tx
+    }
+
+    // 发行上限为 1000 的资产，初始发行量 600 记入 issuer
+    fn issued(issuer: &Wallet) -> (Blockchain, String) {
+        let mut blockchain = Blockchain::new(1);
+        let issue = signed(issue_transaction(&issuer.address(), &issuer.address(), 600, "Gold", "GLD", 2, Some(1000)), issuer);
+        let asset_id = issue.id();
+        blockchain.produce_block(vec![issue], "miner", None).unwrap();
+        (blockchain, asset_id)
+    }
+
+    #[test]
+    fn issued_assets_transfer_independently_of_the_native_coin() {
+        let alice = Wallet::new();
+        let (mut blockchain, asset_id) = issued(&alice);
+        assert_eq!(blockchain.assets[&asset_id].supply, 600);
+        assert_eq!(blockchain.get_asset_balance(&alice.address(), &asset_id), 600);
+        assert_eq!(blockchain.get_balance(&alice.address()), 0);
+
+        let mut transfer = Transaction::new_unsigned(alice.address(), "bob".to_string(), 250);
+        transfer.asset = asset_id.clone();
+        let transfer = signed(transfer, &alice);
+        blockchain.produce_block(vec![transfer], "miner", None).unwrap();
+        assert_eq!(blockchain.get_asset_balance("bob", &asset_id), 250);
+        assert!(blockchain.get_holdings(&alice.address()).contains(&(asset_id.clone(), 350)));
+
+        // 资产余额不足，或资产不存在
+        let mut overspend = Transaction::new_unsigned(alice.address(), "bob".to_string(), 351);
+        overspend.asset = asset_id.clone();
+        assert!(blockchain.add_transaction(signed(overspend, &alice)).is_err());
+        let mut unknown = Transaction::new_unsigned(alice.address(), "bob".to_string(), 1);
+        unknown.asset = "unknown".to_string();
+        assert!(blockchain.add_transaction(signed(unknown, &alice)).is_err());
+    }
+
+    #[test]
+    fn only_the_issuer_mints_within_the_maximum_supply() {
+        let alice = Wallet::new();
+        let bob = Wallet::new();
+        let (mut blockchain, asset_id) = issued(&alice);
+
+        assert!(blockchain.add_transaction(signed(mint_transaction(&bob.address(), &asset_id, &bob.address(), 10), &bob)).is_err());
+        assert!(blockchain.add_transaction(signed(mint_transaction(&alice.address(), &asset_id, "bob", 0), &alice)).is_err());
+        // 交易池中的增发合计不能超过剩余的供应量
+        blockchain.add_transaction(signed(mint_transaction(&alice.address(), &asset_id, "bob", 300), &alice)).unwrap();
+        assert!(blockchain.add_transaction(signed(mint_transaction(&alice.address(), &asset_id, "carol", 101), &alice)).is_err());
+        // 同一区块中的两次增发合计超过上限时区块无效
+        let mints = vec![
+            signed(mint_transaction(&alice.address(), &asset_id, "carol", 250), &alice),
+            signed(mint_transaction(&alice.address(), &asset_id, "dave", 250), &alice),
+        ];
+        assert!(blockchain.produce_block(mints, "miner", None).is_err());
+
+        let transactions = std::mem::take(&mut blockchain.pending_transactions);
+        blockchain.produce_block(transactions, "miner", None).unwrap();
+        assert_eq!(blockchain.assets[&asset_id].supply, 900);
+        assert_eq!(blockchain.assets[&asset_id].remaining_supply(), Some(100));
+        assert!(blockchain.is_chain_valid());
+    }
+
+    #[test]
+    fn issue_parameters_are_checked() {
+        let tx = issue_transaction("alice", "alice", 10, "Gold", "GLD", 0, Some(5));
+        assert!(check_issue(&tx, "Gold", "GLD", Some(5)).is_err());
+        assert!(check_issue(&tx, "", "GLD", None).is_err());
+        assert!(check_issue(&issue_transaction("alice", "", 10, "Gold", "GLD", 0, None), "Gold", "GLD", None).is_err());
+        assert!(check_issue(&tx, "Gold", "GLD", None).is_ok());
+    }
+}
@@ -1,63 +1,221 @@
-use chrono::Utc;
 use serde::{Serialize, Deserialize};
 use crate::transaction::Transaction;
-use crate::merkle_tree::MerkleTree;
+use crate::merkle_tree::{MerkleProof, MerkleTree};
+use crate::finality::CommitCertificate;
 use sha2::{Sha256, Digest};
 
+pub const BLOCK_VERSION: u32 = 1; // 当前的区块头版本
+
+// 区块头：单独计算哈希，只同步区块头的轻客户端和节点不需要区块体
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub height: u64,
+    pub previous_hash: String,
+    pub merkle_root: String, // 区块体中交易的默克尔根
+    pub timestamp: i64,
+    pub target: usize, // 难度目标：区块哈希需要的前导零个数，创世区块为 0
+    pub nonce: u64,
+    #[serde(default)]
+    pub receipts_root: String, // 交易收据的默克尔根
+    #[serde(default)]
+    pub state_root: String, // 执行区块后的状态根（稀疏默克尔树）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub validator: String, // 权益证明：出块者地址
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub signature: String, // 出块者对区块哈希的签名，不参与哈希计算
+}
+
+// 区块：区块头和区块体（交易列表），hash 为区块头的哈希
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Block {
-    pub index: u64,
-    pub timestamp: i64,
-    pub transactions: Vec<Transaction>,
-    pub previous_hash: String,
+    pub header: BlockHeader,
     pub hash: String,
-    pub nonce: u64,
-    pub merkle_root: String, 
+    pub transactions: Vec<Transaction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<CommitCertificate>, // BFT 最终性：验证者对该区块的提交证书，不参与哈希计算
+}
+
+impl BlockHeader {
+    // 计算区块头的哈希：对除出块者签名外的全部字段按固定顺序编码为 JSON 后取 SHA-256，
+    // 字段之间有明确的边界，不同的区块头不会得到相同的编码
+    pub fn calculate_hash(&self) -> String {
+        let hashed = HashedHeader {
+            version: self.version,
+            height: self.height,
+            previous_hash: &self.previous_hash,
+            merkle_root: &self.merkle_root,
+            timestamp: self.timestamp,
+            target: self.target,
+            nonce: self.nonce,
+            receipts_root: &self.receipts_root,
+            state_root: &self.state_root,
+            validator: &self.validator,
+        };
+        hex::encode(Sha256::digest(serde_json::to_vec(&hashed).unwrap()))
+    }
+
+    // 哈希是否满足难度目标
+    pub fn meets_target(&self, hash: &str) -> bool {
+        hash.starts_with(&"0".repeat(self.target))
+    }
+
+    // 区块在分叉选择中的权重：难度为 d 的区块期望计算 16^d 次哈希（权益证明的区块为 1）
+    pub fn work(&self) -> u128 {
+        16u128.saturating_pow(self.target as u32)
+    }
+
+    // 检查区块头能否接在 previous（哈希为 previous_hash）之后：高度连续且哈希链接，返回区块头的哈希；
+    // 难度目标、工作量证明和出块者签名由共识引擎检查
+    pub fn validate(&self, previous: &BlockHeader, previous_hash: &str) -> Result<String, String> {
+        if self.height != previous.height + 1 {
+            return Err(format!("Header {} does not follow header {}", self.height, previous.height));
+        }
+        if self.previous_hash != previous_hash {
+            return Err(format!("Invalid previous hash for header {}", self.height));
+        }
+        Ok(self.calculate_hash())
+    }
+}
+
+// 区块头中参与哈希计算的字段
+#[derive(Serialize)]
+struct HashedHeader<'a> {
+    version: u32,
+    height: u64,
+    previous_hash: &'a str,
+    merkle_root: &'a str,
+    timestamp: i64,
+    target: usize,
+    nonce: u64,
+    receipts_root: &'a str,
+    state_root: &'a str,
+    validator: &'a str,
 }
 
 impl Block {
     // 创建一个新区块
-    pub fn new(index: u64, timestamp: i64, transactions: Vec<Transaction>, previous_hash: String) -> Self {
-        let merkle_tree = MerkleTree::new(transactions.iter().map(|tx| tx.to_string()).collect());
-        let merkle_root = hex::encode(merkle_tree.root_hash().unwrap());
-
-        let mut block = Block {
-            index,
-            timestamp,
-            transactions,
+    pub fn new(height: u64, timestamp: i64, transactions: Vec<Transaction>, previous_hash: String) -> Self {
+        let header = BlockHeader {
+            version: BLOCK_VERSION,
+            height,
             previous_hash,
-            hash: String::new(),
+            merkle_root: merkle_root(&transactions),
+            timestamp,
+            target: 0,
             nonce: 0,
-            merkle_root,
+            receipts_root: String::new(),
+            state_root: String::new(),
+            validator: String::new(),
+            signature: String::new(),
         };
-        block.hash = block.calculate_hash();
-        block
+        let hash = header.calculate_hash();
+        Block { header, hash, transactions, commit: None }
     }
 
-    // 计算区块的哈希值
+    // 计算区块的哈希值（即区块头的哈希）
     pub fn calculate_hash(&self) -> String {
-        let input = format!(
-            "{}{}{}{}{}{}",
-            self.index,
-            self.timestamp,
-            self.merkle_root,
-            self.transactions.len(),
-            self.previous_hash,
-            self.nonce
-        );
-        let mut hasher = Sha256::new(); 
-        hasher.update(input);
-        format!("{:x}", hasher.finalize())
-    }
-    
-
-    // 挖矿（PoW）
+        self.header.calculate_hash()
+    }
+
+    // 区块体是否与区块头中的默克尔根一致
+    pub fn has_valid_merkle_root(&self) -> bool {
+        self.header.merkle_root == merkle_root(&self.transactions)
+    }
+
+    // 生成第 position 笔交易对 merkle_root 的默克尔证明
+    pub fn merkle_proof(&self, position: usize) -> Option<MerkleProof> {
+        if position >= self.transactions.len() {
+            return None;
+        }
+        MerkleTree::new(self.transactions.iter().map(merkle_leaf).collect()).proof(position)
+    }
+
+    // 挖矿（PoW）：以 difficulty 为难度目标
     pub fn mine_block(&mut self, difficulty: usize) {
-        let target = "0".repeat(difficulty);
-        while &self.hash[..difficulty] != target {
-            self.nonce += 1;
+        self.header.target = difficulty;
+        self.hash = self.calculate_hash();
+        while !self.header.meets_target(&self.hash) {
+            self.header.nonce += 1;
             self.hash = self.calculate_hash();
         }
         println!("Block mined: {}", self.hash);
     }
 }
+
+// 交易在默克尔树中的叶子：完整的原始交易（包括签名、载荷、资产、输入输出和时间锁）
+pub fn merkle_leaf(transaction: &Transaction) -> String {
+    transaction.to_raw()
+}
+
+// 交易列表的默克尔根
+pub fn merkle_root(transactions: &[Transaction]) -> String {
+    let merkle_tree = MerkleTree::new(transactions.iter().map(merkle_leaf).collect());
+    hex::encode(merkle_tree.root_hash().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn child(previous: &Block, transactions: Vec<Transaction>) -> Block {
+        Block::new(previous.header.height + 1, previous.header.timestamp + 1, transactions, previous.hash.clone())
+    }
+
+    #[test]
+    fn the_block_hash_is_the_header_hash() {
+        let genesis = Block::new(0, 0, Vec::new(), "0".to_string());
+        let mut block = child(&genesis, vec![Transaction::new_unsigned("alice".to_string(), "bob".to_string(), 1)]);
+        assert_eq!(block.hash, block.header.calculate_hash());
+
+        // 出块者签名不参与哈希，区块体通过默克尔根参与
+        block.header.signature = "signature".to_string();
+        assert_eq!(block.calculate_hash(), block.hash);
+        block.transactions[0].amount = 2;
+        assert_eq!(block.calculate_hash(), block.hash);
+        assert!(!block.has_valid_merkle_root());
+        block.header.nonce += 1;
+        assert_ne!(block.calculate_hash(), block.hash);
+    }
+
+    #[test]
+    fn shifting_digits_between_target_and_nonce_changes_the_hash() {
+        let genesis = Block::new(0, 0, Vec::new(), "0".to_string());
+        let mut first = child(&genesis, Vec::new()).header;
+        let mut second = first.clone();
+        first.target = 1;
+        first.nonce = 23;
+        second.target = 12;
+        second.nonce = 3;
+        assert_ne!(first.calculate_hash(), second.calculate_hash());
+
+        // 字符串字段之间移动字符同样得到不同的哈希
+        let mut third = first.clone();
+        first.receipts_root = "ab".to_string();
+        first.state_root = "c".to_string();
+        third.receipts_root = "a".to_string();
+        third.state_root = "bc".to_string();
+        assert_ne!(first.calculate_hash(), third.calculate_hash());
+    }
+
+    #[test]
+    fn headers_link_by_height_and_previous_hash() {
+        let genesis = Block::new(0, 0, Vec::new(), "0".to_string());
+        let block = child(&genesis, Vec::new());
+        assert_eq!(block.header.validate(&genesis.header, &genesis.hash), Ok(block.hash.clone()));
+        assert!(block.header.validate(&genesis.header, &block.hash).is_err());
+        let grandchild = child(&block, Vec::new());
+        assert!(grandchild.header.validate(&genesis.header, &genesis.hash).is_err());
+    }
+
+    #[test]
+    fn mined_blocks_meet_their_target_and_carry_work() {
+        let genesis = Block::new(0, 0, Vec::new(), "0".to_string());
+        let mut block = child(&genesis, Vec::new());
+        block.mine_block(2);
+        assert!(block.hash.starts_with("00"));
+        assert!(block.header.meets_target(&block.hash));
+        assert_eq!(block.header.work(), 256);
+        assert_eq!(genesis.header.work(), 1);
+    }
+}
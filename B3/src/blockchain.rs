@@ -1,126 +1,1574 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::{self, Write, Read};
-use serde_json;
 use chrono::Utc;
-use crate::block::Block;
-use crate::transaction::Transaction;
-use crate::smart_contract::SmartContract;
-use crate::privacy::PrivacyTransaction;
+use crate::block::{Block, BlockHeader};
+use crate::transaction::{Transaction, TxPayload};
+use crate::smart_contract::{self, SmartContract};
+use crate::vm::{CallContext, ExecutionResult, VmError};
+use crate::privacy::{self, PrivacyTransaction, ProofStatement, VerifyingKeys};
+use crate::subscription::{EventBus, ChainEvent};
+use crate::tx_index::{TxIndex, TxLocation};
+use crate::utxo::{LedgerMode, OutPoint, TxOutput, UtxoSet};
+use crate::timelock::LockedFunds;
+use crate::wallet::Wallet;
+use crate::htlc::{Htlc, HtlcState};
+use crate::asset::{self, Asset, NATIVE_ASSET};
+use crate::state_tree::{StateProof, StateTree};
+use crate::receipt::{self, LogEntry, LogFilter, Receipt, ReceiptStatus};
+use crate::shielded_pool::{ShieldedPool, SHIELDED_SENDER};
+use crate::confidential::{self, ConfidentialOutput};
+use crate::light_client::TxProof;
+use crate::consensus::{Consensus, ConsensusConfig, ConsensusContext, ConsensusState};
+use crate::finality::CommitCertificate;
 use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+const MINING_REWARD: u64 = 50; // 挖矿奖励金额
+
+// 区块链概要信息
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainInfo {
+    pub height: u64,
+    pub tip: String,
+    pub difficulty: usize,
+    pub total_work: u128,   // 累计工作量（每个区块期望的哈希次数之和）
+    pub total_supply: u64,  // 所有地址余额之和
+    pub pending_transactions: usize,
+    #[serde(default)]
+    pub finalized_height: u64, // 最近一个有提交证书的区块高度
+}
+
+// 正在构建或执行的区块的上下文（用于检查时间锁等条件）
+#[derive(Debug, Clone, Copy)]
+pub struct BlockContext {
+    pub height: u64,
+    pub timestamp: i64,
+}
+
+// 回滚区块所需的数据：区块执行前被修改的状态的旧值
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BlockUndo {
+    pub balances: HashMap<String, Option<u64>>,
+    #[serde(default)]
+    pub utxos: HashMap<String, Option<TxOutput>>,
+    #[serde(default)]
+    pub locked_funds: HashMap<String, Option<Vec<LockedFunds>>>,
+    #[serde(default)]
+    pub htlcs: HashMap<String, Option<Htlc>>,
+    #[serde(default)]
+    pub contracts: HashMap<String, Option<SmartContract>>,
+    #[serde(default)]
+    pub asset_balances: HashMap<String, HashMap<String, Option<u64>>>,
+    #[serde(default)]
+    pub assets: HashMap<String, Option<Asset>>,
+    #[serde(default)]
+    pub shielded_pool: Option<ShieldedPool>,
+    #[serde(default)]
+    pub confidential_outputs: HashMap<String, Option<ConfidentialOutput>>,
+    #[serde(default)]
+    pub confidential_supply: Option<u64>,
+    #[serde(default)]
+    pub stakes: HashMap<String, Option<u64>>,
+    #[serde(default)]
+    pub authorities: HashMap<String, Option<u64>>,
+    #[serde(default)]
+    pub authority_votes: HashMap<String, Option<bool>>,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Blockchain {
     pub chain: Vec<Block>,
     pub difficulty: usize,
+    #[serde(default)]
+    pub ledger_mode: LedgerMode, // 账本模式，在创世时确定
+    #[serde(default)]
+    pub consensus: ConsensusConfig, // 共识配置，在创世时确定
+    #[serde(default)]
+    pub stakes: HashMap<String, u64>, // 验证者的质押（地址 -> 金额）
+    #[serde(default)]
+    pub authorities: HashMap<String, u64>, // 权威证明的签名者（地址 -> 最近签名的区块高度，0 表示尚未签名）
+    #[serde(default)]
+    pub authority_votes: HashMap<String, bool>, // 签名者的投票（"<候选者>:<投票者>" -> 是否增加）
+    #[serde(default)]
+    pub finality_validators: Vec<String>, // 创世时登记的 BFT 最终性验证者，之后不能修改
     pub contracts: HashMap<String, SmartContract>, // 存储智能合约
-    pub privacy_transactions: Vec<PrivacyTransaction>, // 存储隐私交易
     pub balances: HashMap<String, u64>, // 存储地址余额
+    #[serde(default)]
+    pub asset_balances: HashMap<String, HashMap<String, u64>>, // 原生币以外的资产余额（地址 -> 资产 ID -> 余额）
+    #[serde(default)]
+    pub assets: HashMap<String, Asset>, // 已发行的资产（交易 ID -> 资产）
+    #[serde(default)]
+    pub shielded_pool: ShieldedPool, // 隐私池（票据承诺树和已花费的作废标识）
+    #[serde(default)]
+    pub confidential_outputs: HashMap<String, ConfidentialOutput>, // 未花费的机密输出（"<交易ID>:<序号>" -> 输出）
+    #[serde(default)]
+    pub confidential_supply: u64, // 机密输出中的资金总额（公开存入减去公开取出）
+    #[serde(default)]
+    pub privacy_vks: Vec<String>, // 创世时登记的隐私转账验证密钥（十六进制），之后不能修改
+    #[serde(default)]
+    pub utxo_set: UtxoSet, // UTXO 模式下的未花费输出集合
+    #[serde(default)]
+    pub locked_funds: HashMap<String, Vec<LockedFunds>>, // 账户模式下被时间锁锁定的资金
+    #[serde(default)]
+    pub htlcs: HashMap<String, Htlc>, // 哈希时间锁（交易 ID -> HTLC）
+    #[serde(default)]
+    pub pending_transactions: Vec<Transaction>, // 交易池（未确认的交易）
+    #[serde(skip)]
+    pub events: EventBus, // 事件总线，用于推送订阅
+    #[serde(default)]
+    pub tx_index: Option<TxIndex>, // 可选的交易索引
+    #[serde(skip)]
+    confirmed_txs: HashSet<String>, // 已上链的交易 ID（加载时根据区块重建），用于拒绝重放的交易
+    #[serde(default)]
+    pub undo_data: HashMap<u64, BlockUndo>, // 每个区块的回滚数据
+    #[serde(default)]
+    pub receipts: HashMap<u64, Vec<Receipt>>, // 每个区块的交易收据（区块高度 -> 收据）
+    #[serde(skip)]
+    undo_recorder: Option<BlockUndo>, // 正在接入的区块的回滚数据
 }
 
 impl Blockchain {
     // 创建一个新的区块链
     pub fn new(difficulty: usize) -> Self {
+        Self::with_ledger_mode(difficulty, LedgerMode::Account)
+    }
+
+    // 创建指定账本模式的区块链
+    pub fn with_ledger_mode(difficulty: usize, ledger_mode: LedgerMode) -> Self {
+        Self::with_genesis(difficulty, ledger_mode, Vec::new(), ConsensusConfig::ProofOfWork, Vec::new()).unwrap()
+    }
+
+    // 创建区块链并在创世时登记隐私转账的验证密钥、共识配置和最终性验证者，创世区块的状态根承诺这些内容
+    pub fn with_genesis(
+        difficulty: usize,
+        ledger_mode: LedgerMode,
+        privacy_vks: Vec<String>,
+        consensus: ConsensusConfig,
+        finality_validators: Vec<String>,
+    ) -> Result<Self, String> {
+        VerifyingKeys::parse(&privacy_vks)?;
+        let mut validators: Vec<String> = Vec::new();
+        for validator in finality_validators {
+            if !is_address(&validator) {
+                return Err(format!("Invalid finality validator {}", validator));
+            }
+            if !validators.contains(&validator) {
+                validators.push(validator);
+            }
+        }
+        let mut stakes = HashMap::new();
+        let mut authorities = HashMap::new();
+        match &consensus {
+            ConsensusConfig::ProofOfWork => {}
+            ConsensusConfig::ProofOfStake { slot_duration, genesis_stakes } => {
+                if ledger_mode != LedgerMode::Account {
+                    return Err("Proof of stake requires the account ledger".to_string());
+                }
+                if *slot_duration <= 0 {
+                    return Err("Slot duration must be positive".to_string());
+                }
+                for (address, stake) in genesis_stakes {
+                    if !is_address(address) || *stake == 0 {
+                        return Err(format!("Invalid genesis stake {}:{}", address, stake));
+                    }
+                    *stakes.entry(address.clone()).or_insert(0) += stake;
+                }
+                if stakes.is_empty() {
+                    return Err("Proof of stake needs at least one genesis validator".to_string());
+                }
+            }
+            ConsensusConfig::ProofOfAuthority { period, genesis_signers } => {
+                if ledger_mode != LedgerMode::Account {
+                    return Err("Proof of authority requires the account ledger".to_string());
+                }
+                if *period < 0 {
+                    return Err("Period must not be negative".to_string());
+                }
+                for signer in genesis_signers {
+                    if !is_address(signer) {
+                        return Err(format!("Invalid genesis signer {}", signer));
+                    }
+                    authorities.insert(signer.clone(), 0);
+                }
+                if authorities.is_empty() {
+                    return Err("Proof of authority needs at least one genesis signer".to_string());
+                }
+            }
+        }
         let mut blockchain = Blockchain {
             chain: Vec::new(),
             difficulty,
+            ledger_mode,
+            consensus,
+            stakes,
+            authorities,
+            authority_votes: HashMap::new(),
+            finality_validators: validators,
             contracts: HashMap::new(),
-            privacy_transactions: Vec::new(),
             balances: HashMap::new(),
+            asset_balances: HashMap::new(),
+            assets: HashMap::new(),
+            shielded_pool: ShieldedPool::default(),
+            confidential_outputs: HashMap::new(),
+            confidential_supply: 0,
+            privacy_vks,
+            utxo_set: UtxoSet::default(),
+            locked_funds: HashMap::new(),
+            htlcs: HashMap::new(),
+            pending_transactions: Vec::new(),
+            events: EventBus::default(),
+            tx_index: None,
+            confirmed_txs: HashSet::new(),
+            undo_data: HashMap::new(),
+            receipts: HashMap::new(),
+            undo_recorder: None,
         };
         // 创建创世区块
-        let genesis_block = Block::new(0, Utc::now().timestamp(), Vec::new(), "0".to_string());
+        let mut genesis_block = Block::new(0, Utc::now().timestamp(), Vec::new(), "0".to_string());
+        genesis_block.header.state_root = blockchain.state_root();
+        genesis_block.hash = genesis_block.calculate_hash();
         blockchain.chain.push(genesis_block);
-        blockchain
+        Ok(blockchain)
     }
 
     // 获取地址的余额
     pub fn get_balance(&self, address: &str) -> u64 {
-        *self.balances.get(address).unwrap_or(&0)
+        match self.ledger_mode {
+            LedgerMode::Account => *self.balances.get(address).unwrap_or(&0),
+            LedgerMode::Utxo => self.utxo_set.balance_of(address),
+        }
+    }
+
+    // 地址在给定区块中可花费的余额（扣除尚未解锁的资金）
+    pub fn get_spendable_balance(&self, address: &str, context: &BlockContext) -> u64 {
+        match self.ledger_mode {
+            LedgerMode::Account => {
+                let locked: u64 = self.get_locked_funds(address)
+                    .iter()
+                    .filter(|funds| !funds.lock_until.is_unlocked(context.height, context.timestamp))
+                    .map(|funds| funds.amount)
+                    .sum();
+                self.get_balance(address).saturating_sub(locked)
+            }
+            LedgerMode::Utxo => self.utxo_set
+                .unspent_for(address)
+                .iter()
+                .filter(|(_, output)| output.is_spendable(context.height, context.timestamp))
+                .map(|(_, output)| output.amount)
+                .sum(),
+        }
+    }
+
+    // 获取地址持有的某种资产的余额
+    pub fn get_asset_balance(&self, address: &str, asset_id: &str) -> u64 {
+        if asset_id == NATIVE_ASSET {
+            return self.get_balance(address);
+        }
+        self.asset_balances
+            .get(address)
+            .and_then(|holdings| holdings.get(asset_id))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // 地址持有的所有资产：原生币在前，其余按资产 ID 排序
+    pub fn get_holdings(&self, address: &str) -> Vec<(String, u64)> {
+        let mut holdings: Vec<(String, u64)> = self.asset_balances
+            .get(address)
+            .map(|holdings| holdings.iter().map(|(asset_id, balance)| (asset_id.clone(), *balance)).collect())
+            .unwrap_or_default();
+        holdings.sort();
+        holdings.insert(0, (NATIVE_ASSET.to_string(), self.get_balance(address)));
+        holdings
+    }
+
+    // 地址可用于交易的资产余额，原生币需扣除尚未解锁的资金
+    fn get_spendable_asset_balance(&self, address: &str, asset_id: &str, context: &BlockContext) -> u64 {
+        if asset_id == NATIVE_ASSET {
+            return self.get_spendable_balance(address, context);
+        }
+        self.get_asset_balance(address, asset_id)
+    }
+
+    // 账户模式下地址被锁定的资金记录
+    pub fn get_locked_funds(&self, address: &str) -> &[LockedFunds] {
+        self.locked_funds.get(address).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    // 下一个区块的上下文
+    pub fn next_block_context(&self) -> BlockContext {
+        BlockContext {
+            height: self.get_latest_block().header.height + 1,
+            timestamp: Utc::now().timestamp(),
+        }
     }
 
-    // 设置地址的余额
-    pub fn set_balance(&mut self, address: &str, balance: u64) {
+    // 设置地址的余额，并记录回滚数据（只在执行区块时调用，区块之外修改余额会使状态根失效）
+    fn set_balance(&mut self, address: &str, balance: u64) {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            let previous = self.balances.get(address).copied();
+            undo.balances.entry(address.to_string()).or_insert(previous);
+        }
         self.balances.insert(address.to_string(), balance);
     }
 
+    // 设置地址的资产余额，并记录回滚数据（余额为 0 时移除记录）
+    fn set_asset_balance(&mut self, address: &str, asset_id: &str, balance: u64) {
+        if asset_id == NATIVE_ASSET {
+            return self.set_balance(address, balance);
+        }
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            let previous = self.asset_balances.get(address).and_then(|holdings| holdings.get(asset_id)).copied();
+            undo.asset_balances
+                .entry(address.to_string())
+                .or_default()
+                .entry(asset_id.to_string())
+                .or_insert(previous);
+        }
+        self.write_asset_balance(address, asset_id, Some(balance).filter(|balance| *balance > 0));
+    }
+
+    fn write_asset_balance(&mut self, address: &str, asset_id: &str, balance: Option<u64>) {
+        let holdings = self.asset_balances.entry(address.to_string()).or_default();
+        match balance {
+            Some(balance) => holdings.insert(asset_id.to_string(), balance),
+            None => holdings.remove(asset_id),
+        };
+        if holdings.is_empty() {
+            self.asset_balances.remove(address);
+        }
+    }
+
+    // 增加地址的资产余额，余额溢出时返回错误
+    fn credit_asset(&mut self, address: &str, asset_id: &str, amount: u64) -> Result<(), String> {
+        if amount == 0 {
+            return Ok(());
+        }
+        let balance = self.get_asset_balance(address, asset_id).checked_add(amount).ok_or_else(|| format!("Balance overflow for {}", address))?;
+        self.set_asset_balance(address, asset_id, balance);
+        Ok(())
+    }
+
+    // 减少地址的资产余额，余额不足时返回错误
+    fn debit_asset(&mut self, address: &str, asset_id: &str, amount: u64) -> Result<(), String> {
+        if amount == 0 {
+            return Ok(());
+        }
+        let balance = self.get_asset_balance(address, asset_id).checked_sub(amount).ok_or_else(|| format!("Insufficient balance for {}", address))?;
+        self.set_asset_balance(address, asset_id, balance);
+        Ok(())
+    }
+
+    // 增加地址余额
+    fn credit_balance(&mut self, address: &str, amount: u64) -> Result<(), String> {
+        self.credit_asset(address, NATIVE_ASSET, amount)
+    }
+
+    // 减少地址余额
+    fn debit_balance(&mut self, address: &str, amount: u64) -> Result<(), String> {
+        self.debit_asset(address, NATIVE_ASSET, amount)
+    }
+
     // 获取最新区块
     pub fn get_latest_block(&self) -> &Block {
         self.chain.last().unwrap()
     }
 
-    // 添加新区块
+    // 按高度或哈希查询区块
+    pub fn get_block(&self, height_or_hash: &str) -> Option<&Block> {
+        match height_or_hash.parse::<usize>() {
+            Ok(height) => self.chain.get(height),
+            Err(_) => self.chain.iter().find(|block| block.hash == height_or_hash),
+        }
+    }
+
+    // 查询高度区间 [from, to] 内的区块
+    pub fn get_blocks(&self, from: u64, to: u64) -> &[Block] {
+        let end = (to as usize).saturating_add(1).min(self.chain.len());
+        let start = (from as usize).min(end);
+        &self.chain[start..end]
+    }
+
+    // 累计工作量：各区块头难度目标对应的工作量之和（不含创世区块）
+    pub fn total_work(&self) -> u128 {
+        self.chain.iter().skip(1).map(|block| block.header.work()).sum()
+    }
+
+    // 当前流通总量（账户模式包含隐私池、机密输出和质押中的资金）
+    pub fn total_supply(&self) -> Result<u64, String> {
+        let total = match self.ledger_mode {
+            LedgerMode::Account => checked_sum(
+                self.balances
+                    .values()
+                    .chain(self.stakes.values())
+                    .copied()
+                    .chain([self.shielded_pool.value, self.confidential_supply]),
+            ),
+            LedgerMode::Utxo => self.utxo_set.total(),
+        };
+        total.ok_or_else(|| "Total supply overflows u64".to_string())
+    }
+
+    // 区块链概要信息
+    pub fn chain_info(&self) -> Result<ChainInfo, String> {
+        let tip = self.get_latest_block();
+        Ok(ChainInfo {
+            height: tip.header.height,
+            tip: tip.hash.clone(),
+            difficulty: self.difficulty,
+            total_work: self.total_work(),
+            total_supply: self.total_supply()?,
+            pending_transactions: self.pending_transactions.len(),
+            finalized_height: self.finalized_height(),
+        })
+    }
+
+    // 添加新区块（工作量证明，不需要出块者签名）
     pub fn add_block(&mut self, transactions: Vec<Transaction>, miner: &str) {
-        // 检查交易的有效性
-        for tx in &transactions {
-            if !self.is_transaction_valid(&tx) {
-                panic!("Invalid transaction: {:?}", tx);
+        if let Err(err) = self.produce_block(transactions, miner, None) {
+            panic!("{}", err);
+        }
+    }
+
+    // 出块：由共识引擎填写区块头中的共识字段，执行交易后封装区块；signer 为出块者的钱包
+    pub fn produce_block(&mut self, transactions: Vec<Transaction>, miner: &str, signer: Option<&Wallet>) -> Result<(), String> {
+        let context = self.next_block_context();
+        let index = context.height;
+        let previous_hash = self.get_latest_block().hash.clone();
+        self.verify_privacy_proofs(&transactions)?;
+
+        // 挖矿奖励作为区块的第一笔交易
+        let mut block_transactions = vec![self.coinbase(miner, index)];
+        block_transactions.extend(transactions);
+
+        // 创建新区块：共识引擎先检查出块资格，逐笔检查并执行交易得到收据，将收据根写入区块头后再封装
+        let mut new_block = Block::new(index, context.timestamp, block_transactions, previous_hash);
+        let engine = self.consensus_engine();
+        let state = self.consensus_state();
+        engine.prepare(&mut new_block.header, &self.consensus_context(&state), signer)?;
+        let (undo, receipts) = self.execute_block(&new_block)?;
+        new_block.header.receipts_root = receipt::receipts_root(&receipts);
+        new_block.header.state_root = self.state_root();
+        if let Err(err) = engine.seal(&mut new_block, signer) {
+            self.revert_state(undo);
+            return Err(err);
+        }
+        self.connect_block(new_block, undo, receipts);
+        Ok(())
+    }
+
+    // 当前链的共识引擎
+    pub fn consensus_engine(&self) -> Box<dyn Consensus> {
+        self.consensus.engine(self.difficulty)
+    }
+
+    // 按地址排序的质押和签名者（共识引擎按此顺序选择出块者）
+    pub fn consensus_state(&self) -> ConsensusState {
+        ConsensusState {
+            stakes: self.stakes.iter().map(|(address, stake)| (address.clone(), *stake)).collect(),
+            authorities: self.authorities.iter().map(|(address, last)| (address.clone(), *last)).collect(),
+        }
+    }
+
+    // 在当前最新区块之后出块或接入区块时的共识上下文
+    fn consensus_context<'a>(&'a self, state: &'a ConsensusState) -> ConsensusContext<'a> {
+        ConsensusContext { genesis: &self.chain[0].header, parent: &self.get_latest_block().header, state }
+    }
+
+    // 接入从其他节点收到的区块：检查区块头和区块体，重新执行交易并核对收据根和状态根
+    pub fn import_block(&mut self, block: Block) -> Result<(), String> {
+        let tip = self.get_latest_block();
+        let height = block.header.height;
+        if block.header.validate(&tip.header, &tip.hash)? != block.hash {
+            return Err(format!("Invalid hash for block {}", height));
+        }
+        let state = self.consensus_state();
+        self.consensus_engine().verify_header(&block.header, &block.hash, &self.consensus_context(&state))?;
+        if !block.has_valid_merkle_root() {
+            return Err(format!("Invalid merkle root for block {}", height));
+        }
+        self.check_commit(&block)?;
+
+        // 第一笔交易必须是本高度的挖矿奖励，其余交易在执行时按交易池的规则逐笔检查
+        let (coinbase, transactions) = block.transactions.split_first().ok_or_else(|| format!("Block {} has no coinbase", height))?;
+        if coinbase.id() != self.coinbase(&coinbase.receiver, height).id() {
+            return Err(format!("Invalid coinbase in block {}", height));
+        }
+        self.verify_privacy_proofs(transactions)?;
+
+        let (undo, receipts) = self.execute_block(&block)?;
+        if block.header.receipts_root != receipt::receipts_root(&receipts) || block.header.state_root != self.state_root() {
+            self.revert_state(undo);
+            return Err(format!("Receipts root or state root mismatch in block {}", height));
+        }
+        self.connect_block(block, undo, receipts);
+        Ok(())
+    }
+
+    // 高度 height 的挖矿奖励交易
+    fn coinbase(&self, miner: &str, height: u64) -> Transaction {
+        let mut coinbase = Transaction::coinbase(miner, MINING_REWARD, height);
+        if self.ledger_mode == LedgerMode::Utxo {
+            coinbase.outputs = vec![TxOutput::new(miner.to_string(), MINING_REWARD)];
+        }
+        coinbase
+    }
+
+    // 执行区块中的交易，返回回滚数据和每笔交易的收据；任一交易无效时恢复区块执行前的状态并返回错误
+    fn execute_block(&mut self, block: &Block) -> Result<(BlockUndo, Vec<Receipt>), String> {
+        self.undo_recorder = Some(BlockUndo::default());
+        let result = self.apply_transactions(block);
+        let undo = self.undo_recorder.take().unwrap_or_default();
+        match result {
+            Ok(receipts) => Ok((undo, receipts)),
+            Err(err) => {
+                self.revert_state(undo);
+                Err(err)
             }
         }
+    }
 
-        // 更新余额
-        for tx in &transactions {
-            self.update_balances(&tx);
+    // 逐笔检查并应用区块中的交易：每笔交易都在前面的交易执行后的状态上检查（第一笔为挖矿奖励）
+    fn apply_transactions(&mut self, block: &Block) -> Result<Vec<Receipt>, String> {
+        let context = BlockContext { height: block.header.height, timestamp: block.header.timestamp };
+        // 权威证明：先记录签名者本次签名的高度，再执行交易（交易中的投票可能移除该签名者）
+        if self.authorities.contains_key(&block.header.validator) {
+            self.set_authority(&block.header.validator, Some(block.header.height));
+        }
+        // 合约交易的手续费支付给出块者（挖矿奖励交易的接收方）
+        let miner = block.transactions.first().map(|tx| tx.receiver.clone()).unwrap_or_default();
+        let mut spent = HashSet::new();
+        let mut receipts = Vec::with_capacity(block.transactions.len());
+        for (position, tx) in block.transactions.iter().enumerate() {
+            if position > 0 && (tx.is_coinbase() || !self.is_transaction_valid(tx, &context, &mut spent)) {
+                return Err(format!("Invalid transaction {} in block {}", tx.id(), block.header.height));
+            }
+            let receipt = match self.ledger_mode {
+                LedgerMode::Account => self.update_balances(tx, &context, &miner)?,
+                LedgerMode::Utxo => {
+                    self.update_utxos(tx);
+                    Receipt::success(tx.id())
+                }
+            };
+            receipts.push(receipt);
         }
+        Ok(receipts)
+    }
+
+    // 将已执行的区块接入链：保存回滚数据和收据并更新索引，然后推送新区块和其中的合约事件
+    fn connect_block(&mut self, block: Block, undo: BlockUndo, receipts: Vec<Receipt>) {
+        let height = block.header.height;
+        self.undo_data.insert(height, undo);
+        self.receipts.insert(height, receipts);
+
+        if let Some(tx_index) = self.tx_index.as_mut() {
+            tx_index.connect_block(&block);
+        }
+        let included: Vec<String> = block.transactions.iter().map(Transaction::id).collect();
+        self.pending_transactions.retain(|tx| !included.contains(&tx.id()));
+        self.confirmed_txs.extend(included);
 
-        // 发放挖矿奖励
-        let mining_reward = 50; // 挖矿奖励金额
-        let reward_transaction = Transaction {
-            sender: "0".to_string(), // 系统地址
-            receiver: miner.to_string(),
-            amount: mining_reward,
-            signature: "mining_reward".to_string(), // 挖矿奖励不需要签名
+        self.chain.push(block.clone());
+        self.events.publish(ChainEvent::NewHead(block));
+        // 合约事件在区块上链后才从收据中推送，执行失败的区块中的事件不会推送
+        for receipt in &self.receipts[&height] {
+            for log in &receipt.logs {
+                self.events.publish(ChainEvent::ContractEvent { block_index: height, tx_id: receipt.tx_id.clone(), log: log.clone() });
+            }
+        }
+    }
+
+    // 最近一个有提交证书的区块高度，该区块及之前的区块都已最终确定（没有时为创世区块）
+    pub fn finalized_height(&self) -> u64 {
+        self.chain.iter().rev().find(|block| block.commit.is_some()).map(|block| block.header.height).unwrap_or(0)
+    }
+
+    // 检查区块携带的提交证书：针对该区块，且有超过 2/3 的最终性验证者签名（没有证书时不检查）
+    fn check_commit(&self, block: &Block) -> Result<(), String> {
+        let Some(commit) = &block.commit else {
+            return Ok(());
         };
-        self.update_balances(&reward_transaction);
+        if commit.height != block.header.height || commit.block_hash != block.hash {
+            return Err(format!("Commit certificate does not match block {}", block.header.height));
+        }
+        commit.verify(&self.finality_validators)
+    }
+
+    // 为本地链上的区块附加提交证书，区块高度需要高于已最终确定的高度
+    pub fn finalize(&mut self, certificate: CommitCertificate) -> Result<(), String> {
+        if self.finality_validators.is_empty() {
+            return Err("No finality validators registered in genesis".to_string());
+        }
+        if certificate.height <= self.finalized_height() {
+            return Err(format!("Block {} is already finalized", certificate.height));
+        }
+        let block = self.chain.get(certificate.height as usize).ok_or_else(|| format!("Block {} not found", certificate.height))?;
+        if block.hash != certificate.block_hash {
+            return Err(format!("Certificate is for a different block at height {}", certificate.height));
+        }
+        certificate.verify(&self.finality_validators)?;
+        let height = certificate.height as usize;
+        self.chain[height].commit = Some(certificate);
+        Ok(())
+    }
+
+    // 断开最新区块：恢复状态和索引，并将其中的普通交易放回交易池；已最终确定的区块不能断开
+    pub fn disconnect_latest_block(&mut self) -> Result<Block, String> {
+        if self.chain.len() <= 1 {
+            return Err("Cannot disconnect the genesis block".to_string());
+        }
+        let height = self.get_latest_block().header.height;
+        if height <= self.finalized_height() {
+            return Err(format!("Cannot disconnect finalized block {}", height));
+        }
+        let undo = self.undo_data.remove(&height).ok_or("Missing undo data for block")?;
+        let block = self.chain.pop().unwrap();
+        self.receipts.remove(&height);
+        self.revert_state(undo);
+        if let Some(tx_index) = self.tx_index.as_mut() {
+            tx_index.disconnect_block(&block);
+        }
+        for tx in &block.transactions {
+            self.confirmed_txs.remove(&tx.id());
+        }
+        self.pending_transactions.extend(block.transactions.iter().filter(|tx| !tx.is_coinbase()).cloned());
+        Ok(block)
+    }
+
+    // 按回滚数据恢复区块执行前的状态
+    fn revert_state(&mut self, undo: BlockUndo) {
+        for (address, balance) in undo.balances {
+            match balance {
+                Some(balance) => self.balances.insert(address, balance),
+                None => self.balances.remove(&address),
+            };
+        }
+        for (key, output) in undo.utxos {
+            match output {
+                Some(output) => self.utxo_set.outputs.insert(key, output),
+                None => self.utxo_set.outputs.remove(&key),
+            };
+        }
+        for (address, locked) in undo.locked_funds {
+            match locked {
+                Some(locked) => self.locked_funds.insert(address, locked),
+                None => self.locked_funds.remove(&address),
+            };
+        }
+        for (htlc_id, htlc) in undo.htlcs {
+            match htlc {
+                Some(htlc) => self.htlcs.insert(htlc_id, htlc),
+                None => self.htlcs.remove(&htlc_id),
+            };
+        }
+        for (contract_id, contract) in undo.contracts {
+            match contract {
+                Some(contract) => self.contracts.insert(contract_id, contract),
+                None => self.contracts.remove(&contract_id),
+            };
+        }
+        for (address, holdings) in undo.asset_balances {
+            for (asset_id, balance) in holdings {
+                self.write_asset_balance(&address, &asset_id, balance);
+            }
+        }
+        for (asset_id, asset) in undo.assets {
+            match asset {
+                Some(asset) => self.assets.insert(asset_id, asset),
+                None => self.assets.remove(&asset_id),
+            };
+        }
+        if let Some(pool) = undo.shielded_pool {
+            self.shielded_pool = pool;
+        }
+        for (id, output) in undo.confidential_outputs {
+            match output {
+                Some(output) => self.confidential_outputs.insert(id, output),
+                None => self.confidential_outputs.remove(&id),
+            };
+        }
+        if let Some(supply) = undo.confidential_supply {
+            self.confidential_supply = supply;
+        }
+        for (address, stake) in undo.stakes {
+            match stake {
+                Some(stake) => self.stakes.insert(address, stake),
+                None => self.stakes.remove(&address),
+            };
+        }
+        for (address, last) in undo.authorities {
+            match last {
+                Some(last) => self.authorities.insert(address, last),
+                None => self.authorities.remove(&address),
+            };
+        }
+        for (key, authorize) in undo.authority_votes {
+            match authorize {
+                Some(authorize) => self.authority_votes.insert(key, authorize),
+                None => self.authority_votes.remove(&key),
+            };
+        }
+    }
 
-        // 创建新区块
-        let latest_block = self.get_latest_block();
-        let mut new_block = Block::new(
-            latest_block.index + 1,
-            Utc::now().timestamp(),
-            transactions,
-            latest_block.hash.clone(),
-        );
-        new_block.mine_block(self.difficulty);
-        self.chain.push(new_block);
+    // 启用交易索引（根据现有区块重建）
+    pub fn enable_tx_index(&mut self) {
+        self.tx_index = Some(TxIndex::build(&self.chain));
     }
 
-    // 检查交易的有效性
-    fn is_transaction_valid(&self, tx: &Transaction) -> bool {
-        let sender_balance = self.get_balance(&tx.sender);
-        sender_balance >= tx.amount
+    // 根据交易 ID 查询交易及其位置，未启用索引时遍历整条链
+    pub fn get_transaction(&self, tx_id: &str) -> Option<(&Transaction, TxLocation)> {
+        if let Some(tx_index) = &self.tx_index {
+            let location = tx_index.get_location(tx_id)?;
+            let tx = self.chain.get(location.block_index as usize)?.transactions.get(location.position)?;
+            return Some((tx, location.clone()));
+        }
+        self.chain.iter().find_map(|block| {
+            block.transactions.iter().position(|tx| tx.id() == tx_id).map(|position| {
+                (&block.transactions[position], Self::location(block, position))
+            })
+        })
+    }
+
+    // 查询地址的历史交易（按上链顺序）
+    pub fn get_address_history(&self, address: &str) -> Vec<(&Transaction, TxLocation)> {
+        if let Some(tx_index) = &self.tx_index {
+            return tx_index
+                .get_history(address)
+                .iter()
+                .filter_map(|tx_id| self.get_transaction(tx_id))
+                .collect();
+        }
+        self.chain
+            .iter()
+            .flat_map(|block| {
+                block.transactions.iter().enumerate()
+                    .filter(|(_, tx)| tx.involves(address))
+                    .map(move |(position, tx)| (tx, Self::location(block, position)))
+            })
+            .collect()
     }
 
-    // 更新余额
-    fn update_balances(&mut self, tx: &Transaction) {
-        let sender_balance = self.get_balance(&tx.sender);
-        let receiver_balance = self.get_balance(&tx.receiver);
-        self.set_balance(&tx.sender, sender_balance - tx.amount);
-        self.set_balance(&tx.receiver, receiver_balance + tx.amount);
+    // 按交易 ID 查询交易收据
+    pub fn get_receipt(&self, tx_id: &str) -> Option<&Receipt> {
+        let (_, location) = self.get_transaction(tx_id)?;
+        self.receipts.get(&location.block_index)?.get(location.position)
+    }
+
+    // 从高度 from 开始的区块头（供轻客户端和其他节点同步）
+    pub fn get_headers(&self, from: u64) -> Vec<BlockHeader> {
+        self.chain.iter().skip(from as usize).map(|block| block.header.clone()).collect()
+    }
+
+    // 生成交易的包含证明（供轻客户端验证）
+    pub fn get_tx_proof(&self, tx_id: &str) -> Option<TxProof> {
+        let (tx, location) = self.get_transaction(tx_id)?;
+        let block = self.chain.get(location.block_index as usize)?;
+        Some(TxProof {
+            block_index: block.header.height,
+            block_hash: block.hash.clone(),
+            transaction: tx.clone(),
+            merkle_proof: block.merkle_proof(location.position)?,
+        })
+    }
+
+    // 查询高度区间内满足过滤条件的合约事件
+    pub fn get_logs(&self, filter: &LogFilter, from: u64, to: u64) -> Vec<LogEntry<'_>> {
+        self.get_blocks(from, to)
+            .iter()
+            .filter_map(|block| self.receipts.get(&block.header.height).map(|receipts| (block.header.height, receipts)))
+            .flat_map(|(block_index, receipts)| {
+                receipts.iter().flat_map(move |receipt| {
+                    receipt.logs
+                        .iter()
+                        .filter(|log| filter.matches(log))
+                        .map(move |log| LogEntry { block_index, tx_id: &receipt.tx_id, log })
+                })
+            })
+            .collect()
+    }
+
+    fn location(block: &Block, position: usize) -> TxLocation {
+        TxLocation {
+            block_index: block.header.height,
+            block_hash: block.hash.clone(),
+            position,
+        }
+    }
+
+    // 将交易加入交易池
+    pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), String> {
+        if !self.is_signature_valid(&transaction) {
+            return Err("Invalid transaction signature".to_string());
+        }
+        let id = transaction.id();
+        if self.confirmed_txs.contains(&id) || self.pending_transactions.iter().any(|tx| tx.id() == id) {
+            return Err(format!("Transaction {} is already confirmed or pending", id));
+        }
+        let context = self.next_block_context();
+        match self.ledger_mode {
+            LedgerMode::Account => {
+                self.check_payload(&transaction, &context)?;
+                // 已经生效的提议不再接受投票（区块中因较早的投票而失效的投票在执行时忽略）
+                if let TxPayload::Vote { candidate, authorize } = &transaction.payload {
+                    if *authorize == self.authorities.contains_key(candidate) {
+                        return Err(format!("{} is {} a signer", candidate, if *authorize { "already" } else { "not" }));
+                    }
+                }
+                self.verify_privacy_proofs(std::slice::from_ref(&transaction))?;
+                let pending_amount = checked_sum(
+                    self.pending_transactions
+                        .iter()
+                        .filter(|tx| tx.sender == transaction.sender && tx.asset == transaction.asset)
+                        .filter_map(Transaction::debit_amount)
+                        .chain([transaction.debit_amount().ok_or("Transaction amount overflow")?]),
+                )
+                .ok_or("Pending amount overflow")?;
+                let spendable = self.get_spendable_asset_balance(&transaction.sender, &transaction.asset, &context);
+                if spendable < pending_amount {
+                    return Err("Insufficient spendable balance".to_string());
+                }
+                // 交易池中的增发与本交易合计不能超过供应量上限
+                if transaction.payload == TxPayload::MintAsset {
+                    let pending_minted = checked_sum(
+                        self.pending_transactions
+                            .iter()
+                            .filter(|tx| tx.payload == TxPayload::MintAsset && tx.asset == transaction.asset)
+                            .map(|tx| tx.amount)
+                            .chain([transaction.amount]),
+                    )
+                    .ok_or("Pending mint amount overflow")?;
+                    let remaining = self.assets.get(&transaction.asset).and_then(Asset::remaining_supply);
+                    if remaining.is_some_and(|remaining| pending_minted > remaining) {
+                        return Err("Pending mints would exceed the maximum supply".to_string());
+                    }
+                }
+                // 交易池中的解除质押与本交易合计不能超过已有的质押
+                if transaction.payload == TxPayload::Unstake {
+                    let pending_unstaked = checked_sum(
+                        self.pending_transactions
+                            .iter()
+                            .filter(|tx| tx.payload == TxPayload::Unstake && tx.sender == transaction.sender)
+                            .map(|tx| tx.amount)
+                            .chain([transaction.amount]),
+                    )
+                    .ok_or("Pending unstake amount overflow")?;
+                    if pending_unstaked > self.stakes.get(&transaction.sender).copied().unwrap_or(0) {
+                        return Err("Pending unstakes would exceed the stake".to_string());
+                    }
+                }
+                // 与交易池中已有交易使用相同作废标识、机密输出或结算同一 HTLC 的交易视为双花
+                let mut spent = self.pending_spent();
+                if !claim_spent(&transaction, &mut spent) {
+                    return Err("Note, confidential output or HTLC already spent by a pending transaction".to_string());
+                }
+            }
+            LedgerMode::Utxo => {
+                // 与交易池中已有交易花费相同输出的交易视为双花
+                let mut spent = self.pending_spent_outputs();
+                if !self.is_utxo_transaction_valid(&transaction, &context, &mut spent) {
+                    return Err("Invalid, locked or double-spending UTXO transaction".to_string());
+                }
+            }
+        }
+        self.pending_transactions.push(transaction.clone());
+        self.events.publish(ChainEvent::PendingTransaction(transaction));
+        Ok(())
+    }
+
+    // 从交易池中移除在下一个区块中已经无效的交易（例如被已上链的交易花费了余额），返回移除的交易
+    pub fn remove_invalid_pending(&mut self) -> Vec<Transaction> {
+        let context = self.next_block_context();
+        let mut spent = HashSet::new();
+        let (valid, invalid) = std::mem::take(&mut self.pending_transactions)
+            .into_iter()
+            .partition(|tx| self.is_transaction_valid(tx, &context, &mut spent));
+        self.pending_transactions = valid;
+        invalid
+    }
+
+    // 检查交易的签名：携带多签账户定义的交易需要达到门限的不同签名者，普通账户验证单个签名，
+    // 隐私转账由零知识证明授权（在 check_payload 中验证）
+    fn is_signature_valid(&self, tx: &Transaction) -> bool {
+        if tx.sender == SHIELDED_SENDER && self.ledger_mode == LedgerMode::Account {
+            return matches!(tx.payload, TxPayload::ShieldedTransfer(_));
+        }
+        match tx.multisig {
+            Some(_) => tx.verify_multisig(),
+            None => tx.verify(),
+        }
+    }
+
+    // 检查交易在给定区块中的有效性，spent 记录本区块内已花费的输出（账户模式下为已使用的作废标识、机密输出和结算的 HTLC）
+    fn is_transaction_valid(&self, tx: &Transaction, context: &BlockContext, spent: &mut HashSet<String>) -> bool {
+        if !self.is_signature_valid(tx) {
+            return false;
+        }
+        // 已上链或在本区块中重复出现的交易是重放
+        let id = tx.id();
+        if self.confirmed_txs.contains(&id) || !spent.insert(format!("tx:{}", id)) {
+            return false;
+        }
+        match self.ledger_mode {
+            LedgerMode::Account => {
+                self.check_payload(tx, context).is_ok()
+                    && tx.debit_amount().is_some_and(|debit| self.get_spendable_asset_balance(&tx.sender, &tx.asset, context) >= debit)
+                    && claim_spent(tx, spent)
+            }
+            LedgerMode::Utxo => self.is_utxo_transaction_valid(tx, context, spent),
+        }
+    }
+
+    // 检查交易类型相关的规则（账户模式）
+    fn check_payload(&self, tx: &Transaction, context: &BlockContext) -> Result<(), String> {
+        // 原生币以外的资产只能转账和增发，且不支持时间锁
+        if !tx.asset.is_empty() {
+            if !self.assets.contains_key(&tx.asset) {
+                return Err("Asset not found".to_string());
+            }
+            if !matches!(tx.payload, TxPayload::Transfer | TxPayload::MintAsset) || tx.lock_until.is_some() {
+                return Err("Assets only support plain transfers and minting".to_string());
+            }
+        }
+        match &tx.payload {
+            TxPayload::Transfer => Ok(()),
+            TxPayload::Shield { .. } | TxPayload::ShieldedTransfer(_) | TxPayload::Confidential(_) if tx.lock_until.is_some() || !tx.asset.is_empty() => {
+                Err("Shielded and confidential transactions only support the native asset without time locks".to_string())
+            }
+            TxPayload::Confidential(transfer) => {
+                if transfer.withdraw > 0 && tx.receiver.is_empty() {
+                    return Err("Withdrawn funds need a receiver".to_string());
+                }
+                let mut commitments = Vec::new();
+                for (i, id) in transfer.inputs.iter().enumerate() {
+                    let output = self.confidential_outputs.get(id).ok_or_else(|| format!("Confidential output not found: {}", id))?;
+                    if output.owner != tx.sender || transfer.inputs[..i].contains(id) {
+                        return Err(format!("Cannot spend confidential output {}", id));
+                    }
+                    commitments.push(output.commitment.clone());
+                }
+                transfer.verify(&commitments, tx.amount)
+            }
+            TxPayload::Shield { note_key, blinding } => {
+                if !tx.receiver.is_empty() || tx.amount == 0 {
+                    return Err("Shield transactions must have a positive amount and no receiver".to_string());
+                }
+                privacy::note_commitment(tx.amount, note_key, blinding).map(|_| ())
+            }
+            TxPayload::ShieldedTransfer(transaction) => {
+                if tx.sender != SHIELDED_SENDER {
+                    return Err(format!("Shielded transfers must be sent by \"{}\"", SHIELDED_SENDER));
+                }
+                if tx.amount > 0 && tx.receiver.is_empty() {
+                    return Err("Unshielded funds need a receiver".to_string());
+                }
+                // 证明由 verify_privacy_proofs 单独（批量）验证
+                self.shielded_pool.check_transfer(transaction, tx.amount)
+            }
+            TxPayload::IssueAsset { .. } | TxPayload::MintAsset if tx.lock_until.is_some() => {
+                Err("Asset issuance cannot be time-locked".to_string())
+            }
+            TxPayload::IssueAsset { name, symbol, max_supply, .. } => asset::check_issue(tx, name, symbol, *max_supply),
+            TxPayload::MintAsset => {
+                let asset = self.assets.get(&tx.asset).ok_or("Mint transactions must specify an asset")?;
+                asset.check_mint(tx)
+            }
+            TxPayload::Deploy { .. } | TxPayload::Call { .. } if tx.lock_until.is_some() => {
+                Err("Contract transactions cannot be time-locked".to_string())
+            }
+            TxPayload::Deploy { kind, code } => {
+                if !tx.receiver.is_empty() {
+                    return Err("Deploy transactions must not have a receiver".to_string());
+                }
+                SmartContract::with_kind(*kind, code.clone()).validate()
+            }
+            TxPayload::Call { gas_limit, .. } => {
+                if !self.contracts.contains_key(&tx.receiver) {
+                    return Err("Contract not found".to_string());
+                }
+                if *gas_limit == 0 || *gas_limit > smart_contract::MAX_GAS_LIMIT {
+                    return Err(format!("Gas limit must be between 1 and {}", smart_contract::MAX_GAS_LIMIT));
+                }
+                Ok(())
+            }
+            TxPayload::HtlcLock { hashlock, timeout } => {
+                if hex::decode(hashlock).map(|bytes| bytes.len()) != Ok(32) {
+                    return Err("Hashlock must be a hex encoded SHA-256 hash".to_string());
+                }
+                if timeout.is_unlocked(context.height, context.timestamp) {
+                    return Err("HTLC timeout is already in the past".to_string());
+                }
+                Ok(())
+            }
+            TxPayload::HtlcClaim { htlc_id, preimage } => {
+                let htlc = self.htlcs.get(htlc_id).ok_or("HTLC not found")?;
+                htlc.check_claim(tx, preimage, context)
+            }
+            TxPayload::HtlcRefund { htlc_id } => {
+                let htlc = self.htlcs.get(htlc_id).ok_or("HTLC not found")?;
+                htlc.check_refund(tx, context)
+            }
+            TxPayload::Vote { candidate, authorize } => {
+                if !matches!(self.consensus, ConsensusConfig::ProofOfAuthority { .. }) {
+                    return Err("Votes are only used by proof of authority".to_string());
+                }
+                if !self.authorities.contains_key(&tx.sender) {
+                    return Err("Only authorized signers can vote".to_string());
+                }
+                if !tx.receiver.is_empty() || tx.amount != 0 || tx.lock_until.is_some() || !is_address(candidate) {
+                    return Err("Votes need a valid candidate and no receiver, amount or time lock".to_string());
+                }
+                if !*authorize && self.authorities.len() == 1 && self.authorities.contains_key(candidate) {
+                    return Err("Cannot remove the last signer".to_string());
+                }
+                Ok(())
+            }
+            TxPayload::Stake | TxPayload::Unstake if tx.lock_until.is_some() || !tx.receiver.is_empty() || tx.amount == 0 => {
+                Err("Staking transactions need a positive amount and no receiver or time lock".to_string())
+            }
+            TxPayload::Stake => Ok(()),
+            TxPayload::Unstake => {
+                let stake = self.stakes.get(&tx.sender).copied().unwrap_or(0);
+                if stake < tx.amount {
+                    return Err("Unstake amount exceeds the stake".to_string());
+                }
+                // 权益证明的链必须始终保留质押，否则无法选出出块者
+                let total: u64 = self.stakes.values().sum();
+                if matches!(self.consensus, ConsensusConfig::ProofOfStake { .. }) && total == tx.amount {
+                    return Err("Cannot unstake the last staked coins".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // 验证隐私转账的证明：只使用创世时登记的验证密钥，区块中的全部证明一起批量验证
+    fn verify_privacy_proofs(&self, transactions: &[Transaction]) -> Result<(), String> {
+        let shielded: Vec<(&Transaction, &PrivacyTransaction)> = transactions
+            .iter()
+            .filter_map(|tx| match &tx.payload {
+                TxPayload::ShieldedTransfer(transaction) => Some((tx, transaction)),
+                _ => None,
+            })
+            .collect();
+        if shielded.is_empty() {
+            return Ok(());
+        }
+        let keys = VerifyingKeys::parse(&self.privacy_vks)?;
+        if keys.is_empty() {
+            return Err("No privacy verifying key registered in genesis".to_string());
+        }
+        let statements: Vec<ProofStatement> = shielded
+            .iter()
+            .map(|(tx, transaction)| (*transaction, tx.amount, tx.receiver.as_str()))
+            .collect();
+        keys.verify_batch(&statements)
+            .map_err(|index| format!("Invalid shielded transfer proof in transaction {}", shielded[index].0.id()))
+    }
+
+    // 检查 UTXO 交易：输入必须存在、属于发送方、已解锁且未被花费，输入总额等于输出总额
+    fn is_utxo_transaction_valid(&self, tx: &Transaction, context: &BlockContext, spent: &mut HashSet<String>) -> bool {
+        // UTXO 模式只支持原生币的普通转账
+        if !tx.payload.is_transfer() || !tx.asset.is_empty() || tx.inputs.is_empty() || tx.outputs.is_empty() {
+            return false;
+        }
+        let mut input_total = 0u64;
+        let mut tx_spent = Vec::new();
+        for input in &tx.inputs {
+            let key = input.previous_output.to_string();
+            if spent.contains(&key) || tx_spent.contains(&key) {
+                return false; // 双花
+            }
+            match self.utxo_set.get(&input.previous_output) {
+                Some(output) if output.address == tx.sender && output.is_spendable(context.height, context.timestamp) => {
+                    match input_total.checked_add(output.amount) {
+                        Some(total) => input_total = total,
+                        None => return false, // 输入总额溢出
+                    }
+                }
+                _ => return false,
+            }
+            tx_spent.push(key);
+        }
+        // 输出总额溢出的交易无效
+        if checked_sum(tx.outputs.iter().map(|output| output.amount)) != Some(input_total) {
+            return false;
+        }
+        spent.extend(tx_spent);
+        true
+    }
+
+    // 交易池中的交易已经花费的输出
+    fn pending_spent_outputs(&self) -> HashSet<String> {
+        self.pending_transactions
+            .iter()
+            .flat_map(|tx| tx.inputs.iter().map(|input| input.previous_output.to_string()))
+            .collect()
+    }
+
+    // 地址可用于新交易的未花费输出（排除交易池中已被花费的和尚未解锁的）
+    pub fn spendable_outputs(&self, address: &str) -> Vec<(OutPoint, TxOutput)> {
+        let pending = self.pending_spent_outputs();
+        let context = self.next_block_context();
+        self.utxo_set
+            .unspent_for(address)
+            .into_iter()
+            .filter(|(outpoint, _)| !pending.contains(&outpoint.to_string()))
+            .filter(|(_, output)| output.is_spendable(context.height, context.timestamp))
+            .collect()
+    }
+
+    // 构建未签名的 UTXO 交易：为给定的支付输出选币，并把找零返还给发送方
+    pub fn create_utxo_transaction(&self, sender: &str, payments: Vec<TxOutput>) -> Result<Transaction, String> {
+        let target = checked_sum(payments.iter().map(|output| output.amount)).ok_or("Payment total overflow")?;
+        let (inputs, change) = Wallet::select_coins(&self.spendable_outputs(sender), target)?;
+        let mut outputs = payments;
+        if change > 0 {
+            outputs.push(TxOutput::new(sender.to_string(), change));
+        }
+        Ok(Transaction::new_utxo_unsigned(sender.to_string(), inputs, outputs))
+    }
+
+    // 修改 UTXO 集合，并记录回滚数据
+    fn set_utxo(&mut self, key: String, output: Option<TxOutput>) {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            let previous = self.utxo_set.outputs.get(&key).cloned();
+            undo.utxos.entry(key.clone()).or_insert(previous);
+        }
+        match output {
+            Some(output) => self.utxo_set.outputs.insert(key, output),
+            None => self.utxo_set.outputs.remove(&key),
+        };
+    }
+
+    // 更新 UTXO 集合：移除被花费的输出，加入新输出
+    fn update_utxos(&mut self, tx: &Transaction) {
+        for input in &tx.inputs {
+            self.set_utxo(input.previous_output.to_string(), None);
+        }
+        let tx_id = tx.id();
+        for (index, output) in tx.outputs.iter().enumerate() {
+            let outpoint = OutPoint { tx_id: tx_id.clone(), index: index as u32 };
+            self.set_utxo(outpoint.to_string(), Some(output.clone()));
+        }
+    }
+
+    // 更新余额并返回交易收据，合约交易的手续费支付给 miner；余额不足或溢出时返回错误
+    fn update_balances(&mut self, tx: &Transaction, context: &BlockContext, miner: &str) -> Result<Receipt, String> {
+        let mut receipt = Receipt::success(tx.id());
+        // 系统地址 "0" 发放的挖矿奖励和隐私池发出的转账不需要扣减发送方余额
+        if !tx.is_coinbase() && tx.sender != SHIELDED_SENDER {
+            let debit = tx.debit_amount().ok_or_else(|| format!("Amount overflow in transaction {}", tx.id()))?;
+            self.debit_asset(&tx.sender, &tx.asset, debit)?;
+        }
+
+        match &tx.payload {
+            TxPayload::Transfer => self.credit_asset(&tx.receiver, &tx.asset, tx.amount)?,
+            // 发行资产：资产 ID 为交易 ID，初始发行量记入接收方
+            TxPayload::IssueAsset { name, symbol, decimals, max_supply } => {
+                let asset_id = tx.id();
+                let asset = Asset {
+                    issuer: tx.sender.clone(),
+                    name: name.clone(),
+                    symbol: symbol.clone(),
+                    decimals: *decimals,
+                    max_supply: *max_supply,
+                    supply: tx.amount,
+                };
+                self.set_asset(&asset_id, asset);
+                self.credit_asset(&tx.receiver, &asset_id, tx.amount)?;
+                receipt.return_data = asset_id;
+            }
+            TxPayload::MintAsset => {
+                let mut asset = self.assets.get(&tx.asset).cloned().ok_or("Asset not found")?;
+                asset.supply = asset.supply.checked_add(tx.amount).ok_or("Asset supply overflow")?;
+                self.set_asset(&tx.asset, asset);
+                self.credit_asset(&tx.receiver, &tx.asset, tx.amount)?;
+            }
+            // 存入隐私池：树中只出现票据承诺，不出现接收方
+            TxPayload::Shield { note_key, blinding } => {
+                let commitment = privacy::note_commitment(tx.amount, note_key, blinding).expect("validated shield transaction");
+                let pool = self.shielded_pool_mut();
+                pool.append(&commitment)?;
+                pool.value = pool.value.checked_add(tx.amount).ok_or("Shielded pool value overflow")?;
+                receipt.return_data = commitment;
+            }
+            // 隐私转账：记录作废标识，追加新票据承诺，并把 amount 转出给 receiver
+            TxPayload::ShieldedTransfer(transaction) => {
+                let pool = self.shielded_pool_mut();
+                pool.nullifiers.extend(transaction.nullifiers.iter().cloned());
+                for commitment in &transaction.commitments {
+                    pool.append(commitment)?;
+                }
+                pool.value = pool.value.checked_sub(tx.amount).ok_or("Shielded pool value underflow")?;
+                self.credit_balance(&tx.receiver, tx.amount)?;
+            }
+            // 机密转账：移除花费的机密输出，加入新输出，取出的金额记入 receiver
+            TxPayload::Confidential(transfer) => {
+                let tx_id = tx.id();
+                for id in &transfer.inputs {
+                    self.set_confidential_output(id, None);
+                }
+                for (index, output) in transfer.outputs.iter().enumerate() {
+                    self.set_confidential_output(&confidential::output_id(&tx_id, index), Some(output.clone()));
+                }
+                let supply = self.confidential_supply
+                    .checked_add(tx.amount)
+                    .and_then(|supply| supply.checked_sub(transfer.withdraw))
+                    .ok_or("Confidential supply overflow")?;
+                self.set_confidential_supply(supply);
+                self.credit_balance(&tx.receiver, transfer.withdraw)?;
+            }
+            // 锁定的资金由 HTLC 持有，领取或退回时再记入余额
+            TxPayload::HtlcLock { hashlock, timeout } => {
+                let htlc = Htlc {
+                    sender: tx.sender.clone(),
+                    receiver: tx.receiver.clone(),
+                    amount: tx.amount,
+                    hashlock: hashlock.clone(),
+                    timeout: *timeout,
+                    state: HtlcState::Open,
+                };
+                self.set_htlc(&tx.id(), htlc);
+            }
+            TxPayload::HtlcClaim { htlc_id, preimage } => {
+                self.settle_htlc(htlc_id, HtlcState::Claimed { preimage: preimage.clone() })?;
+            }
+            TxPayload::HtlcRefund { htlc_id } => self.settle_htlc(htlc_id, HtlcState::Refunded)?,
+            // 发送方已预付最高手续费，部署按代码大小收费，调用按实际消耗的燃料收费并退回剩余部分
+            TxPayload::Deploy { kind, code } => {
+                let contract_id = tx.id();
+                self.set_contract(&contract_id, SmartContract::with_kind(*kind, code.clone()));
+                self.credit_balance(&contract_id, tx.amount)?;
+                self.credit_balance(miner, tx.max_fee())?;
+                receipt.gas_used = smart_contract::deploy_gas(*kind, code);
+                receipt.fee = tx.max_fee();
+                receipt.return_data = contract_id;
+            }
+            TxPayload::Call { method, args, gas_limit } => {
+                match self.apply_contract_call(tx, method, args, *gas_limit)? {
+                    Ok(result) => {
+                        receipt.gas_used = result.gas_used;
+                        receipt.return_data = result.return_value;
+                        receipt.logs = result.events;
+                    }
+                    Err(err) => {
+                        receipt.status = ReceiptStatus::Failed;
+                        receipt.gas_used = err.gas_used;
+                        receipt.error = Some(err.message);
+                    }
+                }
+                receipt.fee = smart_contract::gas_fee(receipt.gas_used);
+                self.credit_balance(&tx.sender, tx.max_fee() - receipt.fee)?;
+                self.credit_balance(miner, receipt.fee)?;
+            }
+            // 质押：从余额转入发送方的质押，解除质押：从质押退回余额
+            TxPayload::Stake => {
+                let stake = self.stakes.get(&tx.sender).copied().unwrap_or(0);
+                self.set_stake(&tx.sender, stake.checked_add(tx.amount).ok_or("Stake overflow")?);
+            }
+            TxPayload::Unstake => {
+                let stake = self.stakes.get(&tx.sender).copied().unwrap_or(0);
+                self.set_stake(&tx.sender, stake.checked_sub(tx.amount).ok_or("Unstake amount exceeds the stake")?);
+                self.credit_balance(&tx.sender, tx.amount)?;
+            }
+            TxPayload::Vote { candidate, authorize } => self.apply_vote(&tx.sender, candidate, *authorize),
+        }
+
+        // 带时间锁的转账：为接收方记录锁定资金，同时清理已经解锁的记录
+        if let Some(lock_until) = tx.lock_until {
+            let mut locked: Vec<LockedFunds> = self.get_locked_funds(&tx.receiver)
+                .iter()
+                .filter(|funds| !funds.lock_until.is_unlocked(context.height, context.timestamp))
+                .cloned()
+                .collect();
+            locked.push(LockedFunds { tx_id: tx.id(), amount: tx.amount, lock_until });
+            self.set_locked_funds(&tx.receiver, locked);
+        }
+        Ok(receipt)
+    }
+
+    // 修改资产，并记录回滚数据
+    fn set_asset(&mut self, asset_id: &str, asset: Asset) {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            let previous = self.assets.get(asset_id).cloned();
+            undo.assets.entry(asset_id.to_string()).or_insert(previous);
+        }
+        self.assets.insert(asset_id.to_string(), asset);
+    }
+
+    // 修改隐私池，修改前记录整个隐私池作为回滚数据
+    fn shielded_pool_mut(&mut self) -> &mut ShieldedPool {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            if undo.shielded_pool.is_none() {
+                undo.shielded_pool = Some(self.shielded_pool.clone());
+            }
+        }
+        &mut self.shielded_pool
+    }
+
+    // 交易池中的交易已经使用的作废标识、花费的机密输出和结算的 HTLC
+    fn pending_spent(&self) -> HashSet<String> {
+        self.pending_transactions.iter().flat_map(spent_keys).collect()
+    }
+
+    // 修改机密输出，并记录回滚数据
+    fn set_confidential_output(&mut self, id: &str, output: Option<ConfidentialOutput>) {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            let previous = self.confidential_outputs.get(id).cloned();
+            undo.confidential_outputs.entry(id.to_string()).or_insert(previous);
+        }
+        match output {
+            Some(output) => self.confidential_outputs.insert(id.to_string(), output),
+            None => self.confidential_outputs.remove(id),
+        };
+    }
+
+    // 修改地址的质押（为 0 时删除），并记录回滚数据
+    fn set_stake(&mut self, address: &str, stake: u64) {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            let previous = self.stakes.get(address).copied();
+            undo.stakes.entry(address.to_string()).or_insert(previous);
+        }
+        if stake == 0 {
+            self.stakes.remove(address);
+        } else {
+            self.stakes.insert(address.to_string(), stake);
+        }
+    }
+
+    // 修改签名者最近签名的高度（None 表示移除签名者），并记录回滚数据
+    fn set_authority(&mut self, address: &str, last: Option<u64>) {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            let previous = self.authorities.get(address).copied();
+            undo.authorities.entry(address.to_string()).or_insert(previous);
+        }
+        match last {
+            Some(last) => self.authorities.insert(address.to_string(), last),
+            None => self.authorities.remove(address),
+        };
+    }
+
+    // 修改投票（None 表示删除），并记录回滚数据
+    fn set_authority_vote(&mut self, key: &str, authorize: Option<bool>) {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            let previous = self.authority_votes.get(key).copied();
+            undo.authority_votes.entry(key.to_string()).or_insert(previous);
+        }
+        match authorize {
+            Some(authorize) => self.authority_votes.insert(key.to_string(), authorize),
+            None => self.authority_votes.remove(key),
+        };
+    }
+
+    // 记录签名者的投票：同一提议得到超过半数签名者的投票时增加或移除候选者，并清除与其相关的投票
+    fn apply_vote(&mut self, voter: &str, candidate: &str, authorize: bool) {
+        // 同一区块中较早的投票已经使提议生效
+        if authorize == self.authorities.contains_key(candidate) {
+            return;
+        }
+        self.set_authority_vote(&format!("{}:{}", candidate, voter), Some(authorize));
+        let prefix = format!("{}:", candidate);
+        let votes = self.authority_votes
+            .iter()
+            .filter(|(key, vote)| key.starts_with(&prefix) && **vote == authorize)
+            .count();
+        if votes <= self.authorities.len() / 2 {
+            return;
+        }
+        self.set_authority(candidate, if authorize { Some(0) } else { None });
+        let stale: Vec<String> = self.authority_votes
+            .keys()
+            .filter(|key| key.starts_with(&prefix) || (!authorize && key.ends_with(&format!(":{}", candidate))))
+            .cloned()
+            .collect();
+        for key in stale {
+            self.set_authority_vote(&key, None);
+        }
+    }
+
+    // 修改机密输出中的资金总额，并记录回滚数据
+    fn set_confidential_supply(&mut self, supply: u64) {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            undo.confidential_supply.get_or_insert(self.confidential_supply);
+        }
+        self.confidential_supply = supply;
+    }
+
+    // 地址拥有的未花费机密输出（排除交易池中已被花费的）
+    pub fn confidential_outputs_of(&self, owner: &str) -> Vec<(String, ConfidentialOutput)> {
+        let pending = self.pending_spent();
+        let mut outputs: Vec<(String, ConfidentialOutput)> = self.confidential_outputs
+            .iter()
+            .filter(|(id, output)| output.owner == owner && !pending.contains(&format!("confidential:{}", id)))
+            .map(|(id, output)| (id.clone(), output.clone()))
+            .collect();
+        outputs.sort_by(|a, b| a.0.cmp(&b.0));
+        outputs
+    }
+
+    // 修改 HTLC，并记录回滚数据
+    fn set_htlc(&mut self, htlc_id: &str, htlc: Htlc) {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            let previous = self.htlcs.get(htlc_id).cloned();
+            undo.htlcs.entry(htlc_id.to_string()).or_insert(previous);
+        }
+        self.htlcs.insert(htlc_id.to_string(), htlc);
+    }
+
+    // 结算 HTLC：领取时记入接收方余额，退回时记入发送方余额；每个 HTLC 只能结算一次
+    fn settle_htlc(&mut self, htlc_id: &str, state: HtlcState) -> Result<(), String> {
+        let mut htlc = self.htlcs.get(htlc_id).cloned().ok_or("HTLC not found")?;
+        if htlc.state != HtlcState::Open {
+            return Err(format!("HTLC {} is already settled", htlc_id));
+        }
+        let beneficiary = match state {
+            HtlcState::Refunded => htlc.sender.clone(),
+            _ => htlc.receiver.clone(),
+        };
+        self.credit_balance(&beneficiary, htlc.amount)?;
+        htlc.state = state;
+        self.set_htlc(htlc_id, htlc);
+        Ok(())
+    }
+
+    // 修改地址的锁定资金记录，并记录回滚数据
+    fn set_locked_funds(&mut self, address: &str, locked: Vec<LockedFunds>) {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            let previous = self.locked_funds.get(address).cloned();
+            undo.locked_funds.entry(address.to_string()).or_insert(previous);
+        }
+        self.locked_funds.insert(address.to_string(), locked);
     }
 
 
     // 验证区块链的完整性
     pub fn is_chain_valid(&self) -> bool {
+        let engine = self.consensus_engine();
         for i in 1..self.chain.len() {
             let current_block = &self.chain[i];
             let previous_block = &self.chain[i - 1];
 
-            // 检查当前区块的哈希是否正确
-            if current_block.hash != current_block.calculate_hash() {
-                println!("Invalid hash for block {}", current_block.index);
+            // 检查区块头：链接到前一个区块、区块哈希正确，且满足共识引擎的封装规则（难度目标或出块者签名）
+            match current_block.header.validate(&previous_block.header, &previous_block.hash) {
+                Ok(hash) if hash == current_block.hash => {}
+                Ok(_) => {
+                    println!("Invalid hash for block {}", current_block.header.height);
+                    return false;
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    return false;
+                }
+            }
+            if let Err(err) = engine.verify_seal(&current_block.header, &current_block.hash) {
+                println!("{}", err);
                 return false;
             }
 
-            // 检查前一个区块的哈希是否匹配
-            if current_block.previous_hash != previous_block.hash {
-                println!("Invalid previous hash for block {}", current_block.index);
+            if let Err(err) = self.check_commit(current_block) {
+                println!("{}", err);
+                return false;
+            }
+
+            // 检查区块体与区块头中的默克尔根是否一致
+            if !current_block.has_valid_merkle_root() {
+                println!("Invalid merkle root for block {}", current_block.header.height);
+                return false;
+            }
+
+            // 检查保存的收据与区块头中的收据根是否一致
+            if let Some(receipts) = self.receipts.get(&current_block.header.height) {
+                if current_block.header.receipts_root != receipt::receipts_root(receipts) {
+                    println!("Invalid receipts root for block {}", current_block.header.height);
+                    return false;
+                }
+            }
+        }
+
+        // 检查状态根：从当前状态出发，按回滚数据逐个区块倒推各区块执行后的状态（直到创世区块）；
+        // 倒推到父区块执行后的状态时，用其中的质押和签名者检查区块的出块者
+        let mut entries = self.state_entries();
+        for (height, block) in self.chain.iter().enumerate().rev() {
+            if !block.header.state_root.is_empty() && block.header.state_root != StateTree::new(&entries).root() {
+                println!("Invalid state root for block {}", block.header.height);
+                return false;
+            }
+            let Some(undo) = self.undo_data.get(&block.header.height) else {
+                break;
+            };
+            rewind_state(&mut entries, undo);
+            let state = consensus_state(&entries);
+            let context = ConsensusContext { genesis: &self.chain[0].header, parent: &self.chain[height - 1].header, state: &state };
+            if let Err(err) = engine.verify_header(&block.header, &block.hash, &context) {
+                println!("{}", err);
                 return false;
             }
         }
         true
     }
 
+    // 当前状态的全部键值：余额、资产、锁定资金、HTLC、UTXO、合约代码、合约存储、隐私池、机密输出、质押、签名者、投票、共识配置、最终性验证者和登记的验证密钥
+    pub fn state_entries(&self) -> BTreeMap<String, String> {
+        let mut entries = BTreeMap::new();
+        for (address, balance) in &self.balances {
+            apply_entry(&mut entries, account_entry(address, Some(*balance)));
+        }
+        for (address, holdings) in &self.asset_balances {
+            for (asset_id, balance) in holdings {
+                apply_entry(&mut entries, asset_balance_entry(address, asset_id, Some(*balance)));
+            }
+        }
+        for (asset_id, asset) in &self.assets {
+            apply_entry(&mut entries, json_entry("asset", asset_id, Some(asset)));
+        }
+        for (address, locked) in &self.locked_funds {
+            apply_entry(&mut entries, json_entry("locked", address, Some(locked).filter(|locked| !locked.is_empty())));
+        }
+        for (htlc_id, htlc) in &self.htlcs {
+            apply_entry(&mut entries, json_entry("htlc", htlc_id, Some(htlc)));
+        }
+        for (outpoint, output) in &self.utxo_set.outputs {
+            apply_entry(&mut entries, json_entry("utxo", outpoint, Some(output)));
+        }
+        for (contract_id, contract) in &self.contracts {
+            entries.extend(contract_entries(contract_id, contract));
+        }
+        entries.extend(shielded_entries(&self.shielded_pool));
+        for (id, output) in &self.confidential_outputs {
+            apply_entry(&mut entries, json_entry("confidential", id, Some(output)));
+        }
+        apply_entry(&mut entries, confidential_supply_entry(self.confidential_supply));
+        for (address, stake) in &self.stakes {
+            apply_entry(&mut entries, stake_entry(address, Some(*stake)));
+        }
+        for (address, last) in &self.authorities {
+            apply_entry(&mut entries, authority_entry(address, Some(*last)));
+        }
+        for (key, authorize) in &self.authority_votes {
+            apply_entry(&mut entries, authority_vote_entry(key, Some(*authorize)));
+        }
+        apply_entry(&mut entries, json_entry("consensus", "config", Some(&self.consensus)));
+        for validator in &self.finality_validators {
+            entries.insert(format!("finality_validator:{}", validator), "registered".to_string());
+        }
+        for vk in &self.privacy_vks {
+            entries.insert(format!("privacy_vk:{}", hex::encode(Sha256::digest(vk.as_bytes()))), "registered".to_string());
+        }
+        entries
+    }
+
+    // 当前状态的状态根
+    pub fn state_root(&self) -> String {
+        StateTree::new(&self.state_entries()).root()
+    }
+
+    // 生成状态键的默克尔证明（键不存在时为不存在证明）
+    pub fn get_state_proof(&self, key: &str) -> StateProof {
+        let entries = self.state_entries();
+        StateTree::new(&entries).prove(key, entries.get(key).map(String::as_str))
+    }
+
     // 将区块链保存到文件
     pub fn save_to_file(&self, filename: &str) -> io::Result<()> {
         let data = serde_json::to_string(self)?;
@@ -134,31 +1582,452 @@ impl Blockchain {
         let mut file = File::open(filename)?;
         let mut data = String::new();
         file.read_to_string(&mut data)?;
-        let blockchain: Blockchain = serde_json::from_str(&data)?;
+        let mut blockchain: Blockchain = serde_json::from_str(&data)?;
+        blockchain.confirmed_txs = blockchain.chain.iter().flat_map(|block| block.transactions.iter().map(Transaction::id)).collect();
         Ok(blockchain)
     }
 
-    // 部署智能合约
-    pub fn deploy_contract(&mut self, contract_id: String, code: String) {
-        let contract = SmartContract::new(code);
-        self.contracts.insert(contract_id, contract);
+    // 只读调用合约：在当前状态上模拟执行，不修改链上状态
+    pub fn query_contract(&self, contract_id: &str, caller: &str, method: &str, args: Vec<String>, gas_limit: u64) -> Result<ExecutionResult, VmError> {
+        self.run_contract(contract_id, caller, 0, method, args, gas_limit).map(|(_, result)| result)
     }
 
-    // 执行智能合约
-    pub fn execute_contract(&mut self, contract_id: &str, method: &str, args: Vec<String>) -> Result<String, String> {
-        if let Some(contract) = self.contracts.get_mut(contract_id) {
-            contract.execute(method, args)
-        } else {
-            Err("Contract not found".to_string())
+    // 在合约副本上执行方法，返回执行后的合约和结果
+    fn run_contract(&self, contract_id: &str, caller: &str, value: u64, method: &str, args: Vec<String>, gas_limit: u64) -> Result<(SmartContract, ExecutionResult), VmError> {
+        let mut contract = self.contracts.get(contract_id).cloned().ok_or_else(|| VmError {
+            message: "Contract not found".to_string(),
+            gas_used: 0,
+        })?;
+        let balance_of = |address: &str| self.get_balance(address);
+        let context = CallContext {
+            caller: caller.to_string(),
+            address: contract_id.to_string(),
+            value,
+            method: method.to_string(),
+            args,
+            gas_limit,
+            balance_of: &balance_of,
+        };
+        let result = contract.execute(&context)?;
+        Ok((contract, result))
+    }
+
+    // 执行合约调用：先将 amount 转入合约，执行成功后才应用状态修改、合约发出的转账和事件，执行失败时退回 amount；
+    // 内层错误为合约执行失败（记入收据），外层错误为余额溢出（整个区块无效）
+    fn apply_contract_call(&mut self, tx: &Transaction, method: &str, args: &[String], gas_limit: u64) -> Result<Result<ExecutionResult, VmError>, String> {
+        self.credit_balance(&tx.receiver, tx.amount)?;
+        let (contract, result) = match self.run_contract(&tx.receiver, &tx.sender, tx.amount, method, args.to_vec(), gas_limit) {
+            Ok(executed) => executed,
+            Err(err) => {
+                self.debit_balance(&tx.receiver, tx.amount)?;
+                self.credit_balance(&tx.sender, tx.amount)?;
+                return Ok(Err(err));
+            }
+        };
+        self.set_contract(&tx.receiver, contract);
+        for (receiver, amount) in &result.transfers {
+            self.debit_balance(&tx.receiver, *amount)?;
+            self.credit_balance(receiver, *amount)?;
         }
+        Ok(Ok(result))
     }
 
-    // 添加隐私交易
-    pub fn add_privacy_transaction(&mut self, transaction: PrivacyTransaction) {
-        if transaction.verify() {
-            self.privacy_transactions.push(transaction);
-        } else {
-            println!("Invalid privacy transaction");
+    // 修改合约，并记录回滚数据
+    fn set_contract(&mut self, contract_id: &str, contract: SmartContract) {
+        if let Some(undo) = self.undo_recorder.as_mut() {
+            let previous = self.contracts.get(contract_id).cloned();
+            undo.contracts.entry(contract_id.to_string()).or_insert(previous);
+        }
+        self.contracts.insert(contract_id.to_string(), contract);
+    }
+}
+
+// 状态树中的一个键值，值为 None 表示该键不存在（余额为 0 视为不存在）
+type StateEntry = (String, Option<String>);
+
+// 合约存储在状态树中的键
+pub fn storage_key(contract_id: &str, key: &str) -> String {
+    format!("storage:{}:{}", contract_id, key)
+}
+
+fn account_entry(address: &str, balance: Option<u64>) -> StateEntry {
+    (format!("account:{}", address), balance.filter(|balance| *balance > 0).map(|balance| balance.to_string()))
+}
+
+fn asset_balance_entry(address: &str, asset_id: &str, balance: Option<u64>) -> StateEntry {
+    let value = balance.filter(|balance| *balance > 0).map(|balance| balance.to_string());
+    (format!("asset_balance:{}:{}", address, asset_id), value)
+}
+
+fn stake_entry(address: &str, stake: Option<u64>) -> StateEntry {
+    (format!("stake:{}", address), stake.filter(|stake| *stake > 0).map(|stake| stake.to_string()))
+}
+
+fn authority_entry(address: &str, last: Option<u64>) -> StateEntry {
+    (format!("authority:{}", address), last.map(|last| last.to_string()))
+}
+
+fn authority_vote_entry(key: &str, authorize: Option<bool>) -> StateEntry {
+    (format!("authority_vote:{}", key), authorize.map(|authorize| authorize.to_string()))
+}
+
+// 从状态键值中读取质押和签名者
+fn consensus_state(entries: &BTreeMap<String, String>) -> ConsensusState {
+    let table = |prefix: &str| -> BTreeMap<String, u64> {
+        entries
+            .range(format!("{}:", prefix)..format!("{};", prefix))
+            .filter_map(|(key, value)| Some((key[prefix.len() + 1..].to_string(), value.parse().ok()?)))
+            .collect()
+    };
+    ConsensusState { stakes: table("stake"), authorities: table("authority") }
+}
+
+// 是否为 32 字节公钥的十六进制地址
+fn is_address(address: &str) -> bool {
+    hex::decode(address).map(|bytes| bytes.len()) == Ok(32)
+}
+
+fn confidential_supply_entry(supply: u64) -> StateEntry {
+    ("confidential_supply".to_string(), Some(supply).filter(|supply| *supply > 0).map(|supply| supply.to_string()))
+}
+
+fn json_entry<T: Serialize>(prefix: &str, id: &str, value: Option<&T>) -> StateEntry {
+    (format!("{}:{}", prefix, id), value.map(|value| serde_json::to_string(value).unwrap()))
+}
+
+// 合约的代码和每个存储项
+fn contract_entries(contract_id: &str, contract: &SmartContract) -> Vec<(String, String)> {
+    let code = serde_json::to_string(&(contract.kind, &contract.code)).unwrap();
+    let mut entries = vec![(format!("contract:{}", contract_id), code)];
+    entries.extend(contract.state.iter().map(|(key, value)| (storage_key(contract_id, key), value.clone())));
+    entries
+}
+
+// 隐私池的树根、票据数量、资金总额，以及每个已使用的作废标识
+fn shielded_entries(pool: &ShieldedPool) -> Vec<(String, String)> {
+    let summary = serde_json::to_string(&(&pool.root, pool.commitments.len(), pool.value)).unwrap();
+    let mut entries = vec![("shielded_pool".to_string(), summary)];
+    entries.extend(pool.nullifiers.iter().map(|nullifier| (format!("nullifier:{}", nullifier), "spent".to_string())));
+    entries
+}
+
+// 金额之和，溢出时返回 None
+fn checked_sum(amounts: impl IntoIterator<Item = u64>) -> Option<u64> {
+    amounts.into_iter().try_fold(0u64, u64::checked_add)
+}
+
+// 交易花费的一次性标记：隐私转账的作废标识、机密转账花费的机密输出，以及领取或退回的 HTLC
+fn spent_keys(tx: &Transaction) -> Vec<String> {
+    match &tx.payload {
+        TxPayload::ShieldedTransfer(transaction) => transaction.nullifiers.iter().map(|nullifier| format!("nullifier:{}", nullifier)).collect(),
+        TxPayload::Confidential(transfer) => transfer.inputs.iter().map(|id| format!("confidential:{}", id)).collect(),
+        TxPayload::HtlcClaim { htlc_id, .. } | TxPayload::HtlcRefund { htlc_id } => vec![format!("htlc:{}", htlc_id)],
+        _ => Vec::new(),
+    }
+}
+
+// 记录交易花费的标记，与 spent 中已有的重复时返回 false
+fn claim_spent(tx: &Transaction, spent: &mut HashSet<String>) -> bool {
+    let keys = spent_keys(tx);
+    if keys.iter().any(|key| spent.contains(key)) {
+        return false;
+    }
+    spent.extend(keys);
+    true
+}
+
+fn apply_entry(entries: &mut BTreeMap<String, String>, (key, value): StateEntry) {
+    match value {
+        Some(value) => entries.insert(key, value),
+        None => entries.remove(&key),
+    };
+}
+
+// 将状态键值恢复到区块执行之前
+fn rewind_state(entries: &mut BTreeMap<String, String>, undo: &BlockUndo) {
+    for (address, balance) in &undo.balances {
+        apply_entry(entries, account_entry(address, *balance));
+    }
+    for (address, holdings) in &undo.asset_balances {
+        for (asset_id, balance) in holdings {
+            apply_entry(entries, asset_balance_entry(address, asset_id, *balance));
         }
     }
+    for (asset_id, asset) in &undo.assets {
+        apply_entry(entries, json_entry("asset", asset_id, asset.as_ref()));
+    }
+    for (address, locked) in &undo.locked_funds {
+        apply_entry(entries, json_entry("locked", address, locked.as_ref().filter(|locked| !locked.is_empty())));
+    }
+    for (htlc_id, htlc) in &undo.htlcs {
+        apply_entry(entries, json_entry("htlc", htlc_id, htlc.as_ref()));
+    }
+    for (outpoint, output) in &undo.utxos {
+        apply_entry(entries, json_entry("utxo", outpoint, output.as_ref()));
+    }
+    for (contract_id, contract) in &undo.contracts {
+        let storage_prefix = storage_key(contract_id, "");
+        entries.retain(|key, _| !key.starts_with(&storage_prefix));
+        entries.remove(&format!("contract:{}", contract_id));
+        if let Some(contract) = contract {
+            entries.extend(contract_entries(contract_id, contract));
+        }
+    }
+    for (id, output) in &undo.confidential_outputs {
+        apply_entry(entries, json_entry("confidential", id, output.as_ref()));
+    }
+    if let Some(supply) = undo.confidential_supply {
+        apply_entry(entries, confidential_supply_entry(supply));
+    }
+    for (address, stake) in &undo.stakes {
+        apply_entry(entries, stake_entry(address, *stake));
+    }
+    for (address, last) in &undo.authorities {
+        apply_entry(entries, authority_entry(address, *last));
+    }
+    for (key, authorize) in &undo.authority_votes {
+        apply_entry(entries, authority_vote_entry(key, *authorize));
+    }
+    if let Some(pool) = &undo.shielded_pool {
+        entries.retain(|key, _| key != "shielded_pool" && !key.starts_with("nullifier:"));
+        entries.extend(shielded_entries(pool));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 难度为 1 的链，先挖一个区块把挖矿奖励发给 wallet
+    fn funded_chain(wallet: &Wallet) -> Blockchain {
+        let mut blockchain = Blockchain::new(1);
+        blockchain.add_block(Vec::new(), &wallet.address());
+        blockchain
+    }
+
+    fn transfer(from: &Wallet, to: &str, amount: u64) -> Transaction {
+        Transaction::new(from.address(), to.to_string(), amount, &from.key_pair)
+    }
+
+    // 用给定交易构造一个满足工作量证明的下一个区块（不执行交易，状态根随意）
+    fn mined_block(blockchain: &Blockchain, transactions: Vec<Transaction>) -> Block {
+        let tip = blockchain.get_latest_block();
+        let height = tip.header.height + 1;
+        let mut block_transactions = vec![blockchain.coinbase("miner", height)];
+        block_transactions.extend(transactions);
+        let mut block = Block::new(height, Utc::now().timestamp(), block_transactions, tip.hash.clone());
+        block.mine_block(blockchain.difficulty);
+        block
+    }
+
+    #[test]
+    fn double_spend_within_a_block_is_rejected() {
+        let alice = Wallet::new();
+        let mut blockchain = funded_chain(&alice);
+        let spends = vec![transfer(&alice, "bob", MINING_REWARD), transfer(&alice, "carol", MINING_REWARD)];
+
+        assert!(blockchain.produce_block(spends.clone(), "miner", None).is_err());
+        assert_eq!(blockchain.chain.len(), 2);
+        assert_eq!(blockchain.get_balance(&alice.address()), MINING_REWARD);
+        assert_eq!(blockchain.get_balance("bob"), 0);
+
+        let block = mined_block(&blockchain, spends);
+        assert!(blockchain.import_block(block).is_err());
+        assert_eq!(blockchain.get_balance(&alice.address()), MINING_REWARD);
+        assert_eq!(blockchain.get_balance("miner"), 0);
+        assert!(blockchain.is_chain_valid());
+    }
+
+    #[test]
+    fn utxo_totals_that_overflow_are_rejected() {
+        let alice = Wallet::new();
+        let mut blockchain = Blockchain::with_ledger_mode(1, LedgerMode::Utxo);
+        blockchain.add_block(Vec::new(), &alice.address());
+        let inputs = blockchain.spendable_outputs(&alice.address()).into_iter().map(|(outpoint, _)| outpoint).collect();
+        // 两个输出之和溢出后恰好等于输入总额
+        let outputs = vec![TxOutput::new("bob".to_string(), u64::MAX), TxOutput::new("carol".to_string(), MINING_REWARD + 1)];
+        let mut tx = Transaction::new_utxo_unsigned(alice.address(), inputs, outputs);
+        tx.sign(&alice.key_pair);
+
+        assert!(blockchain.add_transaction(tx.clone()).is_err());
+        assert!(blockchain.produce_block(vec![tx], "miner", None).is_err());
+        assert_eq!(blockchain.get_balance(&alice.address()), MINING_REWARD);
+
+        let payments = vec![TxOutput::new("bob".to_string(), u64::MAX), TxOutput::new("carol".to_string(), 1)];
+        assert!(blockchain.create_utxo_transaction(&alice.address(), payments).is_err());
+    }
+
+    #[test]
+    fn transactions_see_earlier_transactions_in_the_same_block() {
+        let alice = Wallet::new();
+        let bob = Wallet::new();
+        let mut blockchain = funded_chain(&alice);
+        let transactions = vec![transfer(&alice, &bob.address(), 30), transfer(&bob, "carol", 20)];
+
+        blockchain.produce_block(transactions, "miner", None).unwrap();
+        assert_eq!(blockchain.get_balance(&alice.address()), 20);
+        assert_eq!(blockchain.get_balance(&bob.address()), 10);
+        assert_eq!(blockchain.get_balance("carol"), 20);
+        assert!(blockchain.is_chain_valid());
+    }
+
+    #[test]
+    fn replayed_transactions_are_rejected() {
+        let alice = Wallet::new();
+        let mut blockchain = funded_chain(&alice);
+        let payment = transfer(&alice, "bob", 10);
+
+        blockchain.add_transaction(payment.clone()).unwrap();
+        assert!(blockchain.add_transaction(payment.clone()).is_err());
+        assert!(blockchain.produce_block(vec![payment.clone(), payment.clone()], "miner", None).is_err());
+        let transactions = std::mem::take(&mut blockchain.pending_transactions);
+        blockchain.produce_block(transactions, "miner", None).unwrap();
+
+        // 同一笔已签名的交易不能再次上链，重新加载后仍然如此
+        assert!(blockchain.add_transaction(payment.clone()).is_err());
+        assert!(blockchain.produce_block(vec![payment.clone()], "miner", None).is_err());
+        let path = std::env::temp_dir().join(format!("replay-{}.json", alice.address()));
+        blockchain.save_to_file(path.to_str().unwrap()).unwrap();
+        let mut reloaded = Blockchain::load_from_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(reloaded.produce_block(vec![payment.clone()], "miner", None).is_err());
+        assert_eq!(reloaded.get_balance("bob"), 10);
+
+        // 断开区块后交易回到交易池，可以重新上链
+        reloaded.disconnect_latest_block().unwrap();
+        assert_eq!(reloaded.pending_transactions.len(), 1);
+        let transactions = std::mem::take(&mut reloaded.pending_transactions);
+        reloaded.produce_block(transactions, "miner", None).unwrap();
+        assert_eq!(reloaded.get_balance("bob"), 10);
+    }
+
+    #[test]
+    fn only_invalid_pending_transactions_are_removed() {
+        let alice = Wallet::new();
+        let dave = Wallet::new();
+        let mut blockchain = funded_chain(&alice);
+        blockchain.add_block(Vec::new(), &dave.address());
+        blockchain.add_transaction(transfer(&alice, "bob", 40)).unwrap();
+        blockchain.add_transaction(transfer(&dave, "erin", 10)).unwrap();
+
+        // 其它节点的区块花掉了 alice 的余额，交易池中 alice 的交易失效
+        blockchain.produce_block(vec![transfer(&alice, "carol", 30)], "miner", None).unwrap();
+        assert!(blockchain.produce_block(blockchain.pending_transactions.clone(), "miner", None).is_err());
+        let removed = blockchain.remove_invalid_pending();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].receiver, "bob");
+
+        blockchain.produce_block(blockchain.pending_transactions.clone(), "miner", None).unwrap();
+        assert!(blockchain.pending_transactions.is_empty());
+        assert_eq!(blockchain.get_balance("erin"), 10);
+        assert_eq!(blockchain.get_balance("bob"), 0);
+    }
+
+    #[test]
+    fn unstakes_beyond_the_stake_in_one_block_are_rejected() {
+        let alice = Wallet::new();
+        let mut blockchain = funded_chain(&alice);
+        let mut stake = Transaction::new_unsigned(alice.address(), String::new(), 40);
+        stake.payload = TxPayload::Stake;
+        stake.sign(&alice.key_pair);
+        blockchain.produce_block(vec![stake], "miner", None).unwrap();
+
+        let unstakes: Vec<Transaction> = (0..2)
+            .map(|_| {
+                let mut unstake = Transaction::new_unsigned(alice.address(), String::new(), 30);
+                unstake.payload = TxPayload::Unstake;
+                unstake.sign(&alice.key_pair);
+                unstake
+            })
+            .collect();
+        assert!(blockchain.produce_block(unstakes, "miner", None).is_err());
+        assert_eq!(blockchain.stakes[&alice.address()], 40);
+        assert_eq!(blockchain.get_balance(&alice.address()), 10);
+    }
+
+    // 部署键值存储合约，返回合约地址
+    fn deploy_kv_store(blockchain: &mut Blockchain, owner: &Wallet) -> String {
+        let code = hex::encode(crate::vm::assemble(include_str!("../contracts/kv_store.asm")).unwrap());
+        let mut deploy = smart_contract::deploy_transaction(&owner.address(), smart_contract::ContractKind::Bytecode, code, 0);
+        deploy.sign(&owner.key_pair);
+        blockchain.produce_block(vec![deploy.clone()], "miner", None).unwrap();
+        deploy.id()
+    }
+
+    fn call(sender: &Wallet, contract_id: &str, args: &[&str]) -> Transaction {
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        let mut tx = smart_contract::call_transaction(&sender.address(), contract_id, "set", args, 0, 10_000);
+        tx.sign(&sender.key_pair);
+        tx
+    }
+
+    #[test]
+    fn contract_events_are_published_only_for_committed_blocks() {
+        let alice = Wallet::new();
+        let mut blockchain = funded_chain(&alice);
+        let contract_id = deploy_kv_store(&mut blockchain, &alice);
+        let receiver = blockchain.events.subscribe();
+
+        // 区块中后面的交易无效，整个区块被拒绝，合约调用的事件不推送
+        let overspend = transfer(&alice, "bob", MINING_REWARD);
+        assert!(blockchain.produce_block(vec![call(&alice, &contract_id, &["k", "v"]), overspend], "miner", None).is_err());
+        assert!(receiver.try_recv().is_err());
+
+        blockchain.produce_block(vec![call(&alice, &contract_id, &["k", "v"])], "miner", None).unwrap();
+        assert!(matches!(receiver.try_recv(), Ok(ChainEvent::NewHead(_))));
+        let call_id = blockchain.get_latest_block().transactions[1].id();
+        match receiver.try_recv() {
+            Ok(ChainEvent::ContractEvent { block_index, tx_id, log }) => {
+                assert_eq!((block_index, tx_id), (blockchain.get_latest_block().header.height, call_id));
+                assert_eq!(log.topics, vec!["set", "k"]);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn contract_calls_whose_debit_overflows_are_rejected() {
+        let alice = Wallet::new();
+        let mut blockchain = funded_chain(&alice);
+        let contract_id = deploy_kv_store(&mut blockchain, &alice);
+        // 转入金额加上最高手续费溢出 u64
+        let args = vec!["k".to_string(), "v".to_string()];
+        let mut tx = smart_contract::call_transaction(&alice.address(), &contract_id, "set", args, u64::MAX, 10_000);
+        tx.sign(&alice.key_pair);
+        assert_eq!(tx.debit_amount(), None);
+
+        assert!(blockchain.add_transaction(tx.clone()).is_err());
+        assert!(blockchain.produce_block(vec![tx.clone()], "miner", None).is_err());
+        assert!(blockchain.import_block(mined_block(&blockchain, vec![tx])).is_err());
+        assert!(blockchain.is_chain_valid());
+    }
+
+    #[test]
+    fn blocks_can_be_looked_up_by_height_hash_and_range() {
+        let alice = Wallet::new();
+        let mut blockchain = funded_chain(&alice);
+        blockchain.add_block(Vec::new(), "miner");
+        let hash = blockchain.chain[1].hash.clone();
+
+        assert_eq!(blockchain.get_block("1").unwrap().hash, hash);
+        assert_eq!(blockchain.get_block(&hash).unwrap().header.height, 1);
+        assert!(blockchain.get_block("9").is_none());
+        assert!(blockchain.get_block("unknown").is_none());
+        let heights: Vec<u64> = blockchain.get_blocks(1, 100).iter().map(|block| block.header.height).collect();
+        assert_eq!(heights, vec![1, 2]);
+        assert!(blockchain.get_blocks(5, 9).is_empty());
+
+        let info = blockchain.chain_info().unwrap();
+        assert_eq!((info.height, &info.tip), (2, &blockchain.get_latest_block().hash));
+        assert_eq!(info.total_supply, 2 * MINING_REWARD);
+        assert_eq!(info.total_work, blockchain.chain[1].header.work() * 2);
+        assert_eq!(info.pending_transactions, 0);
+
+        // 区间上界为 u64::MAX 时不溢出，总量溢出时报告错误
+        assert_eq!(blockchain.get_blocks(1, u64::MAX).len(), 2);
+        blockchain.set_balance("whale", u64::MAX);
+        assert!(blockchain.total_supply().is_err());
+        assert!(blockchain.chain_info().is_err());
+    }
 }
@@ -1,8 +1,34 @@
+use std::collections::HashSet;
+use std::fs;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
 use clap::{Parser, Subcommand};
-use crate::blockchain::Blockchain;
-use crate::transaction::Transaction;
+use chrono::{TimeZone, Utc};
+use serde::Serialize;
+use serde_json::json;
+use crate::block::{Block, BlockHeader};
+use crate::blockchain::{self, Blockchain};
+use crate::transaction::{Transaction, TxPayload};
 use crate::wallet::Wallet;
-use crate::privacy::PrivacyTransaction;
+use crate::privacy::{self, Note, PrivacyTransaction, ProofStatement, ShieldedKey, SpendInput, VerifyingKeys};
+use crate::shielded_pool::SHIELDED_SENDER;
+use crate::confidential::{ConfidentialTransfer, OpenedOutput};
+use crate::node::Node;
+use crate::light_client::{LightClient, TxProof};
+use crate::consensus::{ConsensusConfig, ConsensusKind, ProofOfAuthority, ProofOfStake};
+use crate::finality;
+use crate::multisig::MultisigAccount;
+use crate::utxo::{LedgerMode, TxOutput};
+use crate::timelock::{vesting_schedule, LockTime};
+use crate::htlc;
+use crate::asset;
+use crate::vm;
+use crate::smart_contract::{self, ContractKind, SmartContract};
+use crate::receipt::{LogFilter, Receipt};
+use crate::token::TokenMetadata;
+
+const MAX_WAIT_SLOTS: u64 = 1000; // 等待出块时隙时最多向后查找的时隙数
 
 #[derive(Parser)]
 #[clap(name = "blockchain-cli", version = "1.0", author = "Your Name")]
@@ -13,8 +39,204 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 pub enum Commands {
+    /// 初始化区块链（创世区块），可选择账本模式
+    Init {
+        #[clap(long, default_value_t = 4)]
+        difficulty: usize,
+        /// 账本模式：account 或 utxo
+        #[clap(long, default_value = "account")]
+        ledger: LedgerMode,
+        /// 在创世时登记的隐私转账验证密钥文件（可重复，由 setup-privacy 生成）
+        #[clap(long = "privacy-vk")]
+        privacy_vks: Vec<String>,
+        /// 共识：pow（工作量证明）、pos（权益证明）或 poa（权威证明），后两者需要账户模式
+        #[clap(long, default_value = "pow")]
+        consensus: ConsensusKind,
+        /// 权益证明的时隙长度（秒）
+        #[clap(long, default_value_t = 5)]
+        slot_duration: i64,
+        /// 创世验证者的质押：<地址>:<金额>（可重复，权益证明需要至少一个）
+        #[clap(long = "stake")]
+        stakes: Vec<String>,
+        /// 权威证明的出块周期（秒）：相邻区块的最小时间间隔
+        #[clap(long, default_value_t = 5)]
+        period: i64,
+        /// 创世时授权的签名者地址（可重复，权威证明需要至少一个）
+        #[clap(long = "signer")]
+        signers: Vec<String>,
+        /// 创世时登记的 BFT 最终性验证者地址（可重复），超过 2/3 的验证者签名的区块不能再被回滚
+        #[clap(long = "finality-validator")]
+        finality_validators: Vec<String>,
+    },
+
     /// 创建一个新钱包
-    CreateWallet,
+    CreateWallet {
+        /// 将钱包保存到密钥文件
+        #[clap(long)]
+        keystore: Option<String>,
+    },
+
+    /// 创建未签名的原始交易（十六进制，可写入文件）
+    CreateRawTransaction {
+        sender: String,
+        receiver: String,
+        amount: u64,
+        /// 时间锁：height:<高度> 或 time:<时间戳>，在此之前接收方不能花费
+        #[clap(long)]
+        lock: Option<LockTime>,
+        /// 转账的资产 ID，默认为原生币（仅账户模式）
+        #[clap(long, conflicts_with = "lock")]
+        asset: Option<String>,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 创建发行资产的交易（未签名），资产 ID 为交易 ID
+    IssueAsset {
+        issuer: String,
+        name: String,
+        symbol: String,
+        /// 初始发行量
+        supply: u64,
+        #[clap(long, default_value_t = 0)]
+        decimals: u8,
+        /// 供应量上限，默认不限
+        #[clap(long)]
+        max_supply: Option<u64>,
+        /// 初始发行量的接收方，默认为发行者
+        #[clap(long)]
+        to: Option<String>,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 创建增发资产的交易（未签名），只有发行者可以增发
+    MintAsset {
+        issuer: String,
+        asset_id: String,
+        receiver: String,
+        amount: u64,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 查询资产信息
+    GetAsset {
+        asset_id: String,
+    },
+
+    /// 创建归属计划：总额分期锁定，每期间隔 interval 个区块或秒
+    CreateVestingSchedule {
+        sender: String,
+        receiver: String,
+        total: u64,
+        installments: u64,
+        /// 第一期解锁条件：height:<高度> 或 time:<时间戳>
+        #[clap(long)]
+        start: LockTime,
+        #[clap(long)]
+        interval: u64,
+        /// 输出文件（每行一笔原始交易），默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 查询地址的锁定资金
+    GetLockedBalance {
+        address: String,
+    },
+
+    /// 使用密钥文件签名原始交易（可在离线机器上执行）
+    SignRawTransaction {
+        /// 原始交易的十六进制或文件路径（文件可包含多行）
+        raw: String,
+        #[clap(long)]
+        keystore: String,
+        /// 作为多签参与者追加部分签名
+        #[clap(long)]
+        partial: bool,
+        /// 多签账户定义文件（create-multisig --out 生成），附加到交易中
+        #[clap(long)]
+        multisig: Option<String>,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 合并多份原始交易上的多签部分签名
+    CombineRawTransactions {
+        /// 原始交易的十六进制或文件路径
+        raws: Vec<String>,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 生成哈希时间锁使用的随机原像及其哈希
+    HtlcSecret,
+
+    /// 创建哈希时间锁交易（未签名）：接收方在超时前提供原像即可领取
+    HtlcLock {
+        sender: String,
+        receiver: String,
+        amount: u64,
+        #[clap(long)]
+        hashlock: String,
+        /// 超时：height:<高度> 或 time:<时间戳>
+        #[clap(long)]
+        timeout: LockTime,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 创建领取哈希时间锁的交易（未签名）
+    HtlcClaim {
+        htlc_id: String,
+        preimage: String,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 创建超时后取回哈希时间锁资金的交易（未签名）
+    HtlcRefund {
+        htlc_id: String,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 查询哈希时间锁
+    GetHtlc {
+        htlc_id: String,
+    },
+
+    /// 在两条本地链之间演示原子交换
+    AtomicSwapDemo,
+
+    /// 列出地址的未花费输出（UTXO 模式）
+    ListUnspent {
+        address: String,
+    },
+
+    /// 创建 M-of-N 多签账户
+    CreateMultisig {
+        threshold: usize,
+        public_keys: Vec<String>,
+        /// 账户定义的输出文件，签名多签交易时通过 --multisig 附加到交易中
+        #[clap(long, default_value = "multisig.json")]
+        out: String,
+    },
+
+    /// 广播已签名的原始交易（加入交易池）
+    SendRawTransaction {
+        /// 原始交易的十六进制或文件路径（文件可包含多行）
+        raw: String,
+    },
 
     /// 发起一笔交易
     AddTransaction {
@@ -23,30 +245,221 @@ pub enum Commands {
         amount: u64,
     },
 
-    /// 挖矿新区块
+    /// 创建质押交易（未签名）：把余额转入质押，参与权益证明的出块者选择
+    Stake {
+        sender: String,
+        amount: u64,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 创建解除质押的交易（未签名）：把质押转回余额
+    Unstake {
+        sender: String,
+        amount: u64,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 创建权威证明的签名者投票交易（未签名）：提议增加候选者，加 --drop 时提议移除
+    Vote {
+        sender: String,
+        candidate: String,
+        #[clap(long)]
+        drop: bool,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 查询验证者：权益证明显示质押和接下来各时隙的出块者，权威证明显示签名者和投票
+    GetValidators {
+        #[clap(long, default_value_t = 10)]
+        slots: u64,
+    },
+
+    /// 挖矿新区块（权益证明和权威证明的链由出块者用密钥文件签名出块）
     MineBlock {
-        miner: String,
+        /// 奖励接收地址，默认为出块者地址
+        miner: Option<String>,
+        /// 出块者密钥文件（权益证明和权威证明）
+        #[clap(long)]
+        keystore: Option<String>,
+        /// 等待到该密钥可以出块的时间再出块（权益证明的出块时隙，或权威证明的出块周期）
+        #[clap(long, requires = "keystore")]
+        wait: bool,
+    },
+
+    /// 最终确定最新区块：用验证者的密钥文件在模拟网络中运行 BFT 协议（未提供密钥的验证者视为离线），并保存提交证书
+    Finalize {
+        /// 验证者密钥文件（可重复）
+        #[clap(long = "keystore", required = true)]
+        keystores: Vec<String>,
     },
 
+    /// 查询最终性：验证者集合、已最终确定的高度和提交证书
+    GetFinality,
+
+    /// 演示 BFT 最终性：验证者崩溃、作恶和分叉时的表现
+    FinalityDemo,
+
     /// 验证区块链
     ValidateChain,
 
-    /// 部署智能合约
+    /// 创建部署智能合约的交易（未签名），code 为十六进制字节码，合约地址为交易 ID
     DeployContract {
-        contract_id: String,
+        sender: String,
         code: String,
+        /// 将 code 视为汇编源文件路径，汇编后部署
+        #[clap(long, conflicts_with = "wasm")]
+        asm: bool,
+        /// 将 code 视为 WebAssembly 模块（.wasm 或 .wat）文件路径
+        #[clap(long, conflicts_with = "token")]
+        wasm: bool,
+        /// 部署内置代币合约，code 为代币元数据 JSON 或其文件路径，owner 默认为发送方
+        #[clap(long, conflicts_with = "asm")]
+        token: bool,
+        /// 转入合约的初始金额
+        #[clap(long, default_value_t = 0)]
+        amount: u64,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
     },
 
-    /// 执行智能合约
-    ExecuteContract {
+    /// 创建调用智能合约的交易（未签名）
+    CallContract {
+        sender: String,
         contract_id: String,
         method: String,
         args: Vec<String>,
+        /// 随调用转入合约的金额
+        #[clap(long, default_value_t = 0)]
+        amount: u64,
+        /// 燃料上限，发送方需预付对应的最高手续费
+        #[clap(long, default_value_t = 10_000)]
+        gas_limit: u64,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
     },
 
-    /// 创建隐私交易
-    CreatePrivacyTransaction {
+    /// 只读调用智能合约：在当前状态上模拟执行，不修改链上状态
+    QueryContract {
+        contract_id: String,
+        method: String,
+        args: Vec<String>,
+        /// 调用者地址
+        #[clap(long, default_value = "anonymous")]
+        caller: String,
+        /// 燃料上限
+        #[clap(long, default_value_t = 100_000)]
+        gas_limit: u64,
+    },
+
+    /// 将合约汇编源文件编译为十六进制字节码
+    Assemble {
+        file: String,
+    },
+
+    /// 隐私转账电路的一次性可信设置：证明参数和验证密钥分别写入文件
+    SetupPrivacy {
+        #[clap(long, default_value = "privacy.params")]
+        params: String,
+        #[clap(long, default_value = "privacy.vk")]
+        vk: String,
+    },
+
+    /// 比较隐私转账证明的逐个验证与批量验证耗时
+    BenchPrivacyVerification {
+        /// 可信设置生成的证明参数文件
+        #[clap(long, default_value = "privacy.params")]
+        params: String,
+        /// 生成的不同证明数量（生成证明较慢）
+        #[clap(long, default_value_t = 4)]
+        proofs: usize,
+        /// 参与验证的证明数量（重复使用生成的证明）
+        #[clap(long, default_value_t = 32)]
+        count: usize,
+    },
+
+    /// 创建隐私地址，花费密钥保存到文件
+    CreateShieldedKey {
+        key_file: String,
+    },
+
+    /// 创建存入隐私池的交易（未签名），新票据写入 note_out 文件，需交给接收方保存
+    Shield {
+        sender: String,
+        /// 接收方的隐私地址
+        recipient: String,
         amount: u64,
+        /// 新票据的输出文件
+        #[clap(long)]
+        note_out: String,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 创建隐私转账交易（无需签名）：花费票据、创建新票据，并可转出到公开地址
+    ShieldedTransfer {
+        /// 花费密钥文件
+        #[clap(long)]
+        key: String,
+        /// 可信设置生成的证明参数文件
+        #[clap(long, default_value = "privacy.params")]
+        params: String,
+        /// 要花费的票据文件（可重复，最多 2 个）
+        #[clap(long = "note")]
+        notes: Vec<String>,
+        /// 新票据：<隐私地址>:<金额>（可重复，最多 2 个）
+        #[clap(long = "output")]
+        outputs: Vec<String>,
+        /// 转出到公开地址：<地址>:<金额>
+        #[clap(long)]
+        unshield: Option<String>,
+        /// 新票据文件名前缀，第 i 个票据写入 <前缀>-<i>.json
+        #[clap(long, default_value = "note")]
+        note_out: String,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 创建机密转账交易（未签名）：金额隐藏在承诺中，自动选取发送方的机密输出并找零
+    CreateConfidentialTransaction {
+        /// 发送方密钥文件（用于解密发送方的机密输出）
+        #[clap(long)]
+        keystore: String,
+        /// 机密输出：<地址>:<金额>（可重复）
+        #[clap(long = "output")]
+        outputs: Vec<String>,
+        /// 从发送方公开余额存入的金额
+        #[clap(long, default_value_t = 0)]
+        deposit: u64,
+        /// 取出到公开地址：<地址>:<金额>
+        #[clap(long)]
+        withdraw: Option<String>,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 解密并列出地址拥有的机密输出
+    GetConfidentialBalance {
+        #[clap(long)]
+        keystore: String,
+    },
+
+    /// 查询隐私池，指定票据和密钥时同时显示票据状态
+    GetShieldedPool {
+        #[clap(long)]
+        note: Option<String>,
+        #[clap(long, requires = "note")]
+        key: Option<String>,
     },
 
     /// 查询地址余额
@@ -54,20 +467,432 @@ pub enum Commands {
         address: String,
     },
 
-    /// 设置地址余额
-    SetBalance {
+    /// 根据交易 ID 查询交易
+    GetTransaction {
+        id: String,
+        /// 以 JSON 格式输出
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// 按高度或哈希查询区块
+    GetBlock {
+        height_or_hash: String,
+        /// 以 JSON 格式输出
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// 按交易 ID 查询交易收据
+    GetReceipt {
+        tx_id: String,
+        /// 以 JSON 格式输出
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// 生成状态键的默克尔证明，并用最新区块的状态根验证
+    GetProof {
+        /// 状态键，例如 account:<地址>；指定 --contract 时为合约存储键
+        key: String,
+        /// 合约地址
+        #[clap(long)]
+        contract: Option<String>,
+        /// 以 JSON 格式输出
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// 导出区块头（供轻客户端同步）
+    GetHeaders {
+        #[clap(long, default_value_t = 0)]
+        from: u64,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 生成交易的包含证明（默克尔证明，供轻客户端验证）
+    GetTxProof {
+        tx_id: String,
+        /// 输出文件，默认打印到终端
+        #[clap(long)]
+        out: Option<String>,
+    },
+
+    /// 轻客户端：验证并同步区块头文件，首次同步时从创世区块头开始
+    LightSync {
+        /// get-headers 导出的区块头文件
+        headers: String,
+        /// 轻客户端数据文件
+        #[clap(long, default_value = "light.json")]
+        store: String,
+        /// 工作量证明难度（首次同步时设置）
+        #[clap(long, default_value_t = 4)]
+        difficulty: usize,
+        /// 信任的创世区块哈希（首次同步时检查）
+        #[clap(long)]
+        genesis: Option<String>,
+    },
+
+    /// 轻客户端：用已同步的区块头验证交易包含证明
+    VerifyTxProof {
+        /// get-tx-proof 生成的证明文件
+        proof: String,
+        /// 轻客户端数据文件
+        #[clap(long, default_value = "light.json")]
+        store: String,
+    },
+
+    /// 按合约和主题查询合约事件
+    GetLogs {
+        /// 只查询该合约发出的事件
+        #[clap(long)]
+        contract: Option<String>,
+        /// 按位置匹配的主题，可重复指定，"*" 为通配
+        #[clap(long)]
+        topic: Vec<String>,
+        #[clap(long, default_value_t = 0)]
+        from: u64,
+        /// 默认到最新区块
+        #[clap(long)]
+        to: Option<u64>,
+        /// 以 JSON 格式输出
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// 列出高度区间内的区块
+    ListBlocks {
+        #[clap(long, default_value_t = 0)]
+        from: u64,
+        /// 默认到最新区块
+        #[clap(long)]
+        to: Option<u64>,
+        /// 以 JSON 格式输出
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// 查询区块链概要信息
+    ChainInfo {
+        /// 以 JSON 格式输出
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// 查询地址的历史交易
+    History {
         address: String,
-        balance: u64,
+    },
+
+    /// 启用并重建交易索引
+    Reindex,
+
+    /// 断开最新区块（回滚其状态变更）
+    DisconnectBlock,
+
+    /// 先同步区块头：从对等节点下载并验证区块头，再并行下载区块体并接入（工作量更多时切换到对等节点的链）
+    Sync {
+        /// 对等节点的 WebSocket 地址（可重复）
+        #[clap(required = true)]
+        peers: Vec<SocketAddr>,
+        /// 并行下载区块体的连接数
+        #[clap(long, default_value_t = 4)]
+        parallel: usize,
+    },
+
+    /// 启动节点并提供 WebSocket 订阅和同步服务
+    Serve {
+        #[clap(default_value = "127.0.0.1:8546")]
+        ws_address: SocketAddr,
+        /// 指定矿工地址后，节点会定期打包交易池中的交易
+        #[clap(long)]
+        miner: Option<String>,
+        /// 出块间隔（秒）
+        #[clap(long, default_value_t = 10)]
+        block_interval: u64,
+        /// 节点保存区块链的文件，不存在时从 blockchain.json 加载
+        #[clap(long, default_value = "node.json")]
+        data_file: String,
     },
 }
 
 impl Cli {
     pub fn run(&self) {
         match &self.command {
-            Commands::CreateWallet => {
+            Commands::Init { difficulty, ledger, privacy_vks, consensus, slot_duration, stakes, period, signers, finality_validators } => {
+                if Blockchain::load_from_file("blockchain.json").is_ok() {
+                    return println!("Blockchain already initialized");
+                }
+                let privacy_vks = match privacy_vks.iter().map(|path| fs::read_to_string(path).map(|vk| vk.trim().to_string())).collect() {
+                    Ok(privacy_vks) => privacy_vks,
+                    Err(err) => return println!("Cannot read verifying key: {}", err),
+                };
+                let consensus = match consensus {
+                    ConsensusKind::Pow => ConsensusConfig::ProofOfWork,
+                    ConsensusKind::Pos => match stakes.iter().map(|stake| parse_payment(stake)).collect() {
+                        Ok(genesis_stakes) => ConsensusConfig::ProofOfStake { slot_duration: *slot_duration, genesis_stakes },
+                        Err(err) => return println!("{}", err),
+                    },
+                    ConsensusKind::Poa => ConsensusConfig::ProofOfAuthority { period: *period, genesis_signers: signers.clone() },
+                };
+                let blockchain = match Blockchain::with_genesis(*difficulty, *ledger, privacy_vks, consensus, finality_validators.clone()) {
+                    Ok(blockchain) => blockchain,
+                    Err(err) => return println!("{}", err),
+                };
+                println!("Blockchain initialized with {:?} ledger, genesis: {}", ledger, blockchain.get_latest_block().hash);
+                match &blockchain.consensus {
+                    ConsensusConfig::ProofOfWork => {}
+                    ConsensusConfig::ProofOfStake { slot_duration, .. } => {
+                        println!("Proof of stake with {}s slots and {} validator(s)", slot_duration, blockchain.stakes.len());
+                    }
+                    ConsensusConfig::ProofOfAuthority { period, .. } => {
+                        println!("Proof of authority with a {}s period and {} signer(s)", period, blockchain.authorities.len());
+                    }
+                }
+                if !blockchain.privacy_vks.is_empty() {
+                    println!("Registered {} privacy verifying key(s)", blockchain.privacy_vks.len());
+                }
+                if !blockchain.finality_validators.is_empty() {
+                    let validators = blockchain.finality_validators.len();
+                    println!("BFT finality with {} validator(s), {} precommits required", validators, finality::quorum(validators));
+                }
+                blockchain.save_to_file("blockchain.json").unwrap();
+            }
+            Commands::CreateWallet { keystore } => {
                 let wallet = Wallet::new();
                 println!("New wallet created!");
                 println!("Wallet address: {}", wallet.address());
+                if let Some(keystore) = keystore {
+                    wallet.save_to_file(keystore).unwrap();
+                    println!("Keystore saved to: {}", keystore);
+                }
+            }
+            Commands::CreateRawTransaction { sender, receiver, amount, lock, asset, out } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap_or_else(|_| Blockchain::new(4));
+                let transaction = match blockchain.ledger_mode {
+                    LedgerMode::Account => {
+                        let mut transaction = Transaction::new_unsigned(sender.clone(), receiver.clone(), *amount);
+                        transaction.lock_until = *lock;
+                        transaction.asset = asset.clone().unwrap_or_default();
+                        transaction
+                    }
+                    LedgerMode::Utxo if asset.is_some() => return println!("Assets are not supported in UTXO mode"),
+                    LedgerMode::Utxo => {
+                        let payment = match lock {
+                            Some(lock) => TxOutput::locked(receiver.clone(), *amount, *lock),
+                            None => TxOutput::new(receiver.clone(), *amount),
+                        };
+                        match blockchain.create_utxo_transaction(sender, vec![payment]) {
+                            Ok(transaction) => transaction,
+                            Err(err) => return println!("{}", err),
+                        }
+                    }
+                };
+                println!("Unsigned transaction {}", transaction.id());
+                write_raw(&transaction.to_raw(), out.as_deref());
+            }
+            Commands::IssueAsset { issuer, name, symbol, supply, decimals, max_supply, to, out } => {
+                let receiver = to.as_deref().unwrap_or(issuer);
+                let transaction = asset::issue_transaction(issuer, receiver, *supply, name, symbol, *decimals, *max_supply);
+                println!("Unsigned asset issue transaction, asset id: {}", transaction.id());
+                write_raw(&transaction.to_raw(), out.as_deref());
+            }
+            Commands::MintAsset { issuer, asset_id, receiver, amount, out } => {
+                let transaction = asset::mint_transaction(issuer, asset_id, receiver, *amount);
+                println!("Unsigned asset mint transaction {}", transaction.id());
+                write_raw(&transaction.to_raw(), out.as_deref());
+            }
+            Commands::GetAsset { asset_id } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                match blockchain.assets.get(asset_id) {
+                    Some(asset) => println!("{:#?}", asset),
+                    None => println!("Asset not found: {}", asset_id),
+                }
+            }
+            Commands::CreateVestingSchedule { sender, receiver, total, installments, start, interval, out } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap_or_else(|_| Blockchain::new(4));
+                let schedule = vesting_schedule(*total, *installments, *start, *interval);
+                // 账户模式下每期一笔交易，UTXO 模式下一笔交易包含每期一个锁定输出
+                let transactions = match blockchain.ledger_mode {
+                    LedgerMode::Account => schedule
+                        .iter()
+                        .map(|(amount, lock_until)| {
+                            let mut transaction = Transaction::new_unsigned(sender.clone(), receiver.clone(), *amount);
+                            transaction.lock_until = Some(*lock_until);
+                            transaction
+                        })
+                        .collect(),
+                    LedgerMode::Utxo => {
+                        let payments = schedule
+                            .iter()
+                            .map(|(amount, lock_until)| TxOutput::locked(receiver.clone(), *amount, *lock_until))
+                            .collect();
+                        match blockchain.create_utxo_transaction(sender, payments) {
+                            Ok(transaction) => vec![transaction],
+                            Err(err) => return println!("{}", err),
+                        }
+                    }
+                };
+                for (amount, lock_until) in &schedule {
+                    println!("  {} unlocks at {}", amount, lock_until);
+                }
+                println!("Vesting schedule created with {} unsigned transaction(s)", transactions.len());
+                let raws: Vec<String> = transactions.iter().map(Transaction::to_raw).collect();
+                write_raw(&raws.join("\n"), out.as_deref());
+            }
+            Commands::GetLockedBalance { address } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let context = blockchain.next_block_context();
+                println!("Balance of address {}: {}", address, blockchain.get_balance(address));
+                println!("Spendable in block {}: {}", context.height, blockchain.get_spendable_balance(address, &context));
+                match blockchain.ledger_mode {
+                    LedgerMode::Account => {
+                        for funds in blockchain.get_locked_funds(address) {
+                            let status = if funds.lock_until.is_unlocked(context.height, context.timestamp) { "unlocked" } else { "locked" };
+                            println!("  {}  {} until {} ({})", funds.tx_id, funds.amount, funds.lock_until, status);
+                        }
+                    }
+                    LedgerMode::Utxo => {
+                        for (outpoint, output) in blockchain.utxo_set.unspent_for(address) {
+                            if let Some(lock_until) = output.lock_until {
+                                let status = if lock_until.is_unlocked(context.height, context.timestamp) { "unlocked" } else { "locked" };
+                                println!("  {}  {} until {} ({})", outpoint, output.amount, lock_until, status);
+                            }
+                        }
+                    }
+                }
+            }
+            Commands::SignRawTransaction { raw, keystore, partial, multisig, out } => {
+                let mut transactions = match read_raw_transactions(raw) {
+                    Ok(transactions) => transactions,
+                    Err(err) => return println!("{}", err),
+                };
+                let account = match multisig {
+                    Some(path) => match fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|json| serde_json::from_str::<MultisigAccount>(&json).map_err(|e| e.to_string())) {
+                        Ok(account) => Some(account),
+                        Err(err) => return println!("Cannot read multisig account {}: {}", path, err),
+                    },
+                    None => None,
+                };
+                let wallet = Wallet::load_from_file(keystore).unwrap();
+                for transaction in &mut transactions {
+                    if let Some(account) = &account {
+                        if account.address() != transaction.sender {
+                            return println!("Multisig address {} does not match sender {}", account.address(), transaction.sender);
+                        }
+                        transaction.multisig = Some(account.clone());
+                    }
+                    if *partial {
+                        transaction.add_partial_signature(&wallet.key_pair);
+                        println!("Added partial signature ({} collected) to transaction {}", transaction.signatures.len(), transaction.id());
+                    } else {
+                        if wallet.address() != transaction.sender {
+                            return println!("Keystore address {} does not match sender {}", wallet.address(), transaction.sender);
+                        }
+                        transaction.sign(&wallet.key_pair);
+                        println!("Signed transaction {}", transaction.id());
+                    }
+                }
+                let raws: Vec<String> = transactions.iter().map(Transaction::to_raw).collect();
+                write_raw(&raws.join("\n"), out.as_deref());
+            }
+            Commands::CombineRawTransactions { raws, out } => {
+                let mut transactions = Vec::new();
+                for raw in raws {
+                    match Transaction::from_raw(&read_raw(raw)) {
+                        Ok(transaction) => transactions.push(transaction),
+                        Err(err) => return println!("{}", err),
+                    }
+                }
+                if transactions.is_empty() {
+                    return println!("No raw transactions given");
+                }
+                let mut combined = transactions.remove(0);
+                for transaction in &transactions {
+                    if let Err(err) = combined.merge_signatures(transaction) {
+                        return println!("{}", err);
+                    }
+                }
+                println!("Combined {} signature(s) for transaction {}", combined.signatures.len(), combined.id());
+                write_raw(&combined.to_raw(), out.as_deref());
+            }
+            Commands::HtlcSecret => {
+                let (preimage, hashlock) = htlc::generate_secret();
+                println!("Preimage: {}", preimage);
+                println!("Hashlock: {}", hashlock);
+            }
+            Commands::HtlcLock { sender, receiver, amount, hashlock, timeout, out } => {
+                let transaction = htlc::lock_transaction(sender, receiver, *amount, hashlock, *timeout);
+                println!("Unsigned HTLC lock transaction {} (HTLC id)", transaction.id());
+                write_raw(&transaction.to_raw(), out.as_deref());
+            }
+            Commands::HtlcClaim { htlc_id, preimage, out } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let Some(htlc) = blockchain.htlcs.get(htlc_id) else {
+                    return println!("HTLC not found: {}", htlc_id);
+                };
+                let transaction = htlc::claim_transaction(htlc_id, htlc, preimage);
+                println!("Unsigned HTLC claim transaction {}", transaction.id());
+                write_raw(&transaction.to_raw(), out.as_deref());
+            }
+            Commands::HtlcRefund { htlc_id, out } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let Some(htlc) = blockchain.htlcs.get(htlc_id) else {
+                    return println!("HTLC not found: {}", htlc_id);
+                };
+                let transaction = htlc::refund_transaction(htlc_id, htlc);
+                println!("Unsigned HTLC refund transaction {}", transaction.id());
+                write_raw(&transaction.to_raw(), out.as_deref());
+            }
+            Commands::GetHtlc { htlc_id } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                match blockchain.htlcs.get(htlc_id) {
+                    Some(htlc) => println!("{:#?}", htlc),
+                    None => println!("HTLC not found: {}", htlc_id),
+                }
+            }
+            Commands::AtomicSwapDemo => match htlc::demo_atomic_swap() {
+                Ok(()) => println!("Atomic swap completed successfully"),
+                Err(err) => println!("Atomic swap failed: {}", err),
+            },
+            Commands::ListUnspent { address } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let unspent = blockchain.utxo_set.unspent_for(address);
+                println!("{} unspent output(s) for address {}", unspent.len(), address);
+                for (outpoint, output) in unspent {
+                    println!("{}  {}", outpoint, output.amount);
+                }
+            }
+            Commands::CreateMultisig { threshold, public_keys, out } => {
+                let account = match MultisigAccount::new(public_keys.clone(), *threshold) {
+                    Ok(account) => account,
+                    Err(err) => return println!("{}", err),
+                };
+                fs::write(out, serde_json::to_string_pretty(&account).unwrap()).unwrap();
+                println!("Multisig account created ({}-of-{})", threshold, account.public_keys.len());
+                println!("Multisig address: {}", account.address());
+                println!("Account definition saved to: {}", out);
+            }
+            Commands::SendRawTransaction { raw } => {
+                let transactions = match read_raw_transactions(raw) {
+                    Ok(transactions) => transactions,
+                    Err(err) => return println!("{}", err),
+                };
+                let mut blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                for transaction in transactions {
+                    let id = transaction.id();
+                    if !transaction.is_signed() {
+                        println!("Transaction {} is not signed", id);
+                        continue;
+                    }
+                    match blockchain.add_transaction(transaction) {
+                        Ok(()) => println!("Transaction {} added to the pool", id),
+                        Err(err) => println!("Transaction {} rejected: {}", id, err),
+                    }
+                }
+                blockchain.save_to_file("blockchain.json").unwrap();
             }
             Commands::AddTransaction { sender, receiver, amount } => {
                 let wallet = Wallet::new(); // 这里需要根据地址加载钱包
@@ -79,51 +904,894 @@ impl Cli {
                 );
                 println!("Transaction created: {:?}", transaction);
             }
-            Commands::MineBlock { miner } => {
+            Commands::Stake { sender, amount, out } => staking_transaction(sender, *amount, TxPayload::Stake, out.as_deref()),
+            Commands::Unstake { sender, amount, out } => staking_transaction(sender, *amount, TxPayload::Unstake, out.as_deref()),
+            Commands::Vote { sender, candidate, drop, out } => {
+                let mut transaction = Transaction::new_unsigned(sender.clone(), String::new(), 0);
+                transaction.payload = TxPayload::Vote { candidate: candidate.clone(), authorize: !drop };
+                println!("Unsigned vote transaction {}", transaction.id());
+                write_raw(&transaction.to_raw(), out.as_deref());
+            }
+            Commands::GetValidators { slots } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let state = blockchain.consensus_state();
+                if let ConsensusConfig::ProofOfAuthority { period, .. } = &blockchain.consensus {
+                    let poa = ProofOfAuthority { period: *period };
+                    let height = blockchain.get_latest_block().header.height + 1;
+                    let in_turn = poa.in_turn(height, &state.authorities).cloned().unwrap_or_default();
+                    println!("{} signer(s), block {} is in turn for {}", state.authorities.len(), height, in_turn);
+                    for (address, last) in &state.authorities {
+                        let status = if poa.signed_recently(address, height, &state.authorities) { "signed recently" } else { "can sign" };
+                        println!("{}  last signed {:>6}  {}", address, last, status);
+                    }
+                    let mut votes: Vec<_> = blockchain.authority_votes.iter().collect();
+                    votes.sort();
+                    for (key, authorize) in votes {
+                        let (candidate, voter) = key.split_once(':').unwrap();
+                        println!("vote: {} {} {}", voter, if *authorize { "adds" } else { "drops" }, candidate);
+                    }
+                    return;
+                }
+                let total: u64 = state.stakes.values().sum();
+                println!("{} validator(s), total stake {}", state.stakes.len(), total);
+                for (address, stake) in &state.stakes {
+                    println!("{}  {:>10}  {:>6.2}%", address, stake, *stake as f64 * 100.0 / total as f64);
+                }
+                if let ConsensusConfig::ProofOfStake { slot_duration, .. } = &blockchain.consensus {
+                    let pos = ProofOfStake { slot_duration: *slot_duration };
+                    let genesis = &blockchain.chain[0].header;
+                    let current = pos.slot(genesis, Utc::now().timestamp());
+                    println!("Leader schedule:");
+                    for slot in current..current + slots {
+                        let leader = pos.leader(genesis, slot, &state.stakes).cloned().unwrap_or_default();
+                        println!("slot {:>8}  {}  {}", slot, format_timestamp(pos.slot_start(genesis, slot)), leader);
+                    }
+                }
+            }
+            Commands::MineBlock { miner, keystore, wait } => {
                 let mut blockchain = Blockchain::load_from_file("blockchain.json").unwrap_or_else(|_| Blockchain::new(4));
-                let transactions = vec![/* 未确认的交易 */];
-                blockchain.add_block(transactions, miner);
+                let signer = match keystore.as_deref().map(Wallet::load_from_file).transpose() {
+                    Ok(signer) => signer,
+                    Err(err) => return println!("Cannot load keystore: {}", err),
+                };
+                let Some(miner) = miner.clone().or_else(|| signer.as_ref().map(Wallet::address)) else {
+                    return println!("Specify a miner address or a keystore");
+                };
+                if *wait {
+                    if let Err(err) = wait_for_turn(&blockchain, signer.as_ref().unwrap()) {
+                        return println!("{}", err);
+                    }
+                }
+                let transactions = std::mem::take(&mut blockchain.pending_transactions);
+                let signers_before: HashSet<String> = blockchain.authorities.keys().cloned().collect();
+                if let Err(err) = blockchain.produce_block(transactions, &miner, signer.as_ref()) {
+                    return println!("Failed to produce block: {}", err);
+                }
                 println!("New block mined by miner: {}", miner);
+                // 区块中的投票使提议生效时报告签名者的变化
+                let mut authorized: Vec<&String> = blockchain.authorities.keys().filter(|address| !signers_before.contains(*address)).collect();
+                let mut removed: Vec<&String> = signers_before.iter().filter(|address| !blockchain.authorities.contains_key(*address)).collect();
+                authorized.sort();
+                removed.sort();
+                for address in authorized {
+                    println!("Signer {} authorized", address);
+                }
+                for address in removed {
+                    println!("Signer {} removed", address);
+                }
                 println!("Latest block: {:#?}", blockchain.get_latest_block());
                 blockchain.save_to_file("blockchain.json").unwrap();
             }
+            Commands::Finalize { keystores } => {
+                let mut blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let wallets = match keystores.iter().map(|path| Wallet::load_from_file(path)).collect() {
+                    Ok(wallets) => wallets,
+                    Err(err) => return println!("Cannot load keystore: {}", err),
+                };
+                let certificate = match finality::finalize_latest_block(&blockchain, wallets) {
+                    Ok(certificate) => certificate,
+                    Err(err) => return println!("Failed to finalize: {}", err),
+                };
+                let (height, round, precommits) = (certificate.height, certificate.round, certificate.precommits.len());
+                if let Err(err) = blockchain.finalize(certificate) {
+                    return println!("Failed to finalize: {}", err);
+                }
+                println!("Block {} finalized in round {} with {} precommit(s)", height, round, precommits);
+                blockchain.save_to_file("blockchain.json").unwrap();
+            }
+            Commands::GetFinality => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let validators = &blockchain.finality_validators;
+                if validators.is_empty() {
+                    return println!("No finality validators registered in genesis");
+                }
+                println!("{} validator(s), {} precommits required", validators.len(), finality::quorum(validators.len()));
+                for validator in validators {
+                    println!("  {}", validator);
+                }
+                let finalized = blockchain.finalized_height();
+                println!("Finalized height: {}", finalized);
+                if let Some(commit) = &blockchain.chain[finalized as usize].commit {
+                    println!("Commit: block {} round {} with {} precommit(s)", commit.block_hash, commit.round, commit.precommits.len());
+                }
+            }
+            Commands::FinalityDemo => match finality::demo_finality() {
+                Ok(()) => println!("Finality demo completed successfully"),
+                Err(err) => println!("Finality demo failed: {}", err),
+            },
             Commands::ValidateChain => {
                 let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
                 let is_valid = blockchain.is_chain_valid();
                 println!("Blockchain validity: {}", is_valid);
             }
-            Commands::DeployContract { contract_id, code } => {
-                let mut blockchain = Blockchain::load_from_file("blockchain.json").unwrap_or_else(|_| Blockchain::new(4));
-                blockchain.deploy_contract(contract_id.clone(), code.clone());
-                println!("Contract deployed: {}", contract_id);
-                blockchain.save_to_file("blockchain.json").unwrap();
+            Commands::DeployContract { sender, code, asm, wasm, token, amount, out } => {
+                let (kind, code) = if *token {
+                    match token_metadata(code, sender) {
+                        Ok(metadata) => (ContractKind::Token, metadata),
+                        Err(err) => return println!("Invalid token metadata: {}", err),
+                    }
+                } else if *wasm {
+                    match wat::parse_file(code) {
+                        Ok(module) => (ContractKind::Wasm, hex::encode(module)),
+                        Err(err) => return println!("Cannot load WASM module: {}", err),
+                    }
+                } else if *asm {
+                    match assemble_file(code) {
+                        Ok(bytecode) => (ContractKind::Bytecode, bytecode),
+                        Err(err) => return println!("Assembly failed: {}", err),
+                    }
+                } else {
+                    (ContractKind::Bytecode, code.clone())
+                };
+                if let Err(err) = SmartContract::with_kind(kind, code.clone()).validate() {
+                    return println!("Invalid contract code: {}", err);
+                }
+                let transaction = smart_contract::deploy_transaction(sender, kind, code, *amount);
+                println!("Unsigned deploy transaction, contract address: {}", transaction.id());
+                println!("Deployment fee: {}", transaction.max_fee());
+                write_raw(&transaction.to_raw(), out.as_deref());
             }
-            Commands::ExecuteContract { contract_id, method, args } => {
-                let mut blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
-                match blockchain.execute_contract(&contract_id, &method, args.clone()) {
-                    Ok(result) => println!("Contract execution result: {}", result),
+            Commands::CallContract { sender, contract_id, method, args, amount, gas_limit, out } => {
+                let transaction = smart_contract::call_transaction(sender, contract_id, method, args.clone(), *amount, *gas_limit);
+                println!("Unsigned contract call transaction {}", transaction.id());
+                println!("Maximum fee: {}", transaction.max_fee());
+                write_raw(&transaction.to_raw(), out.as_deref());
+            }
+            Commands::QueryContract { contract_id, method, args, caller, gas_limit } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                match blockchain.query_contract(contract_id, caller, method, args.clone(), *gas_limit) {
+                    Ok(result) => {
+                        println!("Contract execution result: {}", result.return_value);
+                        println!("Gas used: {}", result.gas_used);
+                        for (receiver, amount) in &result.transfers {
+                            println!("Transferred {} to {}", amount, receiver);
+                        }
+                        for event in &result.events {
+                            println!("Event {:?}: {}", event.topics, event.data);
+                        }
+                    }
                     Err(err) => println!("Contract execution failed: {}", err),
                 }
-                blockchain.save_to_file("blockchain.json").unwrap();
             }
-            Commands::CreatePrivacyTransaction { amount } => {
-                let transaction = PrivacyTransaction::new(*amount);
-                let mut blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
-                blockchain.add_privacy_transaction(transaction);
-                println!("Privacy transaction created and added to blockchain");
-                blockchain.save_to_file("blockchain.json").unwrap();
+            Commands::Assemble { file } => match assemble_file(file) {
+                Ok(bytecode) => println!("{}", bytecode),
+                Err(err) => println!("Assembly failed: {}", err),
+            },
+            Commands::SetupPrivacy { params, vk } => {
+                let parameters = match privacy::setup() {
+                    Ok(parameters) => parameters,
+                    Err(err) => return println!("Setup failed: {}", err),
+                };
+                if let Err(err) = privacy::write_parameters(&parameters, params) {
+                    return println!("{}", err);
+                }
+                fs::write(vk, privacy::verifying_key(&parameters)).unwrap();
+                println!("Proving parameters written to: {}", params);
+                println!("Verifying key written to: {} (register it with init --privacy-vk)", vk);
+            }
+            Commands::BenchPrivacyVerification { params, proofs, count } => {
+                if let Err(err) = bench_privacy_verification(params, *proofs, *count) {
+                    println!("Benchmark failed: {}", err);
+                }
+            }
+            Commands::CreateShieldedKey { key_file } => {
+                let key = ShieldedKey::generate();
+                fs::write(key_file, serde_json::to_string_pretty(&key).unwrap()).unwrap();
+                println!("Shielded address: {}", key.address().unwrap());
+                println!("Spending key saved to: {}", key_file);
+            }
+            Commands::Shield { sender, recipient, amount, note_out, out } => {
+                let note = Note::new(recipient, *amount);
+                let note_key = match note.note_key() {
+                    Ok(note_key) => note_key,
+                    Err(err) => return println!("Invalid shielded address: {}", err),
+                };
+                let mut transaction = Transaction::new_unsigned(sender.clone(), String::new(), *amount);
+                transaction.payload = TxPayload::Shield { note_key, blinding: note.blinding.clone() };
+                write_json(note_out, &note);
+                println!("Unsigned shield transaction {}", transaction.id());
+                println!("Note commitment: {}", note.commitment().unwrap());
+                write_raw(&transaction.to_raw(), out.as_deref());
+            }
+            Commands::ShieldedTransfer { key, params, notes, outputs, unshield, note_out, out } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let (transaction, outputs) = match shielded_transfer(&blockchain, key, params, notes, outputs, unshield.as_deref()) {
+                    Ok(result) => result,
+                    Err(err) => return println!("Cannot create shielded transfer: {}", err),
+                };
+                for (i, note) in outputs.iter().enumerate() {
+                    write_json(&format!("{}-{}.json", note_out, i), note);
+                }
+                println!("Shielded transfer {}", transaction.id());
+                write_raw(&transaction.to_raw(), out.as_deref());
+            }
+            Commands::CreateConfidentialTransaction { keystore, outputs, deposit, withdraw, out } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let transaction = match confidential_transaction(&blockchain, keystore, outputs, *deposit, withdraw.as_deref()) {
+                    Ok(transaction) => transaction,
+                    Err(err) => return println!("Cannot create confidential transaction: {}", err),
+                };
+                println!("Unsigned confidential transaction {}", transaction.id());
+                write_raw(&transaction.to_raw(), out.as_deref());
+            }
+            Commands::GetConfidentialBalance { keystore } => {
+                let wallet = match Wallet::load_from_file(keystore) {
+                    Ok(wallet) => wallet,
+                    Err(err) => return println!("Cannot load keystore: {}", err),
+                };
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let outputs = open_confidential_outputs(&blockchain, &wallet);
+                for output in &outputs {
+                    println!("  {}  {}", output.id, output.amount);
+                }
+                println!("Confidential balance of {}: {}", wallet.address(), outputs.iter().map(|output| output.amount).sum::<u64>());
+            }
+            Commands::GetShieldedPool { note, key } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let pool = &blockchain.shielded_pool;
+                println!("Shielded pool:");
+                println!("  root:       {}", pool.root);
+                println!("  notes:      {}", pool.commitments.len());
+                println!("  nullifiers: {}", pool.nullifiers.len());
+                println!("  value:      {}", pool.value);
+                println!("  verifying keys: {}", blockchain.privacy_vks.len());
+                if let Some(note) = note {
+                    let note: Note = match read_json(note) {
+                        Ok(note) => note,
+                        Err(err) => return println!("{}", err),
+                    };
+                    let commitment = note.commitment().unwrap_or_default();
+                    println!("Note {} (amount {}):", commitment, note.amount);
+                    match pool.position(&commitment) {
+                        Some(position) => println!("  position:   {}", position),
+                        None => println!("  position:   not in the pool"),
+                    }
+                    if let Some(key) = key {
+                        let spent = read_json::<ShieldedKey>(key)
+                            .and_then(|key| note.nullifier(&key))
+                            .map(|nullifier| pool.nullifiers.contains(&nullifier));
+                        match spent {
+                            Ok(spent) => println!("  spent:      {}", spent),
+                            Err(err) => println!("  spent:      unknown ({})", err),
+                        }
+                    }
+                }
             }
             Commands::GetBalance { address } => {
                 let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
                 let balance = blockchain.get_balance(address);
                 println!("Balance of address {}: {}", address, balance);
+                for (asset_id, balance) in blockchain.get_holdings(address).into_iter().skip(1) {
+                    let symbol = blockchain.assets.get(&asset_id).map(|asset| asset.symbol.as_str()).unwrap_or("?");
+                    println!("  {:<8} {}  {}", symbol, asset_id, balance);
+                }
+            }
+            Commands::GetTransaction { id, json } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                match blockchain.get_transaction(id) {
+                    Some((tx, location)) if *json => {
+                        print_json(&json!({ "id": id, "location": location, "transaction": tx }));
+                    }
+                    Some((tx, location)) => {
+                        println!("Transaction {}", id);
+                        println!("  block:     {} ({})", location.block_index, location.block_hash);
+                        println!("  position:  {}", location.position);
+                        print_transaction(tx);
+                    }
+                    None => println!("Transaction not found: {}", id),
+                }
+            }
+            Commands::GetBlock { height_or_hash, json } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                match blockchain.get_block(height_or_hash) {
+                    Some(block) if *json => print_json(block),
+                    Some(block) => print_block(block),
+                    None => println!("Block not found: {}", height_or_hash),
+                }
+            }
+            Commands::GetReceipt { tx_id, json } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                match blockchain.get_receipt(tx_id) {
+                    Some(receipt) if *json => print_json(receipt),
+                    Some(receipt) => print_receipt(receipt),
+                    None => println!("Receipt not found: {}", tx_id),
+                }
+            }
+            Commands::GetProof { key, contract, json } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let key = match contract {
+                    Some(contract_id) => blockchain::storage_key(contract_id, key),
+                    None => key.clone(),
+                };
+                let proof = blockchain.get_state_proof(&key);
+                let tip = blockchain.get_latest_block();
+                if *json {
+                    return print_json(&json!({ "block_index": tip.header.height, "state_root": tip.header.state_root, "proof": proof }));
+                }
+                println!("State proof for {}", proof.key);
+                match &proof.value {
+                    Some(value) => println!("  value:      {}", value),
+                    None => println!("  value:      (absent)"),
+                }
+                println!("  bitmap:     {}", proof.bitmap);
+                for sibling in &proof.siblings {
+                    println!("  sibling:    {}", sibling);
+                }
+                println!("Verified against state root of block #{}: {}", tip.header.height, proof.verify(&tip.header.state_root));
+            }
+            Commands::GetHeaders { from, out } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let headers = blockchain.get_headers(*from);
+                match out {
+                    Some(path) => write_json(path, &headers),
+                    None => print_json(&headers),
+                }
+            }
+            Commands::GetTxProof { tx_id, out } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                match (blockchain.get_tx_proof(tx_id), out) {
+                    (Some(proof), Some(path)) => write_json(path, &proof),
+                    (Some(proof), None) => print_json(&proof),
+                    (None, _) => println!("Transaction not found: {}", tx_id),
+                }
+            }
+            Commands::LightSync { headers, store, difficulty, genesis } => {
+                match light_sync(headers, store, *difficulty, genesis.as_deref()) {
+                    Ok(client) => {
+                        let tip = client.tip();
+                        println!("Light client tip: #{} {}", tip.height, tip.calculate_hash());
+                    }
+                    Err(err) => println!("Light client sync failed: {}", err),
+                }
+            }
+            Commands::VerifyTxProof { proof, store } => {
+                let client = match LightClient::load_from_file(store) {
+                    Ok(client) => client,
+                    Err(err) => return println!("Cannot load light client {}: {}", store, err),
+                };
+                let proof: TxProof = match read_json(proof) {
+                    Ok(proof) => proof,
+                    Err(err) => return println!("{}", err),
+                };
+                println!("Transaction {}", proof.transaction.id());
+                match client.verify_transaction(&proof) {
+                    Ok(confirmations) => println!("Included in block #{} ({} confirmation(s))", proof.block_index, confirmations),
+                    Err(err) => println!("Invalid transaction proof: {}", err),
+                }
+            }
+            Commands::GetLogs { contract, topic, from, to, json } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let filter = LogFilter {
+                    contract_id: contract.clone(),
+                    topics: topic.iter().map(|topic| (topic != "*").then(|| topic.clone())).collect(),
+                };
+                let to = to.unwrap_or(blockchain.get_latest_block().header.height);
+                let logs = blockchain.get_logs(&filter, *from, to);
+                if *json {
+                    print_json(&logs);
+                } else {
+                    for entry in &logs {
+                        println!("#{:<6} {}  {}  {:?}  {}", entry.block_index, entry.tx_id, entry.log.contract_id, entry.log.topics, entry.log.data);
+                    }
+                    println!("{} log(s)", logs.len());
+                }
+            }
+            Commands::ListBlocks { from, to, json } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let to = to.unwrap_or(blockchain.get_latest_block().header.height);
+                let blocks = blockchain.get_blocks(*from, to);
+                if *json {
+                    print_json(&blocks);
+                } else {
+                    for block in blocks {
+                        println!("#{:<6} {}  {}  {} tx(s)", block.header.height, block.hash, format_timestamp(block.header.timestamp), block.transactions.len());
+                    }
+                }
+            }
+            Commands::ChainInfo { json } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let info = match blockchain.chain_info() {
+                    Ok(info) => info,
+                    Err(err) => return println!("Cannot summarize the chain: {}", err),
+                };
+                if *json {
+                    print_json(&info);
+                } else {
+                    println!("Height:               {}", info.height);
+                    println!("Tip:                  {}", info.tip);
+                    println!("Difficulty:           {}", info.difficulty);
+                    println!("Total work:           {}", info.total_work);
+                    println!("Total supply:         {}", info.total_supply);
+                    println!("Pending transactions: {}", info.pending_transactions);
+                    println!("Finalized height:     {}", info.finalized_height);
+                }
+            }
+            Commands::History { address } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                let history = blockchain.get_address_history(address);
+                println!("{} transaction(s) for address {}", history.len(), address);
+                for (tx, location) in history {
+                    println!("block {:>6}  {}  {} -> {}  {}", location.block_index, tx.id(), tx.sender, tx.receiver, tx.amount);
+                }
             }
-            Commands::SetBalance { address, balance } => {
+            Commands::Reindex => {
                 let mut blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
-                blockchain.set_balance(address, *balance);
-                println!("Balance of address {} set to {}", address, balance);
+                blockchain.enable_tx_index();
+                println!("Transaction index rebuilt for {} blocks", blockchain.chain.len());
                 blockchain.save_to_file("blockchain.json").unwrap();
             }
+            Commands::DisconnectBlock => {
+                let mut blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                match blockchain.disconnect_latest_block() {
+                    Ok(block) => println!("Disconnected block {}: {}", block.header.height, block.hash),
+                    Err(err) => println!("Failed to disconnect block: {}", err),
+                }
+                blockchain.save_to_file("blockchain.json").unwrap();
+            }
+            Commands::Sync { peers, parallel } => {
+                let blockchain = Blockchain::load_from_file("blockchain.json").unwrap();
+                // 只作为客户端向对等节点发起同步，不监听
+                let mut node = Node::with_blockchain(SocketAddr::from(([0, 0, 0, 0], 0)), blockchain);
+                for peer in peers {
+                    node.add_peer(*peer);
+                }
+                node.sync_blockchain(*parallel);
+                let blockchain = node.blockchain.lock().unwrap();
+                let tip = blockchain.get_latest_block();
+                println!("Chain tip: #{} {}", tip.header.height, tip.hash);
+                blockchain.save_to_file("blockchain.json").unwrap();
+            }
+            // 节点只写入自己的数据文件，不会覆盖其他命令正在使用的 blockchain.json
+            Commands::Serve { ws_address, miner, block_interval, data_file } => {
+                let blockchain = Blockchain::load_from_file(data_file)
+                    .or_else(|_| Blockchain::load_from_file("blockchain.json"))
+                    .unwrap_or_else(|_| Blockchain::new(4));
+                let node = Node::with_blockchain(*ws_address, blockchain);
+                let server = match node.start_ws_server(*ws_address) {
+                    Ok(server) => server,
+                    Err(err) => return println!("Cannot start WebSocket server: {}", err),
+                };
+                match miner {
+                    Some(miner) => loop {
+                        thread::sleep(Duration::from_secs(*block_interval));
+                        let mut guard = node.blockchain.lock().unwrap();
+                        let blockchain = &mut *guard;
+                        // 出块失败时只移除无效的交易，其余交易留在交易池中并立即重试
+                        let mut result = blockchain.produce_block(blockchain.pending_transactions.clone(), miner, None);
+                        if let Err(err) = &result {
+                            println!("Failed to produce block: {}", err);
+                            let removed = blockchain.remove_invalid_pending();
+                            println!("Removed {} invalid pending transaction(s)", removed.len());
+                            if !removed.is_empty() {
+                                result = blockchain.produce_block(blockchain.pending_transactions.clone(), miner, None);
+                            }
+                        }
+                        match result {
+                            Ok(()) => {
+                                let block = blockchain.get_latest_block();
+                                println!("Block #{} mined with {} transaction(s)", block.header.height, block.transactions.len() - 1);
+                            }
+                            Err(err) => println!("Failed to produce block: {}", err),
+                        }
+                        if let Err(err) = blockchain.save_to_file(data_file) {
+                            println!("Cannot save {}: {}", data_file, err);
+                        }
+                    },
+                    None => {
+                        let _ = server.join();
+                    }
+                }
+            }
         }
     }
 }
+
+// 以格式化 JSON 输出
+fn print_json<T: Serialize>(data: &T) {
+    println!("{}", serde_json::to_string_pretty(data).unwrap());
+}
+
+// 将时间戳格式化为可读时间
+fn format_timestamp(timestamp: i64) -> String {
+    match Utc.timestamp_opt(timestamp, 0).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+// 以可读格式输出区块
+fn print_block(block: &Block) {
+    let header = &block.header;
+    println!("Block #{}", header.height);
+    println!("  hash:          {}", block.hash);
+    println!("  version:       {}", header.version);
+    println!("  previous hash: {}", header.previous_hash);
+    println!("  timestamp:     {}", format_timestamp(header.timestamp));
+    println!("  target:        {}", header.target);
+    println!("  nonce:         {}", header.nonce);
+    println!("  merkle root:   {}", header.merkle_root);
+    if !header.receipts_root.is_empty() {
+        println!("  receipts root: {}", header.receipts_root);
+    }
+    if !header.state_root.is_empty() {
+        println!("  state root:    {}", header.state_root);
+    }
+    if !header.validator.is_empty() {
+        println!("  validator:     {}", header.validator);
+    }
+    if let Some(commit) = &block.commit {
+        println!("  commit:        round {} with {} precommit(s)", commit.round, commit.precommits.len());
+    }
+    println!("  transactions:  {}", block.transactions.len());
+    for tx in &block.transactions {
+        println!("    {}  {} -> {}  {}", tx.id(), tx.sender, tx.receiver, tx.amount);
+    }
+}
+
+// 以可读格式输出交易收据
+fn print_receipt(receipt: &Receipt) {
+    println!("Receipt for transaction {}", receipt.tx_id);
+    println!("  status:   {:?}", receipt.status);
+    println!("  gas used: {}", receipt.gas_used);
+    println!("  fee:      {}", receipt.fee);
+    if !receipt.return_data.is_empty() {
+        println!("  return:   {}", receipt.return_data);
+    }
+    if let Some(error) = &receipt.error {
+        println!("  error:    {}", error);
+    }
+    for log in &receipt.logs {
+        println!("  log:      {} {:?} {}", log.contract_id, log.topics, log.data);
+    }
+}
+
+// 以可读格式输出交易
+fn print_transaction(tx: &Transaction) {
+    println!("  sender:    {}", tx.sender);
+    println!("  receiver:  {}", tx.receiver);
+    println!("  amount:    {}", tx.amount);
+    if !tx.asset.is_empty() {
+        println!("  asset:     {}", tx.asset);
+    }
+    println!("  nonce:     {}", tx.nonce);
+    println!("  signature: {}", tx.signature);
+}
+
+// 读取原始交易：参数为已存在的文件路径时读取文件内容，否则视为十六进制
+fn read_raw(raw: &str) -> String {
+    match fs::read_to_string(raw) {
+        Ok(content) => content.trim().to_string(),
+        Err(_) => raw.trim().to_string(),
+    }
+}
+
+// 读取一笔或多笔原始交易（每行一笔）
+fn read_raw_transactions(raw: &str) -> Result<Vec<Transaction>, String> {
+    read_raw(raw)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(Transaction::from_raw)
+        .collect()
+}
+
+// 输出原始交易：写入文件或打印到终端
+fn write_raw(raw: &str, out: Option<&str>) {
+    match out {
+        Some(path) => {
+            fs::write(path, raw).unwrap();
+            println!("Raw transaction written to: {}", path);
+        }
+        None => println!("{}", raw),
+    }
+}
+
+// 将数据以 JSON 格式写入文件
+fn write_json<T: Serialize>(path: &str, value: &T) {
+    fs::write(path, serde_json::to_string_pretty(value).unwrap()).unwrap();
+    println!("Written to: {}", path);
+}
+
+// 从 JSON 文件读取数据
+fn read_json<T: serde::de::DeserializeOwned>(path: &str) -> Result<T, String> {
+    let json = fs::read_to_string(path).map_err(|err| format!("Cannot read {}: {}", path, err))?;
+    serde_json::from_str(&json).map_err(|err| format!("Invalid {}: {}", path, err))
+}
+
+// 轻客户端同步区块头：数据文件不存在时以第一个区块头（创世区块）创建
+fn light_sync(headers: &str, store: &str, difficulty: usize, genesis: Option<&str>) -> Result<LightClient, String> {
+    let headers: Vec<BlockHeader> = read_json(headers)?;
+    let mut client = match LightClient::load_from_file(store) {
+        Ok(client) => client,
+        Err(_) => {
+            let first = headers.first().ok_or("No headers to sync")?;
+            let hash = first.calculate_hash();
+            if genesis.is_some_and(|genesis| genesis != hash) {
+                return Err(format!("Genesis header {} does not match the trusted genesis", hash));
+            }
+            LightClient::new(first.clone(), difficulty)?
+        }
+    };
+    // 创世区块头和已有的区块头在同步时跳过，因此可以反复同步完整的区块头文件
+    let start = headers.iter().take_while(|header| header.height == 0).count();
+    let synced = client.sync_headers(&headers[start..])?;
+    println!("Synced {} new header(s)", synced);
+    client.save_to_file(store).map_err(|err| format!("Cannot save {}: {}", store, err))?;
+    Ok(client)
+}
+
+// 创建质押或解除质押的交易（未签名，没有接收方）
+fn staking_transaction(sender: &str, amount: u64, payload: TxPayload, out: Option<&str>) {
+    let mut transaction = Transaction::new_unsigned(sender.to_string(), String::new(), amount);
+    transaction.payload = payload;
+    println!("Unsigned {:?} transaction {}", transaction.payload, transaction.id());
+    write_raw(&transaction.to_raw(), out);
+}
+
+// 等待到 signer 可以出块的时间：权益证明为下一次成为出块者的时隙，
+// 权威证明为父区块之后一个周期（没有轮到的签名者再多等一个周期，让轮到的签名者先出块）
+fn wait_for_turn(blockchain: &Blockchain, signer: &Wallet) -> Result<(), String> {
+    let genesis = &blockchain.chain[0].header;
+    let parent = &blockchain.get_latest_block().header;
+    let state = blockchain.consensus_state();
+    let address = signer.address();
+    let start = match &blockchain.consensus {
+        ConsensusConfig::ProofOfWork => return Err("Waiting for a turn requires proof of stake or proof of authority".to_string()),
+        ConsensusConfig::ProofOfStake { slot_duration, .. } => {
+            let pos = ProofOfStake { slot_duration: *slot_duration };
+            // 新区块必须在父区块之后的时隙
+            let parent_slot = pos.slot(genesis, parent.timestamp);
+            let first = pos.slot(genesis, Utc::now().timestamp()).max(parent_slot + 1);
+            let slot = (first..first + MAX_WAIT_SLOTS)
+                .find(|slot| pos.leader(genesis, *slot, &state.stakes) == Some(&address))
+                .ok_or_else(|| format!("Not a leader in the next {} slots", MAX_WAIT_SLOTS))?;
+            println!("Waiting for slot {}", slot);
+            pos.slot_start(genesis, slot)
+        }
+        ConsensusConfig::ProofOfAuthority { period, .. } => {
+            let poa = ProofOfAuthority { period: *period };
+            let height = parent.height + 1;
+            if !state.authorities.contains_key(&address) {
+                return Err(format!("{} is not an authorized signer", address));
+            }
+            if poa.signed_recently(&address, height, &state.authorities) {
+                return Err(format!("{} signed recently, another signer must produce block {}", address, height));
+            }
+            let in_turn = poa.in_turn(height, &state.authorities) == Some(&address);
+            println!("Block {} is {} for this signer", height, if in_turn { "in turn" } else { "out of turn" });
+            parent.timestamp + if in_turn { *period } else { period * 2 }
+        }
+    };
+    println!("Waiting until {}", format_timestamp(start));
+    let delay = start - Utc::now().timestamp();
+    if delay > 0 {
+        thread::sleep(Duration::from_secs(delay as u64));
+    }
+    Ok(())
+}
+
+// 解析 <地址>:<金额>
+fn parse_payment(value: &str) -> Result<(String, u64), String> {
+    let (address, amount) = value.rsplit_once(':').ok_or_else(|| format!("Expected <address>:<amount>, got {}", value))?;
+    let amount = amount.parse().map_err(|_| format!("Invalid amount in {}", value))?;
+    Ok((address.to_string(), amount))
+}
+
+// 构建隐私转账：基于当前树根为每个票据生成认证路径并创建证明，返回交易和新票据
+fn shielded_transfer(blockchain: &Blockchain, key: &str, params: &str, notes: &[String], outputs: &[String], unshield: Option<&str>) -> Result<(Transaction, Vec<Note>), String> {
+    let key: ShieldedKey = read_json(key)?;
+    let params = privacy::read_parameters(params)?;
+    let pool = &blockchain.shielded_pool;
+    let spends = notes
+        .iter()
+        .map(|path| {
+            let note: Note = read_json(path)?;
+            let position = pool.position(&note.commitment()?).ok_or_else(|| format!("Note {} is not in the shielded pool", path))?;
+            Ok(SpendInput { path: pool.path(position)?, note, position })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let outputs = outputs
+        .iter()
+        .map(|output| parse_payment(output).map(|(owner, amount)| Note::new(&owner, amount)))
+        .collect::<Result<Vec<_>, String>>()?;
+    let (receiver, public_value) = match unshield {
+        Some(unshield) => parse_payment(unshield)?,
+        None => (String::new(), 0),
+    };
+    let privacy_transaction = PrivacyTransaction::new(&params, &key, &pool.root, &spends, &outputs, public_value, &receiver)?;
+    let mut transaction = Transaction::new_unsigned(SHIELDED_SENDER.to_string(), receiver, public_value);
+    transaction.payload = TxPayload::ShieldedTransfer(privacy_transaction);
+    Ok((transaction, outputs))
+}
+
+// 生成金额为 0 的隐私转账证明，分别计时逐个验证、批量验证，以及含一个无效证明时的批量验证
+fn bench_privacy_verification(params: &str, proofs: usize, count: usize) -> Result<(), String> {
+    let params = privacy::read_parameters(params)?;
+    let keys = VerifyingKeys::parse(&[privacy::verifying_key(&params)])?;
+    let key = ShieldedKey::generate();
+    let anchor = privacy::empty_nodes()[privacy::TREE_DEPTH].clone();
+    let start = Instant::now();
+    let transactions = (0..proofs.max(1))
+        .map(|_| PrivacyTransaction::new(&params, &key, &anchor, &[], &[], 0, ""))
+        .collect::<Result<Vec<_>, _>>()?;
+    println!("Generated {} proofs in {:?}", transactions.len(), start.elapsed());
+
+    let mut statements: Vec<ProofStatement> = transactions.iter().cycle().take(count.max(1)).map(|transaction| (transaction, 0, "")).collect();
+    let start = Instant::now();
+    let sequential = keys.verify_sequential(&statements);
+    let sequential_time = start.elapsed();
+    let start = Instant::now();
+    let batched = keys.verify_batch(&statements);
+    let batch_time = start.elapsed();
+    println!("Sequential: {:?} for {} proofs ({:?})", sequential_time, statements.len(), sequential);
+    println!("Batch:      {:?} for {} proofs ({:?})", batch_time, statements.len(), batched);
+    println!("Speedup:    {:.2}x", sequential_time.as_secs_f64() / batch_time.as_secs_f64());
+
+    // 最后一个证明绑定到其它接收方，批量检查失败后逐个验证定位无效证明
+    let last = statements.len() - 1;
+    statements[last].2 = "mallory";
+    let start = Instant::now();
+    let result = keys.verify_batch(&statements);
+    println!("Batch with an invalid proof: {:?}, failing index {:?}", start.elapsed(), result.err());
+    Ok(())
+}
+
+// 解密钱包拥有的机密输出（解密失败的输出忽略）
+fn open_confidential_outputs(blockchain: &Blockchain, wallet: &Wallet) -> Vec<OpenedOutput> {
+    let seed = wallet.seed();
+    blockchain
+        .confidential_outputs_of(&wallet.address())
+        .into_iter()
+        .filter_map(|(id, output)| output.decrypt(&seed).map(|(amount, blinding)| OpenedOutput { id, amount, blinding }))
+        .collect()
+}
+
+// 构建机密转账：按金额从大到小选取发送方的机密输出，多出的部分作为找零输出返还发送方
+fn confidential_transaction(blockchain: &Blockchain, keystore: &str, outputs: &[String], deposit: u64, withdraw: Option<&str>) -> Result<Transaction, String> {
+    let wallet = Wallet::load_from_file(keystore).map_err(|err| format!("Cannot load keystore: {}", err))?;
+    let sender = wallet.address();
+    let mut payments = outputs.iter().map(|output| parse_payment(output)).collect::<Result<Vec<_>, String>>()?;
+    let (receiver, withdraw) = match withdraw {
+        Some(withdraw) => parse_payment(withdraw)?,
+        None => (String::new(), 0),
+    };
+    let target = payments
+        .iter()
+        .try_fold(withdraw, |total, (_, amount)| total.checked_add(*amount))
+        .ok_or("Confidential outputs and withdrawal overflow u64")?;
+
+    let mut available = open_confidential_outputs(blockchain, &wallet);
+    available.sort_by_key(|output| std::cmp::Reverse(output.amount));
+    let mut inputs = Vec::new();
+    let mut total = deposit;
+    for output in available {
+        if total >= target {
+            break;
+        }
+        total = total.checked_add(output.amount).ok_or("Confidential inputs and deposit overflow u64")?;
+        inputs.push(output);
+    }
+    if total < target {
+        return Err(format!("Insufficient confidential funds: need {}, available {}", target, total));
+    }
+    if total > target {
+        payments.push((sender.clone(), total - target));
+    }
+
+    let transfer = ConfidentialTransfer::new(&inputs, &payments, deposit, withdraw)?;
+    let mut transaction = Transaction::new_unsigned(sender, receiver, deposit);
+    transaction.payload = TxPayload::Confidential(transfer);
+    Ok(transaction)
+}
+
+// 读取汇编源文件并编译为十六进制字节码
+fn assemble_file(path: &str) -> Result<String, String> {
+    let source = fs::read_to_string(path).map_err(|err| format!("Cannot read {}: {}", path, err))?;
+    vm::assemble(&source).map(hex::encode)
+}
+
+// 读取代币元数据（JSON 字符串或文件路径），未指定 owner 时使用部署者
+fn token_metadata(code: &str, sender: &str) -> Result<String, String> {
+    let json = if code.trim_start().starts_with('{') {
+        code.to_string()
+    } else {
+        fs::read_to_string(code).map_err(|err| format!("Cannot read {}: {}", code, err))?
+    };
+    let mut metadata: TokenMetadata = serde_json::from_str(&json).map_err(|err| err.to_string())?;
+    if metadata.owner.is_empty() {
+        metadata.owner = sender.to_string();
+    }
+    Ok(serde_json::to_string(&metadata).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_formatted_in_utc() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_700_000_000), "2023-11-14 22:13:20 UTC");
+        assert_eq!(format_timestamp(i64::MAX), i64::MAX.to_string());
+    }
+
+    #[test]
+    fn raw_transactions_are_signed_offline_and_accepted() {
+        let dir = std::env::temp_dir().join(format!("offline-signing-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let keystore = dir.join("key.json").to_string_lossy().to_string();
+        let raw_file = dir.join("unsigned.hex").to_string_lossy().to_string();
+
+        let wallet = Wallet::new();
+        wallet.save_to_file(&keystore).unwrap();
+        let mut blockchain = Blockchain::new(1);
+        blockchain.add_block(Vec::new(), &wallet.address());
+
+        // 在线机器生成未签名的原始交易，一个文件中每行一笔
+        let unsigned = [
+            Transaction::new_unsigned(wallet.address(), "bob".to_string(), 10),
+            Transaction::new_unsigned(wallet.address(), "carol".to_string(), 15),
+        ];
+        let raws: Vec<String> = unsigned.iter().map(Transaction::to_raw).collect();
+        fs::write(&raw_file, raws.join("\n")).unwrap();
+
+        // 离线机器读取文件并用密钥库签名
+        let offline = Wallet::load_from_file(&keystore).unwrap();
+        let mut transactions = read_raw_transactions(&raw_file).unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert!(transactions.iter().all(|tx| !tx.is_signed()));
+        for tx in &mut transactions {
+            tx.sign(&offline.key_pair);
+        }
+
+        // 签名不改变交易 ID，篡改后签名失效
+        let signed = read_raw_transactions(&transactions.iter().map(Transaction::to_raw).collect::<Vec<_>>().join("\n")).unwrap();
+        assert_eq!(signed[0].id(), unsigned[0].id());
+        assert!(signed.iter().all(Transaction::verify));
+        let mut tampered = signed[0].clone();
+        tampered.amount = 40;
+        assert!(!tampered.verify());
+        assert!(blockchain.add_transaction(tampered).is_err());
+
+        for tx in signed {
+            blockchain.add_transaction(tx).unwrap();
+        }
+        assert!(read_raw_transactions("not hex").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn overflowing_confidential_amounts_are_reported() {
+        let dir = std::env::temp_dir().join(format!("confidential-overflow-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let keystore = dir.join("key.json").to_string_lossy().to_string();
+        Wallet::new().save_to_file(&keystore).unwrap();
+        let blockchain = Blockchain::new(1);
+
+        let outputs = vec![format!("bob:{}", u64::MAX), "carol:1".to_string()];
+        let error = confidential_transaction(&blockchain, &keystore, &outputs, 0, None).unwrap_err();
+        assert!(error.contains("overflow"), "{}", error);
+        let withdraw = format!("dave:{}", u64::MAX);
+        let error = confidential_transaction(&blockchain, &keystore, &outputs[1..], 0, Some(&withdraw)).unwrap_err();
+        assert!(error.contains("overflow"), "{}", error);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,304 @@
+use serde::{Serialize, Deserialize};
+use bulletproofs::{BulletproofGens, PedersenGens, RangeProof};
+use curve25519_dalek::constants::ED25519_BASEPOINT_POINT;
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use merlin::Transcript;
+use rand::rngs::OsRng;
+use sha2::{Sha512, Digest};
+
+// 机密交易：金额隐藏在 Pedersen 承诺 C = v·B + r·B' 中（Ristretto 群），
+// 每笔交易的全部输出共用一个聚合的 Bulletproofs 范围证明（金额在 [0, 2^64) 内），
+// 输入承诺之和加公开存入金额等于输出承诺之和加公开取出金额（盲化因子之和相等）。
+// 输出的金额和盲化因子用接收方 Ed25519 公钥做 Diffie-Hellman 加密，只有接收方能解密。
+
+pub const MAX_OUTPUTS: usize = 4; // 每笔交易最多的机密输出数
+const RANGE_BITS: usize = 64;
+const TRANSCRIPT_LABEL: &[u8] = b"b3-confidential-range-proof";
+
+// 机密输出：归属于普通地址，花费时由该地址签名
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfidentialOutput {
+    pub owner: String,            // 接收方地址（Ed25519 公钥）
+    pub commitment: String,       // 金额的 Pedersen 承诺（压缩的 Ristretto 点）
+    pub ephemeral_key: String,    // 发送方的临时公钥 R = r·G（Ed25519 点）
+    pub encrypted_amount: String, // 加密的金额（8 字节）和盲化因子（32 字节）
+}
+
+// 机密转账的附加数据：inputs 为花费的机密输出 ID（"<交易ID>:<序号>"），
+// withdraw 为从机密输出中取出、记入交易 receiver 的公开金额，交易的 amount 为从发送方余额存入的公开金额
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfidentialTransfer {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<ConfidentialOutput>,
+    pub range_proof: String, // 输出承诺的聚合范围证明，没有输出时为空
+    #[serde(default)]
+    pub withdraw: u64,
+}
+
+// 已解密的机密输出
+#[derive(Debug, Clone)]
+pub struct OpenedOutput {
+    pub id: String,
+    pub amount: u64,
+    pub blinding: Scalar,
+}
+
+impl ConfidentialOutput {
+    // 为接收方加密金额，返回输出和承诺
+    fn new(owner: &str, amount: u64, blinding: Scalar) -> Result<Self, String> {
+        let owner_point = parse_address(owner)?;
+        let ephemeral = Scalar::random(&mut OsRng);
+        let commitment = PedersenGens::default().commit(Scalar::from(amount), blinding).compress();
+        let shared = (ephemeral * owner_point).compress();
+        let mut plaintext = amount.to_le_bytes().to_vec();
+        plaintext.extend_from_slice(blinding.as_bytes());
+        Ok(ConfidentialOutput {
+            owner: owner.to_string(),
+            commitment: hex::encode(commitment.as_bytes()),
+            ephemeral_key: hex::encode((ephemeral * ED25519_BASEPOINT_POINT).compress().as_bytes()),
+            encrypted_amount: hex::encode(xor_keystream(&plaintext, shared.as_bytes(), commitment.as_bytes())),
+        })
+    }
+
+    // 用接收方的 Ed25519 私钥种子解密金额，并检查与承诺一致
+    pub fn decrypt(&self, seed: &[u8; 32]) -> Option<(u64, Scalar)> {
+        let commitment = parse_commitment(&self.commitment).ok()?;
+        let ephemeral = CompressedEdwardsY(hex::decode(&self.ephemeral_key).ok()?.try_into().ok()?).decompress()?;
+        let shared = (secret_scalar(seed) * ephemeral).compress();
+        let plaintext = xor_keystream(&hex::decode(&self.encrypted_amount).ok()?, shared.as_bytes(), commitment.as_bytes());
+        if plaintext.len() != 40 {
+            return None;
+        }
+        let amount = u64::from_le_bytes(plaintext[..8].try_into().ok()?);
+        let blinding = Option::from(Scalar::from_canonical_bytes(plaintext[8..].try_into().ok()?))?;
+        (PedersenGens::default().commit(Scalar::from(amount), blinding).compress() == commitment).then_some((amount, blinding))
+    }
+}
+
+impl ConfidentialTransfer {
+    // 创建机密转账：花费已解密的 inputs，存入 deposit，向 payments 中的地址创建机密输出，取出 withdraw；
+    // 最后一个输出的盲化因子使输入和输出的盲化因子之和相等
+    pub fn new(inputs: &[OpenedOutput], payments: &[(String, u64)], deposit: u64, withdraw: u64) -> Result<Self, String> {
+        if payments.len() > MAX_OUTPUTS {
+            return Err(format!("At most {} confidential outputs are supported", MAX_OUTPUTS));
+        }
+        if inputs.is_empty() && payments.is_empty() {
+            return Err("Confidential transfers need inputs or outputs".to_string());
+        }
+        let input_total = inputs.iter().map(|input| input.amount as u128).sum::<u128>() + deposit as u128;
+        let output_total = payments.iter().map(|(_, amount)| *amount as u128).sum::<u128>() + withdraw as u128;
+        if input_total != output_total {
+            return Err(format!("Inputs total {} but outputs total {}", input_total, output_total));
+        }
+
+        let input_blinding: Scalar = inputs.iter().map(|input| input.blinding).sum();
+        let mut blindings: Vec<Scalar> = (1..payments.len()).map(|_| Scalar::random(&mut OsRng)).collect();
+        if !payments.is_empty() {
+            blindings.push(input_blinding - blindings.iter().sum::<Scalar>());
+        }
+        let outputs = payments
+            .iter()
+            .zip(&blindings)
+            .map(|((owner, amount), blinding)| ConfidentialOutput::new(owner, *amount, *blinding))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let range_proof = if payments.is_empty() {
+            String::new()
+        } else {
+            // 聚合证明要求输出数为 2 的幂，用金额和盲化因子都为 0 的承诺（单位元）补齐
+            let size = payments.len().next_power_of_two();
+            let mut values: Vec<u64> = payments.iter().map(|(_, amount)| *amount).collect();
+            values.resize(size, 0);
+            blindings.resize(size, Scalar::ZERO);
+            let mut transcript = Transcript::new(TRANSCRIPT_LABEL);
+            let (proof, _) = RangeProof::prove_multiple(&bulletproof_gens(), &PedersenGens::default(), &mut transcript, &values, &blindings, RANGE_BITS)
+                .map_err(|err| err.to_string())?;
+            hex::encode(proof.to_bytes())
+        };
+        Ok(ConfidentialTransfer {
+            inputs: inputs.iter().map(|input| input.id.clone()).collect(),
+            outputs,
+            range_proof,
+            withdraw,
+        })
+    }
+
+    // 验证范围证明和承诺平衡：Σ 输入承诺 + deposit·B = Σ 输出承诺 + withdraw·B
+    pub fn verify(&self, input_commitments: &[String], deposit: u64) -> Result<(), String> {
+        if self.outputs.len() > MAX_OUTPUTS {
+            return Err(format!("At most {} confidential outputs are supported", MAX_OUTPUTS));
+        }
+        if self.inputs.is_empty() && self.outputs.is_empty() {
+            return Err("Confidential transfers need inputs or outputs".to_string());
+        }
+        let mut outputs = Vec::new();
+        for output in &self.outputs {
+            parse_address(&output.owner)?;
+            outputs.push(parse_commitment(&output.commitment)?);
+        }
+        if !outputs.is_empty() {
+            let proof = hex::decode(&self.range_proof)
+                .ok()
+                .and_then(|bytes| RangeProof::from_bytes(&bytes).ok())
+                .ok_or("Invalid range proof encoding")?;
+            let mut padded = outputs.clone();
+            padded.resize(outputs.len().next_power_of_two(), CompressedRistretto::identity());
+            let mut transcript = Transcript::new(TRANSCRIPT_LABEL);
+            proof
+                .verify_multiple(&bulletproof_gens(), &PedersenGens::default(), &mut transcript, &padded, RANGE_BITS)
+                .map_err(|_| "Invalid range proof".to_string())?;
+        }
+
+        let pedersen = PedersenGens::default();
+        let mut balance = pedersen.commit(Scalar::from(deposit), Scalar::ZERO) - pedersen.commit(Scalar::from(self.withdraw), Scalar::ZERO);
+        for commitment in input_commitments {
+            balance += decompress(&parse_commitment(commitment)?)?;
+        }
+        for commitment in &outputs {
+            balance -= decompress(commitment)?;
+        }
+        if balance != RistrettoPoint::identity() {
+            return Err("Confidential inputs and outputs do not balance".to_string());
+        }
+        Ok(())
+    }
+}
+
+// 机密输出 ID
+pub fn output_id(tx_id: &str, index: usize) -> String {
+    format!("{}:{}", tx_id, index)
+}
+
+fn bulletproof_gens() -> BulletproofGens {
+    BulletproofGens::new(RANGE_BITS, MAX_OUTPUTS)
+}
+
+// Ed25519 私钥对应的标量：SHA-512(种子) 的前 32 字节按 RFC 8032 处理
+fn secret_scalar(seed: &[u8; 32]) -> Scalar {
+    let digest = Sha512::digest(seed);
+    let mut bytes: [u8; 32] = digest[..32].try_into().unwrap();
+    bytes[0] &= 248;
+    bytes[31] &= 127;
+    bytes[31] |= 64;
+    Scalar::from_bytes_mod_order(bytes)
+}
+
+// 地址（Ed25519 公钥）对应的曲线点
+fn parse_address(address: &str) -> Result<curve25519_dalek::EdwardsPoint, String> {
+    hex::decode(address)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .and_then(|bytes| CompressedEdwardsY(bytes).decompress())
+        .ok_or_else(|| format!("Invalid address: {}", address))
+}
+
+fn parse_commitment(commitment: &str) -> Result<CompressedRistretto, String> {
+    hex::decode(commitment)
+        .ok()
+        .and_then(|bytes| CompressedRistretto::from_slice(&bytes).ok())
+        .ok_or_else(|| format!("Invalid commitment: {}", commitment))
+}
+
+fn decompress(commitment: &CompressedRistretto) -> Result<RistrettoPoint, String> {
+    commitment.decompress().ok_or_else(|| "Invalid commitment point".to_string())
+}
+
+// 以共享密钥和承诺派生密钥流，与数据异或（加密和解密相同）
+fn xor_keystream(data: &[u8], shared: &[u8], commitment: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha512::new();
+    hasher.update(b"b3-confidential-amount");
+    hasher.update(shared);
+    hasher.update(commitment);
+    let keystream = hasher.finalize();
+    data.iter().zip(keystream.iter()).map(|(byte, key)| byte ^ key).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::transaction::{Transaction, TxPayload};
+    use crate::wallet::Wallet;
+
+    fn opened(id: &str, output: &ConfidentialOutput, wallet: &Wallet) -> OpenedOutput {
+        let (amount, blinding) = output.decrypt(&wallet.seed()).unwrap();
+        OpenedOutput { id: id.to_string(), amount, blinding }
+    }
+
+    #[test]
+    fn only_the_receiver_decrypts_an_output() {
+        let (alice, bob) = (Wallet::new(), Wallet::new());
+        let transfer = ConfidentialTransfer::new(&[], &[(alice.address(), 6), (bob.address(), 4)], 10, 0).unwrap();
+        assert_eq!(transfer.outputs[0].decrypt(&alice.seed()).map(|(amount, _)| amount), Some(6));
+        assert_eq!(transfer.outputs[1].decrypt(&bob.seed()).map(|(amount, _)| amount), Some(4));
+        assert!(transfer.outputs[0].decrypt(&bob.seed()).is_none());
+        assert!(ConfidentialTransfer::new(&[], &[("bob".to_string(), 1)], 1, 0).is_err());
+    }
+
+    #[test]
+    fn commitments_balance_with_public_amounts() {
+        let (alice, bob) = (Wallet::new(), Wallet::new());
+        let deposit = ConfidentialTransfer::new(&[], &[(alice.address(), 6), (bob.address(), 4)], 10, 0).unwrap();
+        assert!(deposit.verify(&[], 10).is_ok());
+        assert!(deposit.verify(&[], 9).is_err());
+        assert!(ConfidentialTransfer::new(&[], &[(alice.address(), 6)], 10, 0).is_err());
+
+        // 花费输出：2 给 bob，取出 4
+        let input = opened("deposit:0", &deposit.outputs[0], &alice);
+        let spend = ConfidentialTransfer::new(&[input], &[(bob.address(), 2)], 0, 4).unwrap();
+        let inputs = [deposit.outputs[0].commitment.clone()];
+        assert!(spend.verify(&inputs, 0).is_ok());
+        assert!(spend.verify(&[deposit.outputs[1].commitment.clone()], 0).is_err());
+        let mut inflated = spend.clone();
+        inflated.withdraw = 5;
+        assert!(inflated.verify(&inputs, 0).is_err());
+
+        // 范围证明与输出承诺及其顺序绑定
+        let mut swapped = deposit.clone();
+        swapped.outputs.swap(0, 1);
+        assert!(swapped.verify(&[], 10).is_err());
+    }
+
+    #[test]
+    fn confidential_outputs_move_funds_on_chain() {
+        let (alice, bob) = (Wallet::new(), Wallet::new());
+        let mut blockchain = Blockchain::new(1);
+        blockchain.add_block(Vec::new(), &alice.address());
+        let funded = blockchain.get_balance(&alice.address());
+
+        let mut deposit = Transaction::new_unsigned(alice.address(), String::new(), 10);
+        deposit.payload = TxPayload::Confidential(ConfidentialTransfer::new(&[], &[(alice.address(), 10)], 10, 0).unwrap());
+        deposit.sign(&alice.key_pair);
+        blockchain.produce_block(vec![deposit.clone()], "miner", None).unwrap();
+        assert_eq!(blockchain.get_balance(&alice.address()), funded - 10);
+        assert_eq!(blockchain.confidential_supply, 10);
+
+        // 花费机密输出：7 给 bob，取出 3 给 carol；只有所有者能花费
+        let (id, output) = blockchain.confidential_outputs_of(&alice.address()).remove(0);
+        assert_eq!(id, output_id(&deposit.id(), 0));
+        let transfer = ConfidentialTransfer::new(&[opened(&id, &output, &alice)], &[(bob.address(), 7)], 0, 3).unwrap();
+        let mut stolen = Transaction::new_unsigned(bob.address(), "carol".to_string(), 0);
+        stolen.payload = TxPayload::Confidential(transfer.clone());
+        stolen.sign(&bob.key_pair);
+        assert!(blockchain.add_transaction(stolen).is_err());
+
+        let mut spend = Transaction::new_unsigned(alice.address(), "carol".to_string(), 0);
+        spend.payload = TxPayload::Confidential(transfer);
+        spend.sign(&alice.key_pair);
+        blockchain.produce_block(vec![spend.clone()], "miner", None).unwrap();
+        assert_eq!(blockchain.get_balance("carol"), 3);
+        assert_eq!(blockchain.confidential_supply, 7);
+        assert!(blockchain.confidential_outputs_of(&alice.address()).is_empty());
+        assert_eq!(blockchain.confidential_outputs_of(&bob.address())[0].1.decrypt(&bob.seed()).unwrap().0, 7);
+        assert!(blockchain.add_transaction(spend).is_err());
+        assert!(blockchain.is_chain_valid());
+
+        blockchain.disconnect_latest_block().unwrap();
+        assert_eq!(blockchain.confidential_supply, 10);
+        assert!(blockchain.confidential_outputs.contains_key(&id));
+        assert_eq!(blockchain.get_balance("carol"), 0);
+    }
+}
@@ -0,0 +1,471 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use crate::block::{Block, BlockHeader};
+use crate::multisig;
+use crate::wallet::Wallet;
+
+// 共识配置：在创世时确定，决定如何出块和验证区块头
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[allow(clippy::enum_variant_names)] // 与各共识引擎的名称一致
+pub enum ConsensusConfig {
+    #[default]
+    ProofOfWork,
+    // 权益证明：时间按 slot_duration 秒划分为时隙，每个时隙按质押加权选出一个出块者；
+    // genesis_stakes 为创世时的验证者及其质押
+    ProofOfStake { slot_duration: i64, genesis_stakes: Vec<(String, u64)> },
+    // 权威证明：genesis_signers 为创世时授权的签名者，按顺序轮流出块，相邻区块的时间间隔至少为 period 秒；
+    // 签名者可以投票增加或移除签名者
+    ProofOfAuthority { period: i64, genesis_signers: Vec<String> },
+}
+
+// 命令行中选择的共识类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsensusKind {
+    Pow,
+    Pos,
+    Poa,
+}
+
+impl FromStr for ConsensusKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pow" => Ok(ConsensusKind::Pow),
+            "pos" => Ok(ConsensusKind::Pos),
+            "poa" => Ok(ConsensusKind::Poa),
+            _ => Err(format!("Unknown consensus '{}', expected 'pow', 'pos' or 'poa'", s)),
+        }
+    }
+}
+
+// 共识相关的链上状态：验证者的质押，以及权威证明的签名者（地址 -> 最近签名的区块高度，0 表示尚未签名）
+#[derive(Debug, Clone, Default)]
+pub struct ConsensusState {
+    pub stakes: BTreeMap<String, u64>,
+    pub authorities: BTreeMap<String, u64>,
+}
+
+// 检查区块头时需要的链上信息：创世区块头、父区块头和父区块执行后的共识状态
+pub struct ConsensusContext<'a> {
+    pub genesis: &'a BlockHeader,
+    pub parent: &'a BlockHeader,
+    pub state: &'a ConsensusState,
+}
+
+// 共识引擎：负责出块（填写并封装区块头中的共识字段）和验证区块头
+pub trait Consensus {
+    // 出块前填写区块头中的共识字段，并检查 signer 是否有权在该区块出块
+    fn prepare(&self, header: &mut BlockHeader, context: &ConsensusContext, signer: Option<&Wallet>) -> Result<(), String>;
+
+    // 执行交易并写入收据根和状态根后封装区块，计算区块哈希
+    fn seal(&self, block: &mut Block, signer: Option<&Wallet>) -> Result<(), String>;
+
+    // 只依赖区块头本身的检查（先同步区块头时使用）
+    fn verify_seal(&self, header: &BlockHeader, hash: &str) -> Result<(), String>;
+
+    // 完整检查：封装以及依赖父区块和链上状态的规则
+    fn verify_header(&self, header: &BlockHeader, hash: &str, context: &ConsensusContext) -> Result<(), String> {
+        let _ = context;
+        self.verify_seal(header, hash)
+    }
+}
+
+impl ConsensusConfig {
+    // 创建共识引擎，difficulty 为工作量证明的难度
+    pub fn engine(&self, difficulty: usize) -> Box<dyn Consensus> {
+        match self {
+            ConsensusConfig::ProofOfWork => Box::new(ProofOfWork { difficulty }),
+            ConsensusConfig::ProofOfStake { slot_duration, .. } => Box::new(ProofOfStake { slot_duration: *slot_duration }),
+            ConsensusConfig::ProofOfAuthority { period, .. } => Box::new(ProofOfAuthority { period: *period }),
+        }
+    }
+}
+
+// 工作量证明：区块哈希需要 difficulty 个前导零
+pub struct ProofOfWork {
+    pub difficulty: usize,
+}
+
+impl Consensus for ProofOfWork {
+    fn prepare(&self, header: &mut BlockHeader, _context: &ConsensusContext, _signer: Option<&Wallet>) -> Result<(), String> {
+        header.target = self.difficulty;
+        Ok(())
+    }
+
+    fn seal(&self, block: &mut Block, _signer: Option<&Wallet>) -> Result<(), String> {
+        block.mine_block(self.difficulty);
+        Ok(())
+    }
+
+    fn verify_seal(&self, header: &BlockHeader, hash: &str) -> Result<(), String> {
+        if header.target != self.difficulty {
+            return Err(format!("Invalid target for header {}", header.height));
+        }
+        if !header.meets_target(hash) {
+            return Err(format!("Insufficient proof of work for header {}", header.height));
+        }
+        Ok(())
+    }
+}
+
+// 权益证明：每个时隙最多一个区块，由按质押加权确定性选出的出块者签名
+pub struct ProofOfStake {
+    pub slot_duration: i64,
+}
+
+impl ProofOfStake {
+    // 区块头时间戳所在的时隙（从创世区块开始计算）
+    pub fn slot(&self, genesis: &BlockHeader, timestamp: i64) -> u64 {
+        ((timestamp - genesis.timestamp).max(0) / self.slot_duration) as u64
+    }
+
+    // 时隙的开始时间
+    pub fn slot_start(&self, genesis: &BlockHeader, slot: u64) -> i64 {
+        genesis.timestamp + slot as i64 * self.slot_duration
+    }
+
+    // 时隙的出块者：以创世区块哈希和时隙为种子，在按地址排序的质押区间中取一个位置
+    pub fn leader<'a>(&self, genesis: &BlockHeader, slot: u64, stakes: &'a BTreeMap<String, u64>) -> Option<&'a String> {
+        let total: u64 = stakes.values().sum();
+        if total == 0 {
+            return None;
+        }
+        let seed = Sha256::digest(format!("{}:{}", genesis.calculate_hash(), slot));
+        let mut point = u64::from_be_bytes(seed[..8].try_into().unwrap()) % total;
+        for (address, stake) in stakes {
+            if point < *stake {
+                return Some(address);
+            }
+            point -= stake;
+        }
+        None
+    }
+}
+
+impl Consensus for ProofOfStake {
+    fn prepare(&self, header: &mut BlockHeader, context: &ConsensusContext, signer: Option<&Wallet>) -> Result<(), String> {
+        let signer = signer.ok_or("Proof of stake blocks must be signed by the slot leader")?;
+        let slot = self.slot(context.genesis, header.timestamp);
+        if header.height > 1 && slot <= self.slot(context.genesis, context.parent.timestamp) {
+            return Err(format!("Slot {} already has a block", slot));
+        }
+        let leader = self.leader(context.genesis, slot, &context.state.stakes).ok_or("No validators have staked coins")?;
+        if *leader != signer.address() {
+            return Err(format!("Not the leader of slot {} (leader is {})", slot, leader));
+        }
+        header.target = 0;
+        header.validator = leader.clone();
+        Ok(())
+    }
+
+    fn seal(&self, block: &mut Block, signer: Option<&Wallet>) -> Result<(), String> {
+        let signer = signer.ok_or("Proof of stake blocks must be signed by the slot leader")?;
+        sign_block(block, signer);
+        Ok(())
+    }
+
+    fn verify_seal(&self, header: &BlockHeader, hash: &str) -> Result<(), String> {
+        if header.target != 0 {
+            return Err(format!("Invalid target for header {}", header.height));
+        }
+        verify_block_signature(header, hash)
+    }
+
+    fn verify_header(&self, header: &BlockHeader, hash: &str, context: &ConsensusContext) -> Result<(), String> {
+        self.verify_seal(header, hash)?;
+        let slot = self.slot(context.genesis, header.timestamp);
+        if header.height > 1 && slot <= self.slot(context.genesis, context.parent.timestamp) {
+            return Err(format!("Header {} is not in a later slot than its parent", header.height));
+        }
+        if header.timestamp > Utc::now().timestamp() + self.slot_duration {
+            return Err(format!("Header {} is from a future slot", header.height));
+        }
+        if self.leader(context.genesis, slot, &context.state.stakes) != Some(&header.validator) {
+            return Err(format!("Header {} is not signed by the leader of slot {}", header.height, slot));
+        }
+        Ok(())
+    }
+}
+
+// 权威证明（类似 Clique）：授权签名者按地址顺序轮流出块，轮到的签名者（in-turn）出块的目标为 1，
+// 其他签名者（out-of-turn）也可以出块但目标为 0，分叉选择时轮到的签名者的区块权重更大（16 和 1）；
+// 每个签名者在连续 n/2 + 1 个区块中最多签名一个（n 为签名者数量）
+pub struct ProofOfAuthority {
+    pub period: i64,
+}
+
+impl ProofOfAuthority {
+    // 在高度 height 轮到出块的签名者
+    pub fn in_turn<'a>(&self, height: u64, authorities: &'a BTreeMap<String, u64>) -> Option<&'a String> {
+        if authorities.is_empty() {
+            return None;
+        }
+        authorities.keys().nth((height % authorities.len() as u64) as usize)
+    }
+
+    // 签名者是否在最近的区块中签过名，因而不能为高度 height 的区块签名
+    pub fn signed_recently(&self, signer: &str, height: u64, authorities: &BTreeMap<String, u64>) -> bool {
+        let limit = authorities.len() as u64 / 2;
+        match authorities.get(signer) {
+            Some(&last) => last > 0 && height - last <= limit,
+            None => false,
+        }
+    }
+
+    // 签名者在高度 height 出块时区块头的目标
+    fn target(&self, signer: &str, height: u64, authorities: &BTreeMap<String, u64>) -> usize {
+        if self.in_turn(height, authorities).is_some_and(|in_turn| in_turn == signer) { 1 } else { 0 }
+    }
+
+    // 检查签名者能否在父区块之后出块
+    fn check_signer(&self, signer: &str, header: &BlockHeader, context: &ConsensusContext) -> Result<(), String> {
+        let authorities = &context.state.authorities;
+        if !authorities.contains_key(signer) {
+            return Err(format!("{} is not an authorized signer", signer));
+        }
+        if self.signed_recently(signer, header.height, authorities) {
+            return Err(format!("{} signed one of the last {} blocks", signer, authorities.len() / 2));
+        }
+        if header.timestamp < context.parent.timestamp + self.period {
+            return Err(format!("Block {} is too early, the period is {}s", header.height, self.period));
+        }
+        Ok(())
+    }
+}
+
+impl Consensus for ProofOfAuthority {
+    fn prepare(&self, header: &mut BlockHeader, context: &ConsensusContext, signer: Option<&Wallet>) -> Result<(), String> {
+        let signer = signer.ok_or("Proof of authority blocks must be signed by an authorized signer")?.address();
+        self.check_signer(&signer, header, context)?;
+        header.target = self.target(&signer, header.height, &context.state.authorities);
+        header.validator = signer;
+        Ok(())
+    }
+
+    fn seal(&self, block: &mut Block, signer: Option<&Wallet>) -> Result<(), String> {
+        let signer = signer.ok_or("Proof of authority blocks must be signed by an authorized signer")?;
+        sign_block(block, signer);
+        Ok(())
+    }
+
+    fn verify_seal(&self, header: &BlockHeader, hash: &str) -> Result<(), String> {
+        if header.target > 1 {
+            return Err(format!("Invalid target for header {}", header.height));
+        }
+        verify_block_signature(header, hash)
+    }
+
+    fn verify_header(&self, header: &BlockHeader, hash: &str, context: &ConsensusContext) -> Result<(), String> {
+        self.verify_seal(header, hash)?;
+        self.check_signer(&header.validator, header, context)
+            .map_err(|err| format!("Invalid signer for header {}: {}", header.height, err))?;
+        if header.timestamp > Utc::now().timestamp() + self.period {
+            return Err(format!("Header {} is from the future", header.height));
+        }
+        if header.target != self.target(&header.validator, header.height, &context.state.authorities) {
+            return Err(format!("Invalid in-turn target for header {}", header.height));
+        }
+        Ok(())
+    }
+}
+
+// 出块者用钱包私钥签名区块哈希
+fn sign_block(block: &mut Block, signer: &Wallet) {
+    block.hash = block.calculate_hash();
+    block.header.signature = hex::encode(signer.sign(block.hash.as_bytes()));
+}
+
+// 检查区块头中的出块者签名
+fn verify_block_signature(header: &BlockHeader, hash: &str) -> Result<(), String> {
+    if !multisig::verify_signature(&header.validator, hash.as_bytes(), &header.signature) {
+        return Err(format!("Invalid validator signature for header {}", header.height));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::transaction::{Transaction, TxPayload};
+    use crate::utxo::LedgerMode;
+
+    fn genesis() -> Block {
+        Block::new(0, 0, Vec::new(), "0".to_string())
+    }
+
+    fn state(stakes: &[(&str, u64)], authorities: &[(&str, u64)]) -> ConsensusState {
+        ConsensusState {
+            stakes: stakes.iter().map(|(address, stake)| (address.to_string(), *stake)).collect(),
+            authorities: authorities.iter().map(|(address, last)| (address.to_string(), *last)).collect(),
+        }
+    }
+
+    // 在 parent 之后出块：prepare、封装并返回区块
+    fn produce(engine: &dyn Consensus, context: &ConsensusContext, timestamp: i64, signer: &Wallet) -> Result<Block, String> {
+        let mut block = Block::new(context.parent.height + 1, timestamp, Vec::new(), context.parent.calculate_hash());
+        engine.prepare(&mut block.header, context, Some(signer))?;
+        engine.seal(&mut block, Some(signer))?;
+        Ok(block)
+    }
+
+    #[test]
+    fn leaders_are_deterministic_and_stake_weighted() {
+        let pos = ProofOfStake { slot_duration: 10 };
+        let genesis = genesis();
+        let stakes = state(&[("a", 1), ("b", 3)], &[]).stakes;
+        let leaders: Vec<&String> = (0..1000).map(|slot| pos.leader(&genesis.header, slot, &stakes).unwrap()).collect();
+        let b = leaders.iter().filter(|leader| leader.as_str() == "b").count();
+        assert!((650..850).contains(&b), "b led {} of 1000 slots", b);
+        assert_eq!(pos.leader(&genesis.header, 7, &stakes), leaders.get(7).copied());
+        assert_eq!(pos.leader(&genesis.header, 0, &BTreeMap::new()), None);
+
+        assert_eq!(pos.slot(&genesis.header, 25), 2);
+        assert_eq!(pos.slot(&genesis.header, -5), 0);
+        assert_eq!(pos.slot_start(&genesis.header, 2), 20);
+    }
+
+    #[test]
+    fn stake_blocks_are_signed_by_the_slot_leader_once_per_slot() {
+        let pos = ProofOfStake { slot_duration: 10 };
+        let (validator, other) = (Wallet::new(), Wallet::new());
+        let genesis = genesis();
+        let state = state(&[(&validator.address(), 5)], &[]);
+        let context = ConsensusContext { genesis: &genesis.header, parent: &genesis.header, state: &state };
+
+        assert!(produce(&pos, &context, 15, &other).is_err());
+        let block = produce(&pos, &context, 15, &validator).unwrap();
+        assert_eq!(block.header.validator, validator.address());
+        assert!(pos.verify_header(&block.header, &block.hash, &context).is_ok());
+
+        // 篡改区块头或签名、伪造出块者都无法通过验证
+        let mut forged = block.clone();
+        forged.header.timestamp += 1;
+        assert!(pos.verify_header(&forged.header, &forged.calculate_hash(), &context).is_err());
+        let mut forged = block.clone();
+        forged.header.signature = hex::encode(other.sign(block.hash.as_bytes()));
+        assert!(pos.verify_seal(&forged.header, &forged.hash).is_err());
+        let mut forged = block.clone();
+        forged.header.validator = other.address();
+        forged.hash = forged.calculate_hash();
+        forged.header.signature = hex::encode(other.sign(forged.hash.as_bytes()));
+        assert!(pos.verify_seal(&forged.header, &forged.hash).is_ok());
+        assert!(pos.verify_header(&forged.header, &forged.hash, &context).is_err());
+
+        // 同一时隙只能有一个区块，也不能出未来时隙的区块
+        let context = ConsensusContext { genesis: &genesis.header, parent: &block.header, state: &state };
+        assert!(produce(&pos, &context, 19, &validator).is_err());
+        let next = produce(&pos, &context, 20, &validator).unwrap();
+        assert!(pos.verify_header(&next.header, &next.hash, &context).is_ok());
+        let future = produce(&pos, &context, Utc::now().timestamp() + 100, &validator).unwrap();
+        assert!(pos.verify_header(&future.header, &future.hash, &context).is_err());
+    }
+
+    #[test]
+    fn proof_of_stake_chains_need_a_signing_leader() {
+        let validator = Wallet::new();
+        let consensus = ConsensusConfig::ProofOfStake { slot_duration: 5, genesis_stakes: vec![(validator.address(), 10)] };
+        let mut blockchain = Blockchain::with_genesis(1, LedgerMode::Account, Vec::new(), consensus, Vec::new()).unwrap();
+        assert_eq!(blockchain.consensus_state().stakes[&validator.address()], 10);
+        assert!(blockchain.produce_block(Vec::new(), "miner", None).is_err());
+        assert!(blockchain.produce_block(Vec::new(), "miner", Some(&Wallet::new())).is_err());
+        blockchain.produce_block(Vec::new(), &validator.address(), Some(&validator)).unwrap();
+        assert_eq!(blockchain.get_latest_block().header.validator, validator.address());
+        assert!(blockchain.is_chain_valid());
+
+        let invalid = |consensus| Blockchain::with_genesis(1, LedgerMode::Account, Vec::new(), consensus, Vec::new()).is_err();
+        assert!(invalid(ConsensusConfig::ProofOfStake { slot_duration: 0, genesis_stakes: vec![(validator.address(), 10)] }));
+        assert!(invalid(ConsensusConfig::ProofOfStake { slot_duration: 5, genesis_stakes: Vec::new() }));
+        assert!(invalid(ConsensusConfig::ProofOfStake { slot_duration: 5, genesis_stakes: vec![("alice".to_string(), 10)] }));
+        assert!(Blockchain::with_genesis(
+            1,
+            LedgerMode::Utxo,
+            Vec::new(),
+            ConsensusConfig::ProofOfStake { slot_duration: 5, genesis_stakes: vec![(validator.address(), 10)] },
+            Vec::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn authorities_take_turns_and_rest_after_signing() {
+        let poa = ProofOfAuthority { period: 5 };
+        let authorities = state(&[], &[("a", 0), ("b", 2), ("c", 0)]).authorities;
+        let turns: Vec<&str> = (0..4).map(|height| poa.in_turn(height, &authorities).unwrap().as_str()).collect();
+        assert_eq!(turns, ["a", "b", "c", "a"]);
+        assert_eq!(poa.in_turn(1, &BTreeMap::new()), None);
+        // 3 个签名者时，签过名的签名者要隔一个区块才能再签
+        assert!(poa.signed_recently("b", 3, &authorities));
+        assert!(!poa.signed_recently("b", 4, &authorities));
+        assert!(!poa.signed_recently("a", 3, &authorities));
+        assert_eq!((poa.target("c", 2, &authorities), poa.target("a", 2, &authorities)), (1, 0));
+    }
+
+    #[test]
+    fn authority_blocks_follow_turn_period_and_signer_rules() {
+        let poa = ProofOfAuthority { period: 5 };
+        let signers = [Wallet::new(), Wallet::new()];
+        let authorities: Vec<(String, u64)> = signers.iter().map(|signer| (signer.address(), 0)).collect();
+        let state = ConsensusState { stakes: BTreeMap::new(), authorities: authorities.into_iter().collect() };
+        let genesis = genesis();
+        let context = ConsensusContext { genesis: &genesis.header, parent: &genesis.header, state: &state };
+        let in_turn = signers.iter().find(|signer| poa.in_turn(1, &state.authorities) == Some(&signer.address())).unwrap();
+        let out_of_turn = signers.iter().find(|signer| signer.address() != in_turn.address()).unwrap();
+
+        assert!(produce(&poa, &context, 4, in_turn).is_err());
+        assert!(produce(&poa, &context, 5, &Wallet::new()).is_err());
+        let block = produce(&poa, &context, 5, in_turn).unwrap();
+        assert_eq!(block.header.target, 1);
+        assert!(poa.verify_header(&block.header, &block.hash, &context).is_ok());
+        let block = produce(&poa, &context, 5, out_of_turn).unwrap();
+        assert_eq!(block.header.target, 0);
+        assert!(poa.verify_header(&block.header, &block.hash, &context).is_ok());
+
+        // 没轮到的签名者不能声称轮到自己
+        let mut forged = block.clone();
+        forged.header.target = 1;
+        forged.hash = forged.calculate_hash();
+        forged.header.signature = hex::encode(out_of_turn.sign(forged.hash.as_bytes()));
+        assert!(poa.verify_header(&forged.header, &forged.hash, &context).is_err());
+        assert!(block.header.work() < forged.header.work());
+    }
+
+    #[test]
+    fn signers_are_added_by_majority_vote() {
+        let signers = [Wallet::new(), Wallet::new()];
+        let candidate = Wallet::new();
+        let consensus = ConsensusConfig::ProofOfAuthority { period: 0, genesis_signers: signers.iter().map(Wallet::address).collect() };
+        let mut blockchain = Blockchain::with_genesis(1, LedgerMode::Account, Vec::new(), consensus, Vec::new()).unwrap();
+        let vote = |voter: &Wallet, authorize: bool| {
+            let mut tx = Transaction::new_unsigned(voter.address(), String::new(), 0);
+            tx.payload = TxPayload::Vote { candidate: candidate.address(), authorize };
+            tx.sign(&voter.key_pair);
+            tx
+        };
+
+        // 签名者不能连续出块，非签名者不能投票
+        blockchain.produce_block(vec![vote(&signers[0], true)], "miner", Some(&signers[0])).unwrap();
+        assert!(blockchain.produce_block(Vec::new(), "miner", Some(&signers[0])).is_err());
+        assert!(blockchain.add_transaction(vote(&candidate, true)).is_err());
+        assert!(!blockchain.authorities.contains_key(&candidate.address()));
+
+        // 超过半数的签名者投票后加入，断开区块时撤销
+        blockchain.produce_block(vec![vote(&signers[1], true)], "miner", Some(&signers[1])).unwrap();
+        assert!(blockchain.authorities.contains_key(&candidate.address()));
+        assert!(blockchain.authority_votes.is_empty());
+        blockchain.produce_block(Vec::new(), "miner", Some(&candidate)).unwrap();
+        assert!(blockchain.is_chain_valid());
+        blockchain.disconnect_latest_block().unwrap();
+        blockchain.disconnect_latest_block().unwrap();
+        assert!(!blockchain.authorities.contains_key(&candidate.address()));
+        assert_eq!(blockchain.authority_votes.len(), 1);
+
+        // 工作量证明的链不接受投票
+        let mut blockchain = Blockchain::new(1);
+        assert!(blockchain.add_transaction(vote(&signers[0], true)).is_err());
+    }
+}
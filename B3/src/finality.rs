@@ -0,0 +1,677 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use serde::{Serialize, Deserialize};
+use crate::blockchain::Blockchain;
+use crate::consensus::ConsensusConfig;
+use crate::multisig;
+use crate::utxo::LedgerMode;
+use crate::wallet::Wallet;
+
+// BFT 最终性：创世时登记的固定验证者集合按 Tendermint 的轮次协议（提议、预投票、预提交）对某个高度的区块达成共识。
+// 超过 2/3 验证者对同一区块的预提交签名组成提交证书，证书对应的区块及其祖先不能再被回滚；
+// 协议最多容忍 f = (n - 1) / 3 个崩溃或作恶的验证者
+
+pub const MAX_ROUNDS: u32 = 10; // 模拟网络中放弃之前最多进行的轮数
+
+// 达成共识需要的票数：超过 2/3 的验证者
+pub fn quorum(validators: usize) -> usize {
+    validators * 2 / 3 + 1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VoteKind {
+    Prevote,
+    Precommit,
+}
+
+// 验证者在某一轮的签名投票，block_hash 为 None 表示空票（nil）
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Vote {
+    pub kind: VoteKind,
+    pub height: u64,
+    pub round: u32,
+    pub block_hash: Option<String>,
+    pub validator: String,
+    pub signature: String,
+}
+
+impl Vote {
+    // 创建并签名投票
+    pub fn new(kind: VoteKind, height: u64, round: u32, block_hash: Option<String>, wallet: &Wallet) -> Self {
+        let mut vote = Vote { kind, height, round, block_hash, validator: wallet.address(), signature: String::new() };
+        vote.signature = hex::encode(wallet.sign(vote.signed_data().as_bytes()));
+        vote
+    }
+
+    // 签名的内容
+    fn signed_data(&self) -> String {
+        format!("{:?}:{}:{}:{}", self.kind, self.height, self.round, self.block_hash.as_deref().unwrap_or("nil"))
+    }
+
+    pub fn verify(&self) -> bool {
+        multisig::verify_signature(&self.validator, self.signed_data().as_bytes(), &self.signature)
+    }
+}
+
+// 提议者在某一轮提出的区块；valid_round 为提议者此前看到该区块获得超过 2/3 预投票的轮次
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Proposal {
+    pub height: u64,
+    pub round: u32,
+    pub block_hash: String,
+    pub valid_round: Option<u32>,
+    pub proposer: String,
+    pub signature: String,
+}
+
+impl Proposal {
+    // 创建并签名提议
+    pub fn new(height: u64, round: u32, block_hash: String, valid_round: Option<u32>, wallet: &Wallet) -> Self {
+        let mut proposal = Proposal { height, round, block_hash, valid_round, proposer: wallet.address(), signature: String::new() };
+        proposal.signature = hex::encode(wallet.sign(proposal.signed_data().as_bytes()));
+        proposal
+    }
+
+    fn signed_data(&self) -> String {
+        let valid_round = self.valid_round.map(|round| round.to_string()).unwrap_or_else(|| "-1".to_string());
+        format!("Proposal:{}:{}:{}:{}", self.height, self.round, self.block_hash, valid_round)
+    }
+
+    pub fn verify(&self) -> bool {
+        multisig::verify_signature(&self.proposer, self.signed_data().as_bytes(), &self.signature)
+    }
+}
+
+// 提交证书：同一轮中超过 2/3 验证者对区块的预提交
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommitCertificate {
+    pub height: u64,
+    pub round: u32,
+    pub block_hash: String,
+    pub precommits: Vec<Vote>,
+}
+
+impl CommitCertificate {
+    // 检查证书：每个预提交都针对该区块和轮次、来自不同的验证者且签名有效，数量达到 2/3 以上
+    pub fn verify(&self, validators: &[String]) -> Result<(), String> {
+        let mut signers = HashSet::new();
+        for vote in &self.precommits {
+            if vote.kind != VoteKind::Precommit
+                || vote.height != self.height
+                || vote.round != self.round
+                || vote.block_hash.as_ref() != Some(&self.block_hash)
+            {
+                return Err(format!("Precommit from {} does not match the certificate", vote.validator));
+            }
+            if !validators.contains(&vote.validator) || !vote.verify() {
+                return Err(format!("Invalid precommit from {}", vote.validator));
+            }
+            signers.insert(&vote.validator);
+        }
+        if signers.len() < quorum(validators.len()) {
+            return Err(format!(
+                "Certificate for block {} has {} of {} required precommits",
+                self.height,
+                signers.len(),
+                quorum(validators.len())
+            ));
+        }
+        Ok(())
+    }
+}
+
+// 验证者之间广播的消息
+#[derive(Debug, Clone)]
+pub enum Message {
+    Proposal(Proposal),
+    Vote(Vote),
+}
+
+impl Message {
+    fn round(&self) -> u32 {
+        match self {
+            Message::Proposal(proposal) => proposal.round,
+            Message::Vote(vote) => vote.round,
+        }
+    }
+
+    fn sender(&self) -> &str {
+        match self {
+            Message::Proposal(proposal) => &proposal.proposer,
+            Message::Vote(vote) => &vote.validator,
+        }
+    }
+}
+
+// 验证者在当前轮中所处的阶段
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    Propose,
+    Prevote,
+    Precommit,
+}
+
+// 一个验证者在某个高度上的共识状态机
+pub struct FinalityValidator {
+    wallet: Wallet,
+    address: String,
+    validators: Vec<String>,
+    height: u64,
+    candidate: Option<String>, // 本地链在该高度的区块哈希，只为它投赞成票
+    byzantine: bool,           // 作恶的验证者：每次投票都同时为一个伪造的区块投票
+    round: u32,
+    step: Step,
+    locked: Option<(String, u32)>, // 已锁定的区块和锁定时的轮次，之后只为它投赞成票（除非看到更晚轮次的 2/3 预投票）
+    valid: Option<(String, u32)>,  // 最近一次看到获得 2/3 预投票的区块，轮到自己提议时重新提议它
+    proposals: HashMap<u32, Proposal>,
+    votes: HashMap<(VoteKind, u32), HashMap<String, Vote>>,
+    pub decision: Option<CommitCertificate>,
+    pub evidence: Vec<(Vote, Vote)>, // 同一验证者在同一轮对不同区块的两次投票
+}
+
+impl FinalityValidator {
+    pub fn new(wallet: Wallet, validators: Vec<String>, height: u64, candidate: Option<String>, byzantine: bool) -> Self {
+        FinalityValidator {
+            address: wallet.address(),
+            wallet,
+            validators,
+            height,
+            candidate,
+            byzantine,
+            round: 0,
+            step: Step::Propose,
+            locked: None,
+            valid: None,
+            proposals: HashMap::new(),
+            votes: HashMap::new(),
+            decision: None,
+            evidence: Vec::new(),
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    // 某一轮的提议者：按验证者顺序轮换
+    pub fn proposer(&self, round: u32) -> &str {
+        &self.validators[((self.height + round as u64) % self.validators.len() as u64) as usize]
+    }
+
+    // 进入新的一轮，轮到自己时提议已看到获得 2/3 预投票的区块或本地区块
+    pub fn start_round(&mut self, round: u32) -> Vec<Message> {
+        self.round = round;
+        self.step = Step::Propose;
+        if self.proposer(round) != self.address {
+            return Vec::new();
+        }
+        let value = match (&self.valid, &self.candidate) {
+            (Some((hash, valid_round)), _) => Some((hash.clone(), Some(*valid_round))),
+            (None, Some(hash)) => Some((hash.clone(), None)),
+            (None, None) => None,
+        };
+        match value {
+            Some((hash, valid_round)) => vec![Message::Proposal(Proposal::new(self.height, round, hash, valid_round, &self.wallet))],
+            None => Vec::new(),
+        }
+    }
+
+    // 处理收到的消息，返回需要广播的消息
+    pub fn handle(&mut self, message: &Message) -> Vec<Message> {
+        if self.decision.is_some() {
+            return Vec::new();
+        }
+        match message {
+            Message::Proposal(proposal) => {
+                if proposal.height != self.height || proposal.proposer != self.proposer(proposal.round) || !proposal.verify() {
+                    return Vec::new();
+                }
+                self.proposals.entry(proposal.round).or_insert_with(|| proposal.clone());
+            }
+            Message::Vote(vote) => {
+                if vote.height != self.height || !self.validators.contains(&vote.validator) || !vote.verify() {
+                    return Vec::new();
+                }
+                let votes = self.votes.entry((vote.kind, vote.round)).or_default();
+                match votes.get(&vote.validator) {
+                    Some(existing) if existing.block_hash != vote.block_hash => {
+                        self.evidence.push((existing.clone(), vote.clone()));
+                    }
+                    Some(_) => {}
+                    None => {
+                        votes.insert(vote.validator.clone(), vote.clone());
+                    }
+                }
+            }
+        }
+
+        // 看到 f + 1 个验证者已进入更晚的轮次时跟进
+        let mut out = Vec::new();
+        if message.round() > self.round && self.senders_in_round(message.round()) > self.validators.len() - quorum(self.validators.len()) {
+            out.extend(self.start_round(message.round()));
+        }
+        out.extend(self.apply_rules());
+        out
+    }
+
+    // 网络没有新消息时触发当前阶段的超时：提议超时则预投票空票，预投票超时则预提交空票，预提交超时则进入下一轮
+    pub fn on_timeout(&mut self) -> Vec<Message> {
+        if self.decision.is_some() {
+            return Vec::new();
+        }
+        let mut out = match self.step {
+            Step::Propose => {
+                self.step = Step::Prevote;
+                self.cast(VoteKind::Prevote, None)
+            }
+            Step::Prevote => {
+                self.step = Step::Precommit;
+                self.cast(VoteKind::Precommit, None)
+            }
+            Step::Precommit => self.start_round(self.round + 1),
+        };
+        out.extend(self.apply_rules());
+        out
+    }
+
+    // 在某一轮发过消息的不同验证者数量
+    fn senders_in_round(&self, round: u32) -> usize {
+        let mut senders: HashSet<&str> = HashSet::new();
+        if let Some(proposal) = self.proposals.get(&round) {
+            senders.insert(&proposal.proposer);
+        }
+        for kind in [VoteKind::Prevote, VoteKind::Precommit] {
+            if let Some(votes) = self.votes.get(&(kind, round)) {
+                senders.extend(votes.keys().map(String::as_str));
+            }
+        }
+        senders.len()
+    }
+
+    // 某一轮中投给 block_hash 的票数
+    fn count(&self, kind: VoteKind, round: u32, block_hash: Option<&String>) -> usize {
+        self.votes
+            .get(&(kind, round))
+            .map(|votes| votes.values().filter(|vote| vote.block_hash.as_ref() == block_hash).count())
+            .unwrap_or(0)
+    }
+
+    // 签名投票；作恶的验证者同时为伪造的区块投票
+    fn cast(&self, kind: VoteKind, block_hash: Option<String>) -> Vec<Message> {
+        let mut out = Vec::new();
+        if self.byzantine {
+            let forged = hex::encode(self.wallet.sign(format!("{}:{}", self.height, self.round).as_bytes()));
+            out.push(Message::Vote(Vote::new(kind, self.height, self.round, Some(forged), &self.wallet)));
+        }
+        out.push(Message::Vote(Vote::new(kind, self.height, self.round, block_hash, &self.wallet)));
+        out
+    }
+
+    // 按协议规则推进状态，直到没有可以执行的规则
+    fn apply_rules(&mut self) -> Vec<Message> {
+        let mut out = Vec::new();
+        loop {
+            // 任一轮中某个区块获得 2/3 以上的预提交即最终确定
+            let quorum = quorum(self.validators.len());
+            let committed = self.votes.iter().find_map(|((kind, round), votes)| {
+                if *kind != VoteKind::Precommit {
+                    return None;
+                }
+                votes.values().filter_map(|vote| vote.block_hash.as_ref()).find(|hash| self.count(*kind, *round, Some(hash)) >= quorum).map(|hash| (*round, hash.clone()))
+            });
+            if let Some((round, block_hash)) = committed {
+                let precommits = self.votes[&(VoteKind::Precommit, round)]
+                    .values()
+                    .filter(|vote| vote.block_hash.as_ref() == Some(&block_hash))
+                    .cloned()
+                    .collect();
+                self.decision = Some(CommitCertificate { height: self.height, round, block_hash, precommits });
+                return out;
+            }
+
+            let round = self.round;
+            let proposal = self.proposals.get(&round).cloned();
+            match (self.step, proposal) {
+                (Step::Propose, Some(proposal)) => {
+                    let hash = &proposal.block_hash;
+                    let acceptable = match proposal.valid_round {
+                        None => self.locked.as_ref().is_none_or(|(locked, _)| locked == hash),
+                        // 重新提议此前获得 2/3 预投票的区块：需要先看到那一轮的预投票，锁定在更早轮次的验证者可以解锁
+                        Some(valid_round) if valid_round < round => {
+                            if self.count(VoteKind::Prevote, valid_round, Some(hash)) < quorum {
+                                return out;
+                            }
+                            self.locked.as_ref().is_none_or(|(locked, locked_at)| locked == hash || *locked_at <= valid_round)
+                        }
+                        Some(_) => false,
+                    };
+                    let vote = (acceptable && self.candidate.as_ref() == Some(hash)).then(|| hash.clone());
+                    self.step = Step::Prevote;
+                    out.extend(self.cast(VoteKind::Prevote, vote));
+                }
+                (Step::Prevote, proposal) => {
+                    let polka = proposal.map(|proposal| proposal.block_hash).filter(|hash| {
+                        self.candidate.as_ref() == Some(hash) && self.count(VoteKind::Prevote, round, Some(hash)) >= quorum
+                    });
+                    if let Some(hash) = polka {
+                        self.locked = Some((hash.clone(), round));
+                        self.valid = Some((hash.clone(), round));
+                        self.step = Step::Precommit;
+                        out.extend(self.cast(VoteKind::Precommit, Some(hash)));
+                    } else if self.count(VoteKind::Prevote, round, None) >= quorum {
+                        self.step = Step::Precommit;
+                        out.extend(self.cast(VoteKind::Precommit, None));
+                    } else {
+                        return out;
+                    }
+                }
+                _ => return out,
+            }
+        }
+    }
+}
+
+// 进程内的模拟网络：按顺序把每条消息广播给所有在线的验证者（包括发送者自己），
+// 没有消息在传输时触发各验证者的超时；崩溃的验证者不发送也不接收消息
+pub struct SimulatedNetwork {
+    pub validators: Vec<FinalityValidator>,
+    crashed: HashSet<usize>,
+    queue: VecDeque<Message>,
+    pub delivered: usize,
+}
+
+impl SimulatedNetwork {
+    pub fn new(validators: Vec<FinalityValidator>) -> Self {
+        SimulatedNetwork { validators, crashed: HashSet::new(), queue: VecDeque::new(), delivered: 0 }
+    }
+
+    // 让第 index 个验证者崩溃
+    pub fn crash(&mut self, index: usize) {
+        self.crashed.insert(index);
+    }
+
+    fn online(&self) -> Vec<usize> {
+        (0..self.validators.len()).filter(|index| !self.crashed.contains(index)).collect()
+    }
+
+    // 运行协议直到所有在线的验证者都最终确定区块，超过 max_rounds 轮仍未达成时返回错误
+    pub fn run(&mut self, max_rounds: u32) -> Result<CommitCertificate, String> {
+        let online = self.online();
+        for &index in &online {
+            let messages = self.validators[index].start_round(0);
+            self.queue.extend(messages);
+        }
+        loop {
+            while let Some(message) = self.queue.pop_front() {
+                // 崩溃的验证者之前发出的消息也不再传递
+                if online.iter().all(|&index| self.validators[index].address() != message.sender()) {
+                    continue;
+                }
+                for &index in &online {
+                    let messages = self.validators[index].handle(&message);
+                    self.queue.extend(messages);
+                }
+                self.delivered += 1;
+            }
+            if online.iter().all(|&index| self.validators[index].decision.is_some()) {
+                return Ok(self.validators[online[0]].decision.clone().unwrap());
+            }
+            if online.iter().any(|&index| self.validators[index].round() >= max_rounds) {
+                return Err(format!("No finality after {} rounds", max_rounds));
+            }
+            for &index in &online {
+                let messages = self.validators[index].on_timeout();
+                self.queue.extend(messages);
+            }
+        }
+    }
+
+    // 收集到的作恶证据（同一验证者在同一轮的两次不同投票），按验证者去重
+    pub fn equivocators(&self) -> Vec<String> {
+        let mut equivocators: Vec<String> = self.validators
+            .iter()
+            .flat_map(|validator| validator.evidence.iter().map(|(vote, _)| vote.validator.clone()))
+            .collect();
+        equivocators.sort();
+        equivocators.dedup();
+        equivocators
+    }
+}
+
+// 用给定验证者的钱包在模拟网络中对本地链的最新区块运行协议，未提供钱包的验证者视为崩溃
+pub fn finalize_latest_block(blockchain: &Blockchain, wallets: Vec<Wallet>) -> Result<CommitCertificate, String> {
+    let validators = blockchain.finality_validators.clone();
+    if validators.is_empty() {
+        return Err("No finality validators registered in genesis".to_string());
+    }
+    let tip = blockchain.get_latest_block();
+    let mut wallets: HashMap<String, Wallet> = wallets.into_iter().map(|wallet| (wallet.address(), wallet)).collect();
+    if let Some(address) = wallets.keys().find(|address| !validators.contains(address)) {
+        return Err(format!("{} is not a finality validator", address));
+    }
+    let mut network = SimulatedNetwork::new(Vec::new());
+    for address in &validators {
+        let wallet = match wallets.remove(address) {
+            Some(wallet) => wallet,
+            // 崩溃的验证者用临时钱包占位，它不收发任何消息
+            None => {
+                network.crash(network.validators.len());
+                Wallet::new()
+            }
+        };
+        network.validators.push(FinalityValidator::new(wallet, validators.clone(), tip.header.height, Some(tip.hash.clone()), false));
+    }
+    network.run(MAX_ROUNDS)
+}
+
+// 创建 n 个验证者，各自以 candidates[i] 为本地区块
+fn network(wallets: &[Wallet], height: u64, candidates: &[Option<String>], byzantine: Option<usize>) -> SimulatedNetwork {
+    let validators: Vec<String> = wallets.iter().map(Wallet::address).collect();
+    let members = wallets
+        .iter()
+        .enumerate()
+        .map(|(index, wallet)| {
+            FinalityValidator::new(wallet.clone(), validators.clone(), height, candidates[index].clone(), byzantine == Some(index))
+        })
+        .collect();
+    SimulatedNetwork::new(members)
+}
+
+// 最终性演示：4 个验证者（最多容忍 1 个故障）在模拟网络中最终确定区块
+pub fn demo_finality() -> Result<(), String> {
+    let wallets: Vec<Wallet> = (0..4).map(|_| Wallet::new()).collect();
+    let validators: Vec<String> = wallets.iter().map(Wallet::address).collect();
+    let mut blockchain = Blockchain::with_genesis(1, LedgerMode::Account, Vec::new(), ConsensusConfig::ProofOfWork, validators.clone())?;
+    let quorum = quorum(validators.len());
+
+    // 1. 所有验证者在线，第 0 轮最终确定区块 1
+    blockchain.add_block(Vec::new(), &validators[0]);
+    let tip = |blockchain: &Blockchain| (blockchain.get_latest_block().header.height, blockchain.get_latest_block().hash.clone());
+    let (height, hash) = tip(&blockchain);
+    let mut honest = network(&wallets, height, &vec![Some(hash.clone()); 4], None);
+    let certificate = honest.run(MAX_ROUNDS)?;
+    println!("Block {} finalized in round {} with {} precommits ({} messages)", height, certificate.round, certificate.precommits.len(), honest.delivered);
+    blockchain.finalize(certificate)?;
+
+    // 2. 第 0 轮的提议者崩溃：超时后进入第 1 轮，由下一个提议者完成
+    blockchain.add_block(Vec::new(), &validators[1]);
+    blockchain.add_block(Vec::new(), &validators[2]);
+    let (height, hash) = tip(&blockchain);
+    let mut crashed = network(&wallets, height, &vec![Some(hash.clone()); 4], None);
+    let proposer = (height % 4) as usize;
+    crashed.crash(proposer);
+    let certificate = crashed.run(MAX_ROUNDS)?;
+    if certificate.round == 0 {
+        return Err("A crashed proposer must delay finality to a later round".to_string());
+    }
+    println!("Block {} finalized in round {} while the round-0 proposer was down", height, certificate.round);
+
+    // 篡改证书：去掉预提交后不足 2/3 的证书被拒绝
+    let mut forged = certificate.clone();
+    forged.precommits.truncate(quorum - 1);
+    if blockchain.finalize(forged).is_ok() {
+        return Err("A certificate without a quorum must be rejected".to_string());
+    }
+    blockchain.finalize(certificate)?;
+
+    // 3. 一个验证者作恶（同一轮为两个区块投票）：诚实验证者记录证据并照常最终确定
+    blockchain.add_block(Vec::new(), &validators[3]);
+    let (height, hash) = tip(&blockchain);
+    let mut byzantine = network(&wallets, height, &vec![Some(hash.clone()); 4], Some(1));
+    let certificate = byzantine.run(MAX_ROUNDS)?;
+    if certificate.block_hash != hash || byzantine.equivocators() != vec![validators[1].clone()] {
+        return Err("Equivocation must be detected without breaking finality".to_string());
+    }
+    println!("Block {} finalized despite an equivocating validator (evidence against {})", height, &validators[1][..16]);
+    blockchain.finalize(certificate)?;
+
+    // 4. 已最终确定的区块不能回滚，之后的区块仍可以回滚
+    if blockchain.disconnect_latest_block().is_ok() {
+        return Err("Finalized blocks must not be disconnected".to_string());
+    }
+    println!("Disconnecting finalized block {} was refused", height);
+    blockchain.add_block(Vec::new(), &validators[0]);
+    blockchain.disconnect_latest_block()?;
+
+    // 5. 两个验证者崩溃（超过 f）或验证者看到不同的区块时不能最终确定
+    let candidates = vec![Some(hash.clone()); 4];
+    let mut halted = network(&wallets, height + 1, &candidates, None);
+    halted.crash(0);
+    halted.crash(1);
+    if halted.run(3).is_ok() {
+        return Err("Finality needs more than 2/3 of the validators online".to_string());
+    }
+    let forked = vec![Some(hash.clone()), Some(hash.clone()), Some("fork".to_string()), Some("fork".to_string())];
+    if network(&wallets, height + 1, &forked, None).run(3).is_ok() {
+        return Err("Validators split between two blocks must not finalize either".to_string());
+    }
+    println!("No finality with two validators down or with a 2/2 fork, as expected");
+
+    println!("Finalized height: {}", blockchain.finalized_height());
+    if !blockchain.is_chain_valid() {
+        return Err("Chain validation failed".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wallets(count: usize) -> Vec<Wallet> {
+        (0..count).map(|_| Wallet::new()).collect()
+    }
+
+    #[test]
+    fn quorum_is_more_than_two_thirds() {
+        assert_eq!(quorum(1), 1);
+        assert_eq!(quorum(4), 3);
+        assert_eq!(quorum(6), 5);
+        assert_eq!(quorum(7), 5);
+    }
+
+    #[test]
+    fn honest_validators_commit_with_a_quorum() {
+        let wallets = wallets(4);
+        let validators: Vec<String> = wallets.iter().map(Wallet::address).collect();
+        let mut network = network(&wallets, 1, &vec![Some("block".to_string()); 4], None);
+        let certificate = network.run(MAX_ROUNDS).unwrap();
+        assert_eq!((certificate.height, certificate.round), (1, 0));
+        assert_eq!(certificate.block_hash, "block");
+        assert!(certificate.precommits.len() >= quorum(4));
+        assert!(certificate.verify(&validators).is_ok());
+        assert!(network.validators.iter().all(|validator| validator.decision.as_ref().map(|d| &d.block_hash) == Some(&certificate.block_hash)));
+
+        // 预提交不足 2/3 的证书无效
+        let mut forged = certificate.clone();
+        forged.precommits.truncate(quorum(4) - 1);
+        assert!(forged.verify(&validators).is_err());
+    }
+
+    #[test]
+    fn up_to_f_crashed_validators_only_delay_the_commit() {
+        let wallets = wallets(4);
+        let mut network = network(&wallets, 1, &vec![Some("block".to_string()); 4], None);
+        // 高度 1 第 0 轮的提议者是第 1 个验证者
+        network.crash(1);
+        let certificate = network.run(MAX_ROUNDS).unwrap();
+        assert!(certificate.round > 0);
+        assert_eq!(certificate.block_hash, "block");
+    }
+
+    #[test]
+    fn no_commit_with_f_plus_one_validators_offline() {
+        for (count, offline) in [(4, 2), (7, 3)] {
+            let wallets = wallets(count);
+            let mut network = network(&wallets, 1, &vec![Some("block".to_string()); count], None);
+            for index in 0..offline {
+                network.crash(index);
+            }
+            assert!(network.run(3).is_err());
+            assert!(network.validators.iter().all(|validator| validator.decision.is_none()));
+        }
+    }
+
+    #[test]
+    fn equivocating_validator_is_caught_as_evidence() {
+        let wallets = wallets(4);
+        let mut network = network(&wallets, 1, &vec![Some("block".to_string()); 4], Some(2));
+        let certificate = network.run(MAX_ROUNDS).unwrap();
+        assert_eq!(certificate.block_hash, "block");
+        assert_eq!(network.equivocators(), vec![wallets[2].address()]);
+
+        let (first, second) = network.validators[0].evidence.first().unwrap();
+        assert_eq!((&first.validator, first.kind, first.round), (&second.validator, second.kind, second.round));
+        assert_ne!(first.block_hash, second.block_hash);
+        assert!(first.verify() && second.verify());
+    }
+
+    #[test]
+    fn split_validators_do_not_commit() {
+        let wallets = wallets(4);
+        let candidates = vec![Some("a".to_string()), Some("a".to_string()), Some("b".to_string()), Some("b".to_string())];
+        assert!(network(&wallets, 1, &candidates, None).run(3).is_err());
+    }
+
+    #[test]
+    fn finalized_blocks_cannot_be_reorganized() {
+        let wallets = wallets(4);
+        let validators: Vec<String> = wallets.iter().map(Wallet::address).collect();
+        let mut blockchain = Blockchain::with_genesis(1, LedgerMode::Account, Vec::new(), ConsensusConfig::ProofOfWork, validators).unwrap();
+        blockchain.add_block(Vec::new(), "miner");
+        blockchain.add_block(Vec::new(), "miner");
+        let certificate = finalize_latest_block(&blockchain, wallets.clone()).unwrap();
+        blockchain.finalize(certificate.clone()).unwrap();
+        assert_eq!(blockchain.finalized_height(), 2);
+
+        // 已最终确定的区块及其祖先不能断开，之后的区块可以
+        assert!(blockchain.disconnect_latest_block().is_err());
+        blockchain.add_block(Vec::new(), "miner");
+        blockchain.disconnect_latest_block().unwrap();
+        assert!(blockchain.disconnect_latest_block().is_err());
+        assert_eq!(blockchain.chain.len(), 3);
+
+        // 不能重复或向更低的高度最终确定
+        assert!(blockchain.finalize(certificate).is_err());
+        assert!(blockchain.is_chain_valid());
+    }
+
+    #[test]
+    fn finalizing_needs_a_quorum_of_keys() {
+        let wallets = wallets(4);
+        let validators: Vec<String> = wallets.iter().map(Wallet::address).collect();
+        let mut blockchain = Blockchain::with_genesis(1, LedgerMode::Account, Vec::new(), ConsensusConfig::ProofOfWork, validators).unwrap();
+        blockchain.add_block(Vec::new(), "miner");
+        assert!(finalize_latest_block(&blockchain, wallets[..2].to_vec()).is_err());
+        assert!(finalize_latest_block(&blockchain, vec![Wallet::new()]).is_err());
+        assert!(finalize_latest_block(&blockchain, wallets[..3].to_vec()).is_ok());
+    }
+
+    #[test]
+    fn demo_finality_succeeds() {
+        demo_finality().unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use crate::blockchain::{Blockchain, BlockContext};
+use crate::timelock::LockTime;
+use crate::transaction::{Transaction, TxPayload};
+use crate::wallet::Wallet;
+
+// 哈希时间锁的状态
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum HtlcState {
+    Open,
+    Claimed { preimage: String }, // 领取时公开的原像
+    Refunded,
+}
+
+// 哈希时间锁合约：ID 为创建它的交易 ID
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Htlc {
+    pub sender: String,
+    pub receiver: String,
+    pub amount: u64,
+    pub hashlock: String, // 原像的 SHA-256（十六进制）
+    pub timeout: LockTime,
+    pub state: HtlcState,
+}
+
+impl Htlc {
+    // 接收方在超时前提供正确原像即可领取
+    pub fn check_claim(&self, tx: &Transaction, preimage: &str, context: &BlockContext) -> Result<(), String> {
+        if self.state != HtlcState::Open {
+            return Err("HTLC is not open".to_string());
+        }
+        if tx.sender != self.receiver || tx.receiver != self.receiver || tx.amount != self.amount {
+            return Err("Claim must be sent by the HTLC receiver for the locked amount".to_string());
+        }
+        if self.timeout.is_unlocked(context.height, context.timestamp) {
+            return Err("HTLC has timed out".to_string());
+        }
+        if hash_preimage(preimage).as_deref() != Some(self.hashlock.as_str()) {
+            return Err("Preimage does not match hashlock".to_string());
+        }
+        Ok(())
+    }
+
+    // 发送方在超时后可以取回资金
+    pub fn check_refund(&self, tx: &Transaction, context: &BlockContext) -> Result<(), String> {
+        if self.state != HtlcState::Open {
+            return Err("HTLC is not open".to_string());
+        }
+        if tx.sender != self.sender || tx.receiver != self.sender || tx.amount != self.amount {
+            return Err("Refund must be sent by the HTLC sender for the locked amount".to_string());
+        }
+        if !self.timeout.is_unlocked(context.height, context.timestamp) {
+            return Err("HTLC has not timed out yet".to_string());
+        }
+        Ok(())
+    }
+}
+
+// 计算十六进制原像的 SHA-256，原像不是合法十六进制时返回 None
+pub fn hash_preimage(preimage: &str) -> Option<String> {
+    let bytes = hex::decode(preimage).ok()?;
+    Some(hex::encode(Sha256::digest(&bytes)))
+}
+
+// 生成随机原像及其哈希
+pub fn generate_secret() -> (String, String) {
+    let secret: [u8; 32] = rand::random();
+    let preimage = hex::encode(secret);
+    let hashlock = hash_preimage(&preimage).unwrap();
+    (preimage, hashlock)
+}
+
+// 创建锁定交易（未签名）
+pub fn lock_transaction(sender: &str, receiver: &str, amount: u64, hashlock: &str, timeout: LockTime) -> Transaction {
+    let mut tx = Transaction::new_unsigned(sender.to_string(), receiver.to_string(), amount);
+    tx.payload = TxPayload::HtlcLock { hashlock: hashlock.to_string(), timeout };
+    tx
+}
+
+// 创建领取交易（未签名）
+pub fn claim_transaction(htlc_id: &str, htlc: &Htlc, preimage: &str) -> Transaction {
+    let mut tx = Transaction::new_unsigned(htlc.receiver.clone(), htlc.receiver.clone(), htlc.amount);
+    tx.payload = TxPayload::HtlcClaim { htlc_id: htlc_id.to_string(), preimage: preimage.to_string() };
+    tx
+}
+
+// 创建退回交易（未签名）
+pub fn refund_transaction(htlc_id: &str, htlc: &Htlc) -> Transaction {
+    let mut tx = Transaction::new_unsigned(htlc.sender.clone(), htlc.sender.clone(), htlc.amount);
+    tx.payload = TxPayload::HtlcRefund { htlc_id: htlc_id.to_string() };
+    tx
+}
+
+// 签名交易、放入交易池并打包出块
+fn submit(blockchain: &mut Blockchain, mut tx: Transaction, wallet: &Wallet, miner: &str) -> Result<String, String> {
+    tx.sign(&wallet.key_pair);
+    let id = tx.id();
+    blockchain.add_transaction(tx)?;
+    let transactions = std::mem::take(&mut blockchain.pending_transactions);
+    blockchain.produce_block(transactions, miner, None)?;
+    Ok(id)
+}
+
+// 原子交换演示：Alice 用链 A 上的 100 个币交换 Bob 在链 B 上的 50 个币
+pub fn demo_atomic_swap() -> Result<(), String> {
+    let alice = Wallet::new();
+    let bob = Wallet::new();
+    let mut chain_a = Blockchain::new(2);
+    let mut chain_b = Blockchain::new(2);
+    // 双方的资金来自挖矿奖励：Alice 在链 A 上挖两个区块，Bob 在链 B 上挖一个区块
+    chain_a.produce_block(Vec::new(), &alice.address(), None)?;
+    chain_a.produce_block(Vec::new(), &alice.address(), None)?;
+    chain_b.produce_block(Vec::new(), &bob.address(), None)?;
+
+    // 1. Alice 生成秘密原像，在链 A 上锁定资金给 Bob（较长的超时）
+    let (preimage, hashlock) = generate_secret();
+    let timeout_a = LockTime::Height(chain_a.get_latest_block().header.height + 10);
+    let lock_a = lock_transaction(&alice.address(), &bob.address(), 100, &hashlock, timeout_a);
+    let htlc_a = submit(&mut chain_a, lock_a, &alice, "miner-a")?;
+    println!("Chain A: Alice locked 100 in HTLC {}", htlc_a);
+
+    // 2. Bob 看到链 A 上的锁定后，用相同的哈希在链 B 上锁定资金给 Alice（较短的超时）
+    let hashlock_seen = chain_a.htlcs.get(&htlc_a).ok_or("HTLC not found on chain A")?.hashlock.clone();
+    let timeout_b = LockTime::Height(chain_b.get_latest_block().header.height + 5);
+    let lock_b = lock_transaction(&bob.address(), &alice.address(), 50, &hashlock_seen, timeout_b);
+    let htlc_b = submit(&mut chain_b, lock_b, &bob, "miner-b")?;
+    println!("Chain B: Bob locked 50 in HTLC {}", htlc_b);
+
+    // 超时之前 Bob 不能取回资金
+    let mut early_refund = refund_transaction(&htlc_b, &chain_b.htlcs[&htlc_b]);
+    early_refund.sign(&bob.key_pair);
+    if chain_b.add_transaction(early_refund).is_ok() {
+        return Err("Refund before timeout must be rejected".to_string());
+    }
+    println!("Chain B: early refund rejected as expected");
+
+    // 3. Alice 在链 B 上提供原像领取资金，原像因此公开
+    let claim_b = claim_transaction(&htlc_b, &chain_b.htlcs[&htlc_b], &preimage);
+    submit(&mut chain_b, claim_b, &alice, "miner-b")?;
+    println!("Chain B: Alice claimed 50 by revealing the preimage");
+
+    // 4. Bob 从链 B 读取公开的原像，在链 A 上领取资金
+    let revealed = match &chain_b.htlcs[&htlc_b].state {
+        HtlcState::Claimed { preimage } => preimage.clone(),
+        state => return Err(format!("Unexpected HTLC state on chain B: {:?}", state)),
+    };
+    let claim_a = claim_transaction(&htlc_a, &chain_a.htlcs[&htlc_a], &revealed);
+    submit(&mut chain_a, claim_a, &bob, "miner-a")?;
+    println!("Chain A: Bob claimed 100 with the revealed preimage");
+
+    // 已领取的 HTLC 不能再次领取
+    let mut second_claim = claim_transaction(&htlc_a, &chain_a.htlcs[&htlc_a], &revealed);
+    second_claim.sign(&bob.key_pair);
+    if chain_a.add_transaction(second_claim).is_ok() {
+        return Err("A second claim of the same HTLC must be rejected".to_string());
+    }
+
+    let (alice_b, bob_a) = (chain_b.get_balance(&alice.address()), chain_a.get_balance(&bob.address()));
+    let (alice_a, bob_b) = (chain_a.get_balance(&alice.address()), chain_b.get_balance(&bob.address()));
+    println!("Alice: chain A {}, chain B {}", alice_a, alice_b);
+    println!("Bob:   chain A {}, chain B {}", bob_a, bob_b);
+    if (alice_a, alice_b, bob_a, bob_b) != (0, 50, 100, 0) {
+        return Err("Unexpected balances after swap".to_string());
+    }
+
+    // 5. 无人领取的 HTLC 在超时后由发送方取回
+    let (_, unused_hashlock) = generate_secret();
+    let timeout = LockTime::Height(chain_b.get_latest_block().header.height + 2);
+    let lock = lock_transaction(&alice.address(), &bob.address(), 20, &unused_hashlock, timeout);
+    let htlc_id = submit(&mut chain_b, lock, &alice, "miner-b")?;
+    chain_b.add_block(Vec::new(), "miner-b");
+    let refund = refund_transaction(&htlc_id, &chain_b.htlcs[&htlc_id]);
+    submit(&mut chain_b, refund, &alice, "miner-b")?;
+    if chain_b.get_balance(&alice.address()) != 50 || chain_b.htlcs[&htlc_id].state != HtlcState::Refunded {
+        return Err("Refund after timeout failed".to_string());
+    }
+    println!("Chain B: Alice refunded an unclaimed HTLC after its timeout");
+
+    if !chain_a.is_chain_valid() || !chain_b.is_chain_valid() {
+        return Err("Chain validation failed after swap".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 签名并打包一笔交易，返回交易 ID
+    fn mine(blockchain: &mut Blockchain, tx: Transaction, wallet: &Wallet) -> String {
+        submit(blockchain, tx, wallet, "miner").unwrap()
+    }
+
+    fn signed(mut tx: Transaction, wallet: &Wallet) -> Transaction {
+        tx.sign(&wallet.key_pair);
+        tx
+    }
+
+    // 难度为 1 的链，owner 通过挖矿奖励获得 blocks * 50 的余额
+    fn chain_funding(owner: &Wallet, blocks: usize) -> Blockchain {
+        let mut blockchain = Blockchain::new(1);
+        for _ in 0..blocks {
+            blockchain.add_block(Vec::new(), &owner.address());
+        }
+        blockchain
+    }
+
+    #[test]
+    fn atomic_swap_across_two_chains() {
+        let (alice, bob) = (Wallet::new(), Wallet::new());
+        let mut chain_a = chain_funding(&alice, 2);
+        let mut chain_b = chain_funding(&bob, 1);
+        let (preimage, hashlock) = generate_secret();
+
+        let timeout_a = LockTime::Height(chain_a.get_latest_block().header.height + 10);
+        let htlc_a = mine(&mut chain_a, lock_transaction(&alice.address(), &bob.address(), 100, &hashlock, timeout_a), &alice);
+        let timeout_b = LockTime::Height(chain_b.get_latest_block().header.height + 5);
+        let htlc_b = mine(&mut chain_b, lock_transaction(&bob.address(), &alice.address(), 50, &hashlock, timeout_b), &bob);
+        assert_eq!(chain_a.get_balance(&alice.address()), 0);
+        assert_eq!(chain_b.get_balance(&bob.address()), 0);
+
+        // 错误的原像和非接收方的领取都被拒绝
+        let (wrong, _) = generate_secret();
+        assert!(chain_b.add_transaction(signed(claim_transaction(&htlc_b, &chain_b.htlcs[&htlc_b], &wrong), &alice)).is_err());
+        assert!(chain_b.add_transaction(signed(claim_transaction(&htlc_b, &chain_b.htlcs[&htlc_b], &preimage), &bob)).is_err());
+
+        // Alice 在链 B 上领取时公开原像，Bob 用它在链 A 上领取
+        let claim_b = claim_transaction(&htlc_b, &chain_b.htlcs[&htlc_b], &preimage);
+        mine(&mut chain_b, claim_b, &alice);
+        let HtlcState::Claimed { preimage: revealed } = chain_b.htlcs[&htlc_b].state.clone() else {
+            panic!("HTLC on chain B was not claimed");
+        };
+        let claim_a = claim_transaction(&htlc_a, &chain_a.htlcs[&htlc_a], &revealed);
+        mine(&mut chain_a, claim_a, &bob);
+
+        assert_eq!(chain_a.get_balance(&bob.address()), 100);
+        assert_eq!(chain_b.get_balance(&alice.address()), 50);
+        assert_eq!(chain_a.get_balance(&alice.address()), 0);
+        assert_eq!(chain_b.get_balance(&bob.address()), 0);
+
+        // 已领取的 HTLC 不能再次领取，也不能退回
+        let second_claim = signed(claim_transaction(&htlc_a, &chain_a.htlcs[&htlc_a], &revealed), &bob);
+        assert!(chain_a.add_transaction(second_claim.clone()).is_err());
+        assert!(chain_a.produce_block(vec![second_claim], "miner", None).is_err());
+        assert_eq!(chain_a.get_balance(&bob.address()), 100);
+        assert!(chain_a.is_chain_valid() && chain_b.is_chain_valid());
+    }
+
+    #[test]
+    fn duplicate_claims_are_rejected_in_the_pool_and_in_a_block() {
+        let (alice, bob) = (Wallet::new(), Wallet::new());
+        let mut blockchain = chain_funding(&alice, 2);
+        let (preimage, hashlock) = generate_secret();
+        let timeout = LockTime::Height(blockchain.get_latest_block().header.height + 10);
+        let htlc_id = mine(&mut blockchain, lock_transaction(&alice.address(), &bob.address(), 100, &hashlock, timeout), &alice);
+
+        // 两笔内容相同但 nonce 不同的领取交易
+        let htlc = blockchain.htlcs[&htlc_id].clone();
+        let first = signed(claim_transaction(&htlc_id, &htlc, &preimage), &bob);
+        let second = signed(claim_transaction(&htlc_id, &htlc, &preimage), &bob);
+        assert_ne!(first.id(), second.id());
+
+        blockchain.add_transaction(first.clone()).unwrap();
+        assert!(blockchain.add_transaction(second.clone()).is_err());
+        assert!(blockchain.produce_block(vec![first.clone(), second], "miner", None).is_err());
+        assert_eq!(blockchain.get_balance(&bob.address()), 0);
+
+        blockchain.produce_block(vec![first], "miner", None).unwrap();
+        assert_eq!(blockchain.get_balance(&bob.address()), 100);
+        assert!(blockchain.is_chain_valid());
+    }
+
+    #[test]
+    fn refund_only_after_the_timeout_and_only_once() {
+        let (alice, bob) = (Wallet::new(), Wallet::new());
+        let mut blockchain = chain_funding(&alice, 1);
+        let (preimage, hashlock) = generate_secret();
+        let timeout = LockTime::Height(blockchain.get_latest_block().header.height + 3);
+        let htlc_id = mine(&mut blockchain, lock_transaction(&alice.address(), &bob.address(), 20, &hashlock, timeout), &alice);
+        let htlc = blockchain.htlcs[&htlc_id].clone();
+
+        assert!(blockchain.add_transaction(signed(refund_transaction(&htlc_id, &htlc), &alice)).is_err());
+        blockchain.add_block(Vec::new(), "miner");
+        // 到达超时高度后不能再领取，发送方可以退回一次
+        assert!(blockchain.add_transaction(signed(claim_transaction(&htlc_id, &htlc, &preimage), &bob)).is_err());
+        mine(&mut blockchain, refund_transaction(&htlc_id, &htlc), &alice);
+        assert_eq!(blockchain.htlcs[&htlc_id].state, HtlcState::Refunded);
+        assert_eq!(blockchain.get_balance(&alice.address()), 50);
+        assert!(blockchain.add_transaction(signed(refund_transaction(&htlc_id, &htlc), &alice)).is_err());
+    }
+
+    #[test]
+    fn demo_atomic_swap_succeeds() {
+        demo_atomic_swap().unwrap();
+    }
+}
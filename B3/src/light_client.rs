@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::io::{self, Write, Read};
+use serde::{Serialize, Deserialize};
+use crate::block::{self, BlockHeader};
+use crate::consensus::{Consensus, ProofOfWork};
+use crate::merkle_tree::MerkleProof;
+use crate::transaction::Transaction;
+
+// 轻客户端（SPV）：只下载并验证区块头（哈希链接和工作量证明，只支持工作量证明共识的链），
+// 通过默克尔证明对照区块头中的 merkle_root 验证交易是否被打包，不保存交易和状态
+
+// 交易的包含证明：交易本身、所在区块和对该区块 merkle_root 的默克尔证明
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TxProof {
+    pub block_index: u64,
+    pub block_hash: String,
+    pub transaction: Transaction,
+    pub merkle_proof: MerkleProof,
+}
+
+// 轻客户端保存的区块头链，第一个区块头（创世区块）是信任的起点
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LightClient {
+    pub difficulty: usize,
+    pub headers: Vec<BlockHeader>,
+}
+
+impl LightClient {
+    // 以创世区块头为起点创建轻客户端
+    pub fn new(genesis: BlockHeader, difficulty: usize) -> Result<Self, String> {
+        if genesis.height != 0 {
+            return Err("Invalid genesis header".to_string());
+        }
+        Ok(LightClient { difficulty, headers: vec![genesis] })
+    }
+
+    // 最新的区块头
+    pub fn tip(&self) -> &BlockHeader {
+        self.headers.last().unwrap()
+    }
+
+    // 同步一段连续的区块头，返回新接入的区块头数量。
+    // 已有的区块头会被跳过；与已有区块头冲突的分叉只有在更长（工作量更多）时才替换当前链
+    pub fn sync_headers(&mut self, headers: &[BlockHeader]) -> Result<usize, String> {
+        let Some(first) = headers.first() else {
+            return Ok(0);
+        };
+        let tip_height = self.tip().height;
+        if first.height == 0 || first.height > tip_height + 1 {
+            return Err(format!("Header {} does not connect to the synced chain (tip {})", first.height, tip_height));
+        }
+        let mut previous = &self.headers[first.height as usize - 1];
+        let mut previous_hash = previous.calculate_hash();
+        let pow = ProofOfWork { difficulty: self.difficulty };
+        for header in headers {
+            previous_hash = header.validate(previous, &previous_hash)?;
+            pow.verify_seal(header, &previous_hash)?;
+            previous = header;
+        }
+
+        let known = headers
+            .iter()
+            .take_while(|header| self.headers.get(header.height as usize) == Some(*header))
+            .count();
+        let new_headers = &headers[known..];
+        let Some(fork) = new_headers.first() else {
+            return Ok(0);
+        };
+        let work = |headers: &[BlockHeader]| headers.iter().map(BlockHeader::work).sum::<u128>();
+        if fork.height <= tip_height {
+            if work(new_headers) <= work(&self.headers[fork.height as usize..]) {
+                return Err(format!("Competing branch at header {} does not have more work", fork.height));
+            }
+            println!("Reorganizing light client chain from header {}", fork.height);
+        }
+        self.headers.truncate(fork.height as usize);
+        self.headers.extend_from_slice(new_headers);
+        Ok(new_headers.len())
+    }
+
+    // 验证交易包含证明，返回交易所在区块的确认数
+    pub fn verify_transaction(&self, proof: &TxProof) -> Result<u64, String> {
+        let header = self
+            .headers
+            .get(proof.block_index as usize)
+            .filter(|header| header.calculate_hash() == proof.block_hash)
+            .ok_or_else(|| format!("Block {} is not in the synced header chain", proof.block_hash))?;
+        if !proof.merkle_proof.verify(&block::merkle_leaf(&proof.transaction), &header.merkle_root) {
+            return Err("Merkle proof does not match the block's merkle root".to_string());
+        }
+        Ok(self.tip().height - header.height + 1)
+    }
+
+    // 将轻客户端保存到文件
+    pub fn save_to_file(&self, filename: &str) -> io::Result<()> {
+        let data = serde_json::to_string(self)?;
+        let mut file = File::create(filename)?;
+        file.write_all(data.as_bytes())?;
+        Ok(())
+    }
+
+    // 从文件加载轻客户端
+    pub fn load_from_file(filename: &str) -> io::Result<Self> {
+        let mut file = File::open(filename)?;
+        let mut data = String::new();
+        file.read_to_string(&mut data)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::timelock::LockTime;
+    use crate::wallet::Wallet;
+
+    #[test]
+    fn proofs_commit_to_the_full_transaction() {
+        let alice = Wallet::new();
+        let mut blockchain = Blockchain::new(1);
+        blockchain.enable_tx_index();
+        blockchain.add_block(Vec::new(), &alice.address());
+        let tx = Transaction::new(alice.address(), "bob".to_string(), 30, &alice.key_pair);
+        blockchain.produce_block(vec![tx.clone()], "miner", None).unwrap();
+
+        let mut client = LightClient::new(blockchain.get_headers(0)[0].clone(), blockchain.difficulty).unwrap();
+        assert_eq!(client.sync_headers(&blockchain.get_headers(1)).unwrap(), 2);
+        let proof = blockchain.get_tx_proof(&tx.id()).unwrap();
+        assert_eq!(client.verify_transaction(&proof), Ok(1));
+
+        // 交易的显示形式不包含的字段被篡改后同样无法通过验证
+        let mut tampered = proof.clone();
+        tampered.transaction.lock_until = Some(LockTime::Height(100));
+        assert!(client.verify_transaction(&tampered).is_err());
+        let mut tampered = proof;
+        tampered.transaction.signature.clear();
+        assert!(client.verify_transaction(&tampered).is_err());
+    }
+}
@@ -7,6 +7,23 @@ mod cli;
 mod wallet;
 mod smart_contract; 
 mod privacy; 
+mod subscription;
+mod tx_index;
+mod multisig;
+mod utxo;
+mod timelock;
+mod htlc;
+mod vm;
+mod wasm_runtime;
+mod receipt;
+mod token;
+mod asset;
+mod state_tree;
+mod shielded_pool;
+mod confidential;
+mod light_client;
+mod consensus;
+mod finality;
 
 use cli::Cli;
 use clap::Parser;
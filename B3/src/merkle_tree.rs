@@ -1,6 +1,11 @@
 use sha2::{Sha256, Digest};
+use serde::{Serialize, Deserialize};
 use std::fmt;
 
+// 叶子与内部节点的哈希使用不同的前缀，防止把内部节点伪装成叶子
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
 #[derive(Debug)]
 pub struct MerkleTree {
     root: Option<MerkleNode>,
@@ -65,9 +70,39 @@ impl MerkleTree {
         self.root.as_ref().map(|node| node.hash.clone())
     }
 
+    // 生成第 index 个叶子的默克尔证明（从叶子向上的兄弟节点），index 需小于叶子数
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        let root = self.root.as_ref()?;
+        // 树是满二叉树（奇数个节点时复制最后一个），深度即最左路径的长度
+        let mut depth = 0;
+        let mut node = root;
+        while let Some(left) = &node.left {
+            depth += 1;
+            node = left;
+        }
+        if index >> depth != 0 {
+            return None;
+        }
+        let mut siblings = Vec::with_capacity(depth);
+        let mut node = root;
+        for level in (0..depth).rev() {
+            let (left, right) = (node.left.as_ref()?, node.right.as_ref()?);
+            if (index >> level) & 1 == 0 {
+                siblings.push(hex::encode(&right.hash));
+                node = left;
+            } else {
+                siblings.push(hex::encode(&left.hash));
+                node = right;
+            }
+        }
+        siblings.reverse();
+        Some(MerkleProof { index, siblings })
+    }
+
     // 计算叶子节点的哈希
     fn hash_leaf(data: &str) -> Vec<u8> {
         let mut hasher = Sha256::new();
+        hasher.update([LEAF_PREFIX]);
         hasher.update(data.as_bytes());
         hasher.finalize().to_vec()
     }
@@ -75,12 +110,41 @@ impl MerkleTree {
     // 计算两个节点的哈希
     fn hash_nodes(left: &[u8], right: &[u8]) -> Vec<u8> {
         let mut hasher = Sha256::new();
+        hasher.update([NODE_PREFIX]);
         hasher.update(left);
         hasher.update(right);
         hasher.finalize().to_vec()
     }
 }
 
+// 默克尔证明：叶子的序号和从叶子向上的兄弟节点哈希（十六进制）
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub siblings: Vec<String>,
+}
+
+impl MerkleProof {
+    // 由叶子数据和证明重新计算根哈希，并与给定的根哈希（十六进制）比较
+    pub fn verify(&self, data: &str, root: &str) -> bool {
+        if self.index >> self.siblings.len() != 0 {
+            return false;
+        }
+        let mut hash = MerkleTree::hash_leaf(data);
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            let Ok(sibling) = hex::decode(sibling) else {
+                return false;
+            };
+            hash = if (self.index >> level) & 1 == 0 {
+                MerkleTree::hash_nodes(&hash, &sibling)
+            } else {
+                MerkleTree::hash_nodes(&sibling, &hash)
+            };
+        }
+        hex::encode(hash) == root
+    }
+}
+
 impl fmt::Display for MerkleTree {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(root) = &self.root {
@@ -90,3 +154,33 @@ impl fmt::Display for MerkleTree {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("tx{}", i)).collect()
+    }
+
+    #[test]
+    fn proofs_verify_for_every_leaf() {
+        for count in 1..=7 {
+            let tree = MerkleTree::new(leaves(count));
+            let root = hex::encode(tree.root_hash().unwrap());
+            for (index, leaf) in leaves(count).iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert!(proof.verify(leaf, &root));
+                assert!(!proof.verify("other", &root));
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_and_internal_nodes_hash_differently() {
+        // 没有前缀时，叶子 "abcd" 与子节点为 "ab"、"cd" 的内部节点哈希相同
+        assert_ne!(MerkleTree::hash_leaf("abcd"), MerkleTree::hash_nodes(b"ab", b"cd"));
+        assert_eq!(MerkleTree::new(leaves(1)).root_hash().unwrap(), MerkleTree::hash_leaf("tx0"));
+        assert_ne!(MerkleTree::new(leaves(2)).root_hash(), MerkleTree::new(vec!["tx0tx1".to_string()]).root_hash());
+    }
+}
@@ -0,0 +1,168 @@
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+// 多签账户：由一组公钥和签名门限定义（M-of-N）
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MultisigAccount {
+    pub public_keys: Vec<String>, // 参与者公钥（十六进制）
+    pub threshold: usize,         // 至少需要的有效签名数
+}
+
+// 多签交易中单个参与者的签名
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PartialSignature {
+    pub public_key: String,
+    pub signature: String,
+}
+
+impl MultisigAccount {
+    // 创建多签账户，公钥会被排序以保证地址唯一
+    pub fn new(mut public_keys: Vec<String>, threshold: usize) -> Result<Self, String> {
+        public_keys.sort();
+        public_keys.dedup();
+        if threshold == 0 || threshold > public_keys.len() {
+            return Err(format!("Invalid threshold {} for {} distinct keys", threshold, public_keys.len()));
+        }
+        for key in &public_keys {
+            match hex::decode(key) {
+                Ok(bytes) if bytes.len() == 32 => {}
+                _ => return Err(format!("Invalid Ed25519 public key: {}", key)),
+            }
+        }
+        Ok(MultisigAccount { public_keys, threshold })
+    }
+
+    // 多签地址：以 "ms" 开头，后接账户定义的 SHA-256 哈希
+    pub fn address(&self) -> String {
+        let definition = format!("multisig:{}:{}", self.threshold, self.public_keys.join(","));
+        format!("ms{}", hex::encode(Sha256::digest(definition.as_bytes())))
+    }
+
+    // 统计对消息有效的、属于本账户的不同签名者数量
+    pub fn count_valid_signers(&self, message: &[u8], signatures: &[PartialSignature]) -> usize {
+        let mut signers: Vec<&str> = Vec::new();
+        for partial in signatures {
+            if !self.public_keys.contains(&partial.public_key) || signers.contains(&partial.public_key.as_str()) {
+                continue;
+            }
+            if verify_signature(&partial.public_key, message, &partial.signature) {
+                signers.push(&partial.public_key);
+            }
+        }
+        signers.len()
+    }
+
+    // 是否满足签名门限
+    pub fn is_authorized(&self, message: &[u8], signatures: &[PartialSignature]) -> bool {
+        self.count_valid_signers(message, signatures) >= self.threshold
+    }
+}
+
+// 使用十六进制公钥验证 Ed25519 签名
+pub fn verify_signature(public_key: &str, message: &[u8], signature: &str) -> bool {
+    match (hex::decode(public_key), hex::decode(signature)) {
+        (Ok(public_key_bytes), Ok(signature_bytes)) => {
+            let public_key = ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &public_key_bytes);
+            public_key.verify(message, &signature_bytes).is_ok()
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::transaction::Transaction;
+    use crate::wallet::Wallet;
+
+    fn signers(count: usize) -> (Vec<Wallet>, Vec<String>) {
+        let wallets: Vec<Wallet> = (0..count).map(|_| Wallet::new()).collect();
+        let keys = wallets.iter().map(Wallet::address).collect();
+        (wallets, keys)
+    }
+
+    #[test]
+    fn address_does_not_depend_on_key_order() {
+        let (_, keys) = signers(3);
+        let mut reversed = keys.clone();
+        reversed.reverse();
+        let account = MultisigAccount::new(keys.clone(), 2).unwrap();
+        assert_eq!(account.address(), MultisigAccount::new(reversed, 2).unwrap().address());
+        assert_ne!(account.address(), MultisigAccount::new(keys.clone(), 3).unwrap().address());
+        assert!(account.address().starts_with("ms"));
+
+        assert!(MultisigAccount::new(keys.clone(), 0).is_err());
+        assert!(MultisigAccount::new(keys.clone(), 4).is_err());
+        // 重复的公钥只计一次
+        assert!(MultisigAccount::new(vec![keys[0].clone(), keys[0].clone()], 2).is_err());
+        assert!(MultisigAccount::new(vec!["abcd".to_string()], 1).is_err());
+    }
+
+    #[test]
+    fn spending_needs_the_threshold_of_distinct_signers() {
+        let (wallets, keys) = signers(3);
+        let outsider = Wallet::new();
+        let account = MultisigAccount::new(keys, 2).unwrap();
+        let address = account.address();
+        let mut blockchain = Blockchain::new(1);
+        blockchain.add_block(Vec::new(), &address);
+
+        // 各参与者在自己的副本上签名，再合并
+        let mut unsigned = Transaction::new_unsigned(address.clone(), "bob".to_string(), 20);
+        let id = unsigned.id();
+        unsigned.multisig = Some(account.clone());
+        assert_eq!(unsigned.id(), id);
+        let mut first = unsigned.clone();
+        first.add_partial_signature(&wallets[0].key_pair);
+        first.add_partial_signature(&wallets[0].key_pair);
+        first.add_partial_signature(&outsider.key_pair);
+        assert_eq!(first.signatures.len(), 2);
+        assert!(blockchain.add_transaction(first.clone()).is_err());
+
+        let mut second = unsigned.clone();
+        second.add_partial_signature(&wallets[2].key_pair);
+        first.merge_signatures(&second).unwrap();
+        let mut other = Transaction::new_unsigned(address.clone(), "carol".to_string(), 20);
+        assert!(other.merge_signatures(&second).is_err());
+
+        blockchain.produce_block(vec![first], "miner", None).unwrap();
+        assert_eq!(blockchain.get_balance("bob"), 20);
+        assert!(blockchain.is_chain_valid());
+
+        // 普通的单签名不能花费多签账户的资金
+        other.sign(&wallets[1].key_pair);
+        assert!(blockchain.add_transaction(other).is_err());
+    }
+
+    #[test]
+    fn the_carried_account_must_hash_to_the_sender() {
+        let (wallets, keys) = signers(2);
+        let account = MultisigAccount::new(keys.clone(), 1).unwrap();
+        let mut blockchain = Blockchain::new(1);
+        blockchain.add_block(Vec::new(), &account.address());
+
+        // 不携带账户定义时无法验证
+        let mut tx = Transaction::new_unsigned(account.address(), "bob".to_string(), 20);
+        tx.add_partial_signature(&wallets[0].key_pair);
+        assert!(!tx.verify_multisig());
+
+        // 门限不同的定义对应另一个地址
+        tx.multisig = Some(MultisigAccount::new(keys.clone(), 2).unwrap());
+        assert!(!tx.verify_multisig());
+
+        // 未排序的定义不是规范形式
+        let mut reversed = keys.clone();
+        reversed.sort();
+        reversed.reverse();
+        tx.multisig = Some(MultisigAccount { public_keys: reversed, threshold: 1 });
+        assert!(!tx.verify_multisig());
+        assert!(blockchain.add_transaction(tx.clone()).is_err());
+
+        // 携带正确定义的交易可以被没有任何登记信息的节点接受
+        tx.multisig = Some(account);
+        assert!(tx.verify_multisig());
+        let raw = tx.to_raw();
+        blockchain.add_transaction(Transaction::from_raw(&raw).unwrap()).unwrap();
+    }
+}
@@ -1,9 +1,24 @@
-use std::net::SocketAddr;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{accept, Message, WebSocket};
+use crate::block::{Block, BlockHeader};
 use crate::blockchain::Blockchain;
+use crate::finality::CommitCertificate;
+use crate::subscription::{EventBus, Subscription};
+use crate::transaction::Transaction;
+
+const MAX_HEADERS_PER_REQUEST: usize = 500; // 每次 getHeaders 请求最多返回的区块头数
+const BODIES_PER_REQUEST: usize = 50;       // 每次 getBlockBodies 请求的区块体数
 
 #[derive(Debug, Clone)]
 pub struct Node {
+    #[allow(dead_code)] // 节点自身的地址，对等节点发现尚未实现
     pub address: SocketAddr,
     pub blockchain: Arc<Mutex<Blockchain>>,
     pub peers: Vec<SocketAddr>,
@@ -11,10 +26,16 @@ pub struct Node {
 
 impl Node {
     // 创建一个新节点
+    #[allow(dead_code)]
     pub fn new(address: SocketAddr, difficulty: usize) -> Self {
+        Self::with_blockchain(address, Blockchain::new(difficulty))
+    }
+
+    // 使用已有的区块链创建节点
+    pub fn with_blockchain(address: SocketAddr, blockchain: Blockchain) -> Self {
         Node {
             address,
-            blockchain: Arc::new(Mutex::new(Blockchain::new(difficulty))),
+            blockchain: Arc::new(Mutex::new(blockchain)),
             peers: Vec::new(),
         }
     }
@@ -24,10 +45,447 @@ impl Node {
         self.peers.push(peer);
     }
 
-    // 同步区块链
-    pub fn sync_blockchain(&self) {
+    // 依次与对等节点同步区块链，返回接入的区块数；parallel 为并行下载区块体的连接数
+    pub fn sync_blockchain(&self, parallel: usize) -> usize {
+        let mut imported = 0;
         for peer in &self.peers {
             println!("Syncing blockchain with peer: {}", peer);
+            match self.sync_from_peer(*peer, parallel) {
+                Ok(count) => {
+                    println!("Imported {} block(s) from peer {}", count, peer);
+                    imported += count;
+                }
+                Err(err) => println!("Failed to sync with peer {}: {}", peer, err),
+            }
+        }
+        imported
+    }
+
+    // 先同步区块头：下载对等节点的全部区块头并验证工作量链，只有分叉后的工作量更多时，
+    // 才并行下载分叉后的区块体，回滚本地分叉上的区块并逐个接入（接入失败时恢复原来的链）
+    fn sync_from_peer(&self, peer: SocketAddr, parallel: usize) -> Result<usize, String> {
+        let (genesis, genesis_hash, engine) = {
+            let blockchain = self.blockchain.lock().unwrap();
+            let genesis = &blockchain.chain[0];
+            (genesis.header.clone(), genesis.hash.clone(), blockchain.consensus_engine())
+        };
+
+        // 1. 下载区块头，检查高度连续、哈希链接，以及不依赖链上状态的共识规则（工作量证明或出块者签名）
+        let mut client = PeerClient::connect(peer)?;
+        let mut headers: Vec<(BlockHeader, String)> = Vec::new();
+        loop {
+            let batch: Vec<BlockHeader> = client.request_as("getHeaders", json!([headers.len() + 1]))?;
+            for header in &batch {
+                let (previous, previous_hash) = headers.last().map(|(header, hash)| (header, hash)).unwrap_or((&genesis, &genesis_hash));
+                let hash = header.validate(previous, previous_hash)?;
+                engine.verify_seal(header, &hash)?;
+                headers.push((header.clone(), hash));
+            }
+            if batch.len() < MAX_HEADERS_PER_REQUEST {
+                break;
+            }
+        }
+
+        // 2. 找到分叉点，比较分叉后的工作量
+        let (fork, fork_hash) = {
+            let blockchain = self.blockchain.lock().unwrap();
+            let fork = headers
+                .iter()
+                .zip(blockchain.chain.iter().skip(1))
+                .take_while(|((_, hash), block)| *hash == block.hash)
+                .count();
+            let peer_work: u128 = headers[fork..].iter().map(|(header, _)| header.work()).sum();
+            let local_work: u128 = blockchain.chain[fork + 1..].iter().map(|block| block.header.work()).sum();
+            if peer_work <= local_work {
+                return Ok(0);
+            }
+            // 已最终确定的区块不能被更重的分叉取代
+            if (fork as u64) < blockchain.finalized_height() {
+                return Err(format!("Peer chain forks at height {} below the finalized height {}", fork, blockchain.finalized_height()));
+            }
+            (fork, blockchain.chain[fork].hash.clone())
+        };
+
+        // 3. 并行下载分叉后的区块体和提交证书，并检查与区块头中的默克尔根一致（提交证书在接入区块时验证）
+        let headers = headers.split_off(fork);
+        let blocks = download_blocks(peer, headers, parallel)?;
+
+        // 4. 回滚本地分叉上的区块，接入新区块
+        let mut blockchain = self.blockchain.lock().unwrap();
+        if blockchain.chain.get(fork).map(|block| &block.hash) != Some(&fork_hash) {
+            return Err("Local chain changed during sync".to_string());
+        }
+        let mut disconnected = Vec::new();
+        while blockchain.chain.len() > fork + 1 {
+            disconnected.push(blockchain.disconnect_latest_block()?);
+        }
+        for (count, block) in blocks.into_iter().enumerate() {
+            if let Err(err) = blockchain.import_block(block) {
+                for _ in 0..count {
+                    blockchain.disconnect_latest_block()?;
+                }
+                for block in disconnected.into_iter().rev() {
+                    blockchain.import_block(block)?;
+                }
+                return Err(err);
+            }
+        }
+        if !disconnected.is_empty() {
+            println!("Reorganized {} block(s) from height {}", disconnected.len(), fork + 1);
+        }
+        Ok(blockchain.chain.len() - fork - 1)
+    }
+
+    // 启动 WebSocket 服务（订阅推送和区块同步请求），每个连接由独立线程处理
+    pub fn start_ws_server(&self, ws_address: SocketAddr) -> io::Result<thread::JoinHandle<()>> {
+        let listener = TcpListener::bind(ws_address)?;
+        let events = self.blockchain.lock().unwrap().events.clone();
+        let blockchain = self.blockchain.clone();
+        println!("WebSocket server listening on: {}", ws_address);
+        Ok(thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let events = events.clone();
+                let blockchain = blockchain.clone();
+                thread::spawn(move || {
+                    if let Err(err) = handle_ws_connection(stream, events, blockchain) {
+                        println!("WebSocket connection closed: {}", err);
+                    }
+                });
+            }
+        }))
+    }
+}
+
+// 到对等节点的 WebSocket 连接，用于发送同步请求
+struct PeerClient {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    next_id: u64,
+}
+
+impl PeerClient {
+    fn connect(peer: SocketAddr) -> Result<Self, String> {
+        let (socket, _) = tungstenite::connect(format!("ws://{}", peer)).map_err(|e| e.to_string())?;
+        Ok(PeerClient { socket, next_id: 1 })
+    }
+
+    // 发送请求并等待对应 ID 的响应，将结果解析为 T
+    fn request_as<T: serde::de::DeserializeOwned>(&mut self, method: &str, params: Value) -> Result<T, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({ "id": id, "method": method, "params": params });
+        self.socket.send(Message::Text(request.to_string())).map_err(|e| e.to_string())?;
+        loop {
+            match self.socket.read().map_err(|e| e.to_string())? {
+                Message::Text(text) => {
+                    let response: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+                    if response["id"] != json!(id) {
+                        continue;
+                    }
+                    if let Some(error) = response.get("error") {
+                        return Err(format!("Peer returned an error: {}", error));
+                    }
+                    return serde_json::from_value(response["result"].clone()).map_err(|e| format!("Invalid {} response: {}", method, e));
+                }
+                Message::Close(_) => return Err("Peer closed the connection".to_string()),
+                _ => {}
+            }
+        }
+    }
+}
+
+// getBlockBodies 响应中的区块体：交易列表和可选的提交证书
+#[derive(Deserialize)]
+struct BlockBody {
+    transactions: Vec<Transaction>,
+    #[serde(default)]
+    commit: Option<CommitCertificate>,
+}
+
+// 用 parallel 个连接并行下载区块体，按区块头的顺序组装区块
+fn download_blocks(peer: SocketAddr, headers: Vec<(BlockHeader, String)>, parallel: usize) -> Result<Vec<Block>, String> {
+    if headers.is_empty() {
+        return Ok(Vec::new());
+    }
+    let chunk_size = headers.len().div_ceil(parallel.max(1));
+    let bodies = thread::scope(|scope| {
+        let workers: Vec<_> = headers
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || -> Result<Vec<BlockBody>, String> {
+                    let mut client = PeerClient::connect(peer)?;
+                    let mut bodies = Vec::with_capacity(chunk.len());
+                    for batch in chunk.chunks(BODIES_PER_REQUEST) {
+                        let hashes: Vec<&String> = batch.iter().map(|(_, hash)| hash).collect();
+                        let response: Vec<Option<BlockBody>> = client.request_as("getBlockBodies", json!(hashes))?;
+                        if response.len() != batch.len() {
+                            return Err("Peer returned the wrong number of block bodies".to_string());
+                        }
+                        for ((_, hash), body) in batch.iter().zip(response) {
+                            bodies.push(body.ok_or_else(|| format!("Peer does not have block {}", hash))?);
+                        }
+                    }
+                    Ok(bodies)
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap_or_else(|_| Err("Download thread panicked".to_string())))
+            .collect::<Result<Vec<_>, String>>()
+    })?;
+
+    headers
+        .into_iter()
+        .zip(bodies.into_iter().flatten())
+        .map(|((header, hash), body)| {
+            let block = Block { header, hash, transactions: body.transactions, commit: body.commit };
+            if !block.has_valid_merkle_root() {
+                return Err(format!("Block body does not match the merkle root of block {}", block.header.height));
+            }
+            Ok(block)
+        })
+        .collect()
+}
+
+// 处理单个 WebSocket 连接：处理订阅和同步请求，并将匹配的事件推送给客户端
+fn handle_ws_connection(stream: TcpStream, events: EventBus, blockchain: Arc<Mutex<Blockchain>>) -> Result<(), String> {
+    let mut socket = accept(stream).map_err(|e| e.to_string())?;
+    // 读取设置超时，以便在等待请求的同时推送事件
+    socket.get_ref().set_read_timeout(Some(Duration::from_millis(100))).map_err(|e| e.to_string())?;
+    let receiver = events.subscribe();
+    let mut subscriptions: Vec<(u64, Subscription)> = Vec::new();
+    let mut next_id = 1;
+
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let response = handle_ws_request(&text, &blockchain, &mut subscriptions, &mut next_id);
+                socket.send(Message::Text(response.to_string())).map_err(|e| e.to_string())?;
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => {}
+            Err(err) => return Err(err.to_string()),
         }
+
+        while let Ok(event) = receiver.try_recv() {
+            for (id, subscription) in &subscriptions {
+                if let Some(result) = subscription.filter(&event) {
+                    let notification = json!({
+                        "method": "subscription",
+                        "params": { "subscription": id.to_string(), "result": result },
+                    });
+                    socket.send(Message::Text(notification.to_string())).map_err(|e| e.to_string())?;
+                }
+            }
+        }
+    }
+}
+
+// 处理 subscribe / unsubscribe、提交交易（sendRawTransaction）和同步（getHeaders / getBlockBodies）请求，返回 JSON-RPC 风格的响应
+fn handle_ws_request(text: &str, blockchain: &Mutex<Blockchain>, subscriptions: &mut Vec<(u64, Subscription)>, next_id: &mut u64) -> Value {
+    let request: Value = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(err) => return json!({ "id": null, "error": format!("Invalid request: {}", err) }),
+    };
+    let id = request["id"].clone();
+    let params = request["params"].as_array().cloned().unwrap_or_default();
+
+    match request["method"].as_str() {
+        Some("subscribe") => match Subscription::from_params(&params) {
+            Ok(subscription) => {
+                let subscription_id = *next_id;
+                *next_id += 1;
+                subscriptions.push((subscription_id, subscription));
+                json!({ "id": id, "result": subscription_id.to_string() })
+            }
+            Err(err) => json!({ "id": id, "error": err }),
+        },
+        Some("unsubscribe") => {
+            let target = params.first().and_then(Value::as_str).and_then(|s| s.parse::<u64>().ok());
+            let before = subscriptions.len();
+            subscriptions.retain(|(subscription_id, _)| Some(*subscription_id) != target);
+            json!({ "id": id, "result": subscriptions.len() != before })
+        }
+        // 提交原始交易：通过检查后进入交易池（并推送给 pendingTransactions 订阅者），返回交易 ID
+        Some("sendRawTransaction") => {
+            let transaction = match params.first().and_then(Value::as_str).map(Transaction::from_raw) {
+                Some(Ok(transaction)) => transaction,
+                Some(Err(err)) => return json!({ "id": id, "error": err }),
+                None => return json!({ "id": id, "error": "Missing raw transaction" }),
+            };
+            let tx_id = transaction.id();
+            match blockchain.lock().unwrap().add_transaction(transaction) {
+                Ok(()) => json!({ "id": id, "result": tx_id }),
+                Err(err) => json!({ "id": id, "error": err }),
+            }
+        }
+        // 从指定高度开始的区块头
+        Some("getHeaders") => match params.first().and_then(Value::as_u64) {
+            Some(from) => {
+                let mut headers = blockchain.lock().unwrap().get_headers(from);
+                headers.truncate(MAX_HEADERS_PER_REQUEST);
+                json!({ "id": id, "result": headers })
+            }
+            None => json!({ "id": id, "error": "Missing start height" }),
+        },
+        // 按区块哈希查询区块体（交易列表和提交证书），未知的区块返回 null
+        Some("getBlockBodies") => {
+            let blockchain = blockchain.lock().unwrap();
+            let bodies: Vec<Option<Value>> = params
+                .iter()
+                .map(|hash| {
+                    let hash = hash.as_str()?;
+                    let block = blockchain.chain.iter().find(|block| block.hash == hash)?;
+                    Some(json!({ "transactions": block.transactions, "commit": block.commit }))
+                })
+                .collect();
+            json!({ "id": id, "result": bodies })
+        }
+        _ => json!({ "id": id, "error": "Unknown method" }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::ConsensusConfig;
+    use crate::finality;
+    use crate::subscription::ChainEvent;
+    use crate::utxo::LedgerMode;
+    use crate::wallet::Wallet;
+
+    fn request(blockchain: &Mutex<Blockchain>, method: &str, params: Value) -> Value {
+        let text = json!({ "id": 1, "method": method, "params": params }).to_string();
+        handle_ws_request(&text, blockchain, &mut Vec::new(), &mut 1)
+    }
+
+    #[test]
+    fn send_raw_transaction_admits_to_the_pool_and_notifies_subscribers() {
+        let alice = Wallet::new();
+        let mut chain = Blockchain::new(1);
+        chain.add_block(Vec::new(), &alice.address());
+        let receiver = chain.events.subscribe();
+        let blockchain = Mutex::new(chain);
+        let tx = Transaction::new(alice.address(), "bob".to_string(), 10, &alice.key_pair);
+
+        let response = request(&blockchain, "sendRawTransaction", json!([tx.to_raw()]));
+        assert_eq!(response["result"], json!(tx.id()));
+        assert_eq!(blockchain.lock().unwrap().pending_transactions.len(), 1);
+        match receiver.try_recv() {
+            Ok(ChainEvent::PendingTransaction(pending)) => assert_eq!(pending.id(), tx.id()),
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // 重复提交、无效编码和缺少参数都返回错误
+        assert!(request(&blockchain, "sendRawTransaction", json!([tx.to_raw()])).get("error").is_some());
+        assert!(request(&blockchain, "sendRawTransaction", json!(["zz"])).get("error").is_some());
+        assert!(request(&blockchain, "sendRawTransaction", json!([])).get("error").is_some());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn sync_requests_return_headers_and_bodies() {
+        let mut chain = Blockchain::new(1);
+        chain.add_block(Vec::new(), "miner");
+        let hash = chain.get_latest_block().hash.clone();
+        let blockchain = Mutex::new(chain);
+
+        let headers = request(&blockchain, "getHeaders", json!([1]));
+        assert_eq!(headers["result"].as_array().unwrap().len(), 1);
+        let bodies = request(&blockchain, "getBlockBodies", json!([hash, "unknown"]));
+        assert_eq!(bodies["result"][0]["transactions"].as_array().unwrap().len(), 1);
+        assert!(bodies["result"][0]["commit"].is_null());
+        assert!(bodies["result"][1].is_null());
+        assert_eq!(request(&blockchain, "other", json!([]))["error"], "Unknown method");
+    }
+
+    // 在本机空闲端口上启动节点的 WebSocket 服务；选端口到绑定之间加锁，避免并行的测试拿到同一个端口
+    fn start_peer(blockchain: Blockchain) -> SocketAddr {
+        static PORTS: Mutex<()> = Mutex::new(());
+        let _guard = PORTS.lock().unwrap();
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        Node::with_blockchain(address, blockchain).start_ws_server(address).unwrap();
+        address
+    }
+
+    #[test]
+    fn headers_first_sync_switches_to_a_heavier_peer_chain() {
+        let mut peer_chain = Blockchain::new(1);
+        let mut local_chain = Blockchain::new(1);
+        local_chain.chain[0] = peer_chain.chain[0].clone();
+        for _ in 0..3 {
+            peer_chain.add_block(Vec::new(), "peer");
+        }
+        local_chain.add_block(Vec::new(), "local");
+        let peer_tip = peer_chain.get_latest_block().hash.clone();
+
+        let mut node = Node::with_blockchain("127.0.0.1:0".parse().unwrap(), local_chain);
+        node.add_peer(start_peer(peer_chain));
+        assert_eq!(node.sync_blockchain(2), 3);
+        {
+            let blockchain = node.blockchain.lock().unwrap();
+            assert_eq!(blockchain.get_latest_block().hash, peer_tip);
+            assert_eq!(blockchain.get_balance("local"), 0);
+            assert!(blockchain.is_chain_valid());
+        }
+        // 对等节点的链没有更多工作量时不再同步
+        assert_eq!(node.sync_blockchain(2), 0);
+    }
+
+    #[test]
+    fn sync_rejects_a_peer_with_a_different_genesis() {
+        let mut peer_chain = Blockchain::new(1);
+        peer_chain.chain[0].header.nonce += 1;
+        peer_chain.chain[0].hash = peer_chain.chain[0].calculate_hash();
+        peer_chain.add_block(Vec::new(), "peer");
+        let local_chain = Blockchain::new(1);
+        let genesis = local_chain.chain[0].hash.clone();
+
+        let mut node = Node::with_blockchain("127.0.0.1:0".parse().unwrap(), local_chain);
+        node.add_peer(start_peer(peer_chain));
+        assert_eq!(node.sync_blockchain(1), 0);
+        assert_eq!(node.blockchain.lock().unwrap().chain.len(), 1);
+        assert_eq!(node.blockchain.lock().unwrap().chain[0].hash, genesis);
+    }
+    #[test]
+    fn synced_blocks_keep_verified_commit_certificates() {
+        let wallets: Vec<Wallet> = (0..4).map(|_| Wallet::new()).collect();
+        let validators: Vec<String> = wallets.iter().map(Wallet::address).collect();
+        let finality_chain = || Blockchain::with_genesis(1, LedgerMode::Account, Vec::new(), ConsensusConfig::ProofOfWork, validators.clone()).unwrap();
+        let mut peer_chain = finality_chain();
+        peer_chain.add_block(Vec::new(), "peer");
+        let certificate = finality::finalize_latest_block(&peer_chain, wallets.clone()).unwrap();
+        peer_chain.finalize(certificate).unwrap();
+        peer_chain.add_block(Vec::new(), "peer");
+
+        let fresh_chain = || {
+            let mut blockchain = finality_chain();
+            blockchain.chain[0] = peer_chain.chain[0].clone();
+            blockchain
+        };
+
+        // 提交证书缺少预提交的对等节点：区块接入失败，本地链保持不变
+        let mut forged_chain = fresh_chain();
+        for block in &peer_chain.chain[1..] {
+            forged_chain.import_block(Block { commit: None, ..block.clone() }).unwrap();
+        }
+        let mut forged = peer_chain.chain[1].commit.clone().unwrap();
+        forged.precommits.truncate(1);
+        forged_chain.chain[1].commit = Some(forged);
+        let mut node = Node::with_blockchain("127.0.0.1:0".parse().unwrap(), fresh_chain());
+        node.add_peer(start_peer(forged_chain));
+        assert_eq!(node.sync_blockchain(1), 0);
+        assert_eq!(node.blockchain.lock().unwrap().chain.len(), 1);
+
+        // 同步后的区块带有对等节点的提交证书，本地也认为该区块已最终确定
+        let mut node = Node::with_blockchain("127.0.0.1:0".parse().unwrap(), fresh_chain());
+        node.add_peer(start_peer(peer_chain));
+        assert_eq!(node.sync_blockchain(2), 2);
+        let blockchain = node.blockchain.lock().unwrap();
+        assert!(blockchain.chain[1].commit.is_some());
+        assert_eq!(blockchain.finalized_height(), 1);
+        assert!(blockchain.is_chain_valid());
     }
 }
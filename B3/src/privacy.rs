@@ -1,52 +1,689 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 use serde::{Serialize, Deserialize};
-use bellman::groth16::{Proof, VerifyingKey};
-use bellman::pairing::bn256::{Bn256, Fr};
-use bellman::{Circuit, ConstraintSystem, SynthesisError};
-use bellman::groth16::generate_random_parameters;
-use bellman::groth16::verify_proof;
-use bellman::groth16::prepare_verifying_key;
-use bellman::groth16::create_random_proof;
+use bellman::groth16::{self, batch, Parameters, PreparedVerifyingKey, Proof, VerifyingKey};
+use bellman::gadgets::boolean::AllocatedBit;
+use bellman::{Circuit, ConstraintSystem, LinearCombination, SynthesisError, Variable};
+use bls12_381::{Bls12, Scalar};
+use ff::Field;
 use rand::rngs::OsRng;
+use sha2::{Sha512, Digest};
 
-#[derive(Serialize, Deserialize, Debug)]
+// 隐私交易固定花费 NOTES_IN 个票据并创建 NOTES_OUT 个票据，不足时用金额为 0 的票据补齐
+pub const NOTES_IN: usize = 2;
+pub const NOTES_OUT: usize = 2;
+pub const TREE_DEPTH: usize = 16; // 票据承诺树的深度（最多 65536 个票据）
+
+const AMOUNT_BITS: usize = 64;  // 金额范围：[0, 2^64)
+const MIMC_ROUNDS: usize = 322; // MiMC 轮数
+
+// 哈希均为 H(a, b) = MiMC(a, b)：
+//   隐私地址   pk = H(sk, 0)
+//   票据密钥   k  = H(pk, rho)
+//   票据承诺   cm = H(H(amount, blinding), k)
+//   作废标识   nf = H(sk, rho)
+//   树节点        H(左, 右)，空叶子为 0
+
+// 隐私地址的密钥
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShieldedKey {
+    pub spending_key: String, // 花费密钥（标量的十六进制）
+}
+
+impl ShieldedKey {
+    // 生成随机密钥
+    pub fn generate() -> Self {
+        ShieldedKey { spending_key: encode_scalar(&Scalar::random(&mut OsRng)) }
+    }
+
+    // 隐私地址
+    pub fn address(&self) -> Result<String, String> {
+        let sk = parse_scalar(&self.spending_key)?;
+        Ok(encode_scalar(&hash(sk, Scalar::ZERO)))
+    }
+}
+
+// 隐私票据：只有知道 owner 对应花费密钥的人才能花费
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Note {
+    pub owner: String, // 接收方的隐私地址
+    pub amount: u64,
+    pub rho: String,      // 用于派生作废标识的随机数
+    pub blinding: String, // 承诺的盲化因子
+}
+
+impl Note {
+    // 为隐私地址创建票据
+    pub fn new(owner: &str, amount: u64) -> Self {
+        Note {
+            owner: owner.to_string(),
+            amount,
+            rho: encode_scalar(&Scalar::random(&mut OsRng)),
+            blinding: encode_scalar(&Scalar::random(&mut OsRng)),
+        }
+    }
+
+    // 票据密钥 H(pk, rho)：存入隐私池时只公开它，不公开接收方
+    pub fn note_key(&self) -> Result<String, String> {
+        let (owner, rho) = (parse_scalar(&self.owner)?, parse_scalar(&self.rho)?);
+        Ok(encode_scalar(&hash(owner, rho)))
+    }
+
+    // 票据承诺
+    pub fn commitment(&self) -> Result<String, String> {
+        note_commitment(self.amount, &self.note_key()?, &self.blinding)
+    }
+
+    // 作废标识：花费票据时公开，用于防止双花
+    pub fn nullifier(&self, key: &ShieldedKey) -> Result<String, String> {
+        let (sk, rho) = (parse_scalar(&key.spending_key)?, parse_scalar(&self.rho)?);
+        Ok(encode_scalar(&hash(sk, rho)))
+    }
+}
+
+// 由金额、票据密钥和盲化因子计算票据承诺
+pub fn note_commitment(amount: u64, note_key: &str, blinding: &str) -> Result<String, String> {
+    let (note_key, blinding) = (parse_scalar(note_key)?, parse_scalar(blinding)?);
+    Ok(encode_scalar(&hash(hash(Scalar::from(amount), blinding), note_key)))
+}
+
+// 票据承诺树的父节点
+pub fn merkle_parent(left: &str, right: &str) -> Result<String, String> {
+    Ok(encode_scalar(&hash(parse_scalar(left)?, parse_scalar(right)?)))
+}
+
+// 每一层空子树的哈希：下标 0 为空叶子，下标 TREE_DEPTH 为空树的根
+pub fn empty_nodes() -> Vec<String> {
+    let mut node = Scalar::ZERO;
+    let mut nodes = vec![encode_scalar(&node)];
+    for _ in 0..TREE_DEPTH {
+        node = hash(node, node);
+        nodes.push(encode_scalar(&node));
+    }
+    nodes
+}
+
+// 要花费的票据及其在票据承诺树中的位置和认证路径（从叶子向上的兄弟节点）
+#[derive(Debug, Clone)]
+pub struct SpendInput {
+    pub note: Note,
+    pub position: u64,
+    pub path: Vec<String>,
+}
+
+// 一次性可信设置：生成隐私转账电路的证明参数（其中包含验证密钥）
+pub fn setup() -> Result<Parameters<Bls12>, String> {
+    groth16::generate_random_parameters::<Bls12, _, _>(ShieldedCircuit::blank(), &mut OsRng).map_err(|err| err.to_string())
+}
+
+// 将证明参数写入文件
+pub fn write_parameters(params: &Parameters<Bls12>, path: &str) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| format!("Cannot create {}: {}", path, err))?;
+    params.write(BufWriter::new(file)).map_err(|err| err.to_string())
+}
+
+// 从文件读取证明参数：参数只用于生成证明，证明能否通过取决于链上登记的验证密钥，因此不做曲线点检查
+pub fn read_parameters(path: &str) -> Result<Parameters<Bls12>, String> {
+    let file = File::open(path).map_err(|err| format!("Cannot open {}: {}", path, err))?;
+    Parameters::read(BufReader::new(file), false).map_err(|err| format!("Invalid parameters {}: {}", path, err))
+}
+
+// 证明参数中的验证密钥（十六进制）
+pub fn verifying_key(params: &Parameters<Bls12>) -> String {
+    let mut bytes = Vec::new();
+    params.vk.write(&mut bytes).unwrap();
+    hex::encode(bytes)
+}
+
+// 一个待验证的隐私转账：交易、转出金额和转出的公开地址
+pub type ProofStatement<'a> = (&'a PrivacyTransaction, u64, &'a str);
+
+// 登记的验证密钥，证明只要能被其中任意一个密钥验证即为有效
+pub struct VerifyingKeys {
+    keys: Vec<(VerifyingKey<Bls12>, PreparedVerifyingKey<Bls12>)>,
+}
+
+impl VerifyingKeys {
+    // 解析十六进制编码的验证密钥
+    pub fn parse(vks: &[String]) -> Result<Self, String> {
+        let keys = vks
+            .iter()
+            .map(|vk| {
+                let bytes = hex::decode(vk.trim()).map_err(|err| err.to_string())?;
+                let vk = VerifyingKey::<Bls12>::read(&bytes[..]).map_err(|err| format!("Invalid verifying key: {}", err))?;
+                let pvk = groth16::prepare_verifying_key(&vk);
+                Ok((vk, pvk))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(VerifyingKeys { keys })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    // 逐个验证，返回第一个无效证明的下标
+    pub fn verify_sequential(&self, statements: &[ProofStatement]) -> Result<(), usize> {
+        match statements.iter().position(|(transaction, public_value, receiver)| {
+            !self.keys.iter().any(|(_, pvk)| transaction.verify(pvk, *public_value, receiver))
+        }) {
+            Some(index) => Err(index),
+            None => Ok(()),
+        }
+    }
+
+    // 批量验证：用随机系数把所有证明的验证等式合并为一次多重配对检查，
+    // 合并检查不通过时逐个验证，找出第一个无效的证明
+    pub fn verify_batch(&self, statements: &[ProofStatement]) -> Result<(), usize> {
+        let items = match statements
+            .iter()
+            .map(|(transaction, public_value, receiver)| transaction.statement(*public_value, receiver))
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(items) => items,
+            None => return self.verify_sequential(statements),
+        };
+        for (vk, _) in &self.keys {
+            let mut verifier = batch::Verifier::<Bls12>::new();
+            for item in &items {
+                verifier.queue(item.clone());
+            }
+            if verifier.verify(OsRng, vk).is_ok() {
+                return Ok(());
+            }
+        }
+        self.verify_sequential(statements)
+    }
+}
+
+// 隐私转账：花费票据并创建新票据，可以把 public_value 转出到公开地址。
+// 证明在不公开金额和票据的情况下说明：花费的票据在 anchor 对应的承诺树中且属于证明者，
+// 作废标识推导正确，所有金额在 64 位范围内，花费金额之和等于新票据金额之和加 public_value
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PrivacyTransaction {
-    pub proof: Proof<Bn256>, // ZKP 证明
-    pub vk: VerifyingKey<Bn256>, // 验证密钥
+    pub anchor: String,           // 票据承诺树的根（公开输入）
+    pub nullifiers: Vec<String>,  // 花费票据的作废标识（公开输入）
+    pub commitments: Vec<String>, // 新票据的承诺（公开输入）
+    pub proof: String,            // Groth16 证明（十六进制）
+}
+
+impl PrivacyTransaction {
+    // 用可信设置生成的参数创建隐私转账，public_value 转给公开地址 receiver
+    pub fn new(params: &Parameters<Bls12>, key: &ShieldedKey, anchor: &str, spends: &[SpendInput], outputs: &[Note], public_value: u64, receiver: &str) -> Result<Self, String> {
+        if spends.len() > NOTES_IN || outputs.len() > NOTES_OUT {
+            return Err(format!("At most {} input and {} output notes are supported", NOTES_IN, NOTES_OUT));
+        }
+        let address = key.address()?;
+        if spends.iter().any(|spend| spend.note.owner != address) {
+            return Err("Spent notes must belong to the spending key".to_string());
+        }
+        let spent: u128 = spends.iter().map(|spend| spend.note.amount as u128).sum();
+        let created: u128 = outputs.iter().map(|note| note.amount as u128).sum::<u128>() + public_value as u128;
+        if spent != created {
+            return Err(format!("Spent notes total {} but outputs total {}", spent, created));
+        }
+
+        // 用金额为 0 的票据补齐，空票据不需要在承诺树中
+        let mut spends = spends.to_vec();
+        while spends.len() < NOTES_IN {
+            spends.push(SpendInput { note: Note::new(&address, 0), position: 0, path: empty_nodes()[..TREE_DEPTH].to_vec() });
+        }
+        let mut outputs = outputs.to_vec();
+        outputs.resize_with(NOTES_OUT, || Note::new(&address, 0));
+
+        let sk = parse_scalar(&key.spending_key)?;
+        let circuit = ShieldedCircuit {
+            spending_key: Some(sk),
+            anchor: Some(parse_scalar(anchor)?),
+            spends: spends.iter().map(|spend| SpendWitness::new(spend).map(Some)).collect::<Result<_, _>>()?,
+            outputs: outputs.iter().map(|note| OutputWitness::new(note).map(Some)).collect::<Result<_, _>>()?,
+            public_value: Some(Scalar::from(public_value)),
+            binding: Some(binding(receiver)),
+            constants: mimc_constants(),
+        };
+        let proof = groth16::create_random_proof(circuit, params, &mut OsRng).map_err(|err| err.to_string())?;
+
+        let mut proof_bytes = Vec::new();
+        proof.write(&mut proof_bytes).unwrap();
+        Ok(PrivacyTransaction {
+            anchor: anchor.to_string(),
+            nullifiers: spends.iter().map(|spend| spend.note.nullifier(key)).collect::<Result<_, _>>()?,
+            commitments: outputs.iter().map(Note::commitment).collect::<Result<_, _>>()?,
+            proof: hex::encode(proof_bytes),
+        })
+    }
+
+    // 用给定的验证密钥验证证明
+    pub fn verify(&self, pvk: &PreparedVerifyingKey<Bls12>, public_value: u64, receiver: &str) -> bool {
+        let Some((proof, public_inputs)) = self.statement(public_value, receiver) else {
+            return false;
+        };
+        groth16::verify_proof(pvk, &proof, &public_inputs).is_ok()
+    }
+
+    // 证明及其公开输入：依次为 anchor、作废标识、新票据承诺、public_value 和 receiver，格式错误时返回 None
+    fn statement(&self, public_value: u64, receiver: &str) -> Option<(Proof<Bls12>, Vec<Scalar>)> {
+        if self.nullifiers.len() != NOTES_IN || self.commitments.len() != NOTES_OUT {
+            return None;
+        }
+        let mut public_inputs = std::iter::once(&self.anchor)
+            .chain(&self.nullifiers)
+            .chain(&self.commitments)
+            .map(|value| parse_scalar(value).ok())
+            .collect::<Option<Vec<Scalar>>>()?;
+        public_inputs.push(Scalar::from(public_value));
+        public_inputs.push(binding(receiver));
+        Some((self.parse_proof().ok()?, public_inputs))
+    }
+
+    fn parse_proof(&self) -> Result<Proof<Bls12>, String> {
+        let bytes = hex::decode(&self.proof).map_err(|err| err.to_string())?;
+        Proof::read(&bytes[..]).map_err(|err| err.to_string())
+    }
 }
 
+// 花费票据的见证
 #[derive(Clone)]
-struct PrivacyCircuit {
-    amount: Option<u64>,
+struct SpendWitness {
+    amount: u64,
+    rho: Scalar,
+    blinding: Scalar,
+    position: u64,
+    path: Vec<Scalar>,
 }
 
-impl Circuit<Fr> for PrivacyCircuit {
-    fn synthesize<CS: ConstraintSystem<Fr>>(&self, cs: &mut CS) -> Result<(), SynthesisError> {
-        let amount = cs.alloc(|| "amount", || self.amount.ok_or(SynthesisError::AssignmentMissing))?;
+impl SpendWitness {
+    fn new(spend: &SpendInput) -> Result<Self, String> {
+        if spend.path.len() != TREE_DEPTH {
+            return Err(format!("Authentication path must have {} nodes", TREE_DEPTH));
+        }
+        Ok(SpendWitness {
+            amount: spend.note.amount,
+            rho: parse_scalar(&spend.note.rho)?,
+            blinding: parse_scalar(&spend.note.blinding)?,
+            position: spend.position,
+            path: spend.path.iter().map(|node| parse_scalar(node)).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+// 新票据的见证
+#[derive(Clone)]
+struct OutputWitness {
+    amount: u64,
+    blinding: Scalar,
+    note_key: Scalar,
+}
+
+impl OutputWitness {
+    fn new(note: &Note) -> Result<Self, String> {
+        Ok(OutputWitness {
+            amount: note.amount,
+            blinding: parse_scalar(&note.blinding)?,
+            note_key: parse_scalar(&note.note_key()?)?,
+        })
+    }
+}
+
+// 隐私转账电路，见证为 None 时用于生成参数
+#[derive(Clone)]
+struct ShieldedCircuit {
+    spending_key: Option<Scalar>,
+    anchor: Option<Scalar>,
+    spends: Vec<Option<SpendWitness>>,
+    outputs: Vec<Option<OutputWitness>>,
+    public_value: Option<Scalar>,
+    binding: Option<Scalar>, // 公开地址 receiver 的哈希，防止证明被挪用到其它接收方
+    constants: Vec<Scalar>,
+}
+
+impl ShieldedCircuit {
+    fn blank() -> Self {
+        ShieldedCircuit {
+            spending_key: None,
+            anchor: None,
+            spends: vec![None; NOTES_IN],
+            outputs: vec![None; NOTES_OUT],
+            public_value: None,
+            binding: None,
+            constants: mimc_constants(),
+        }
+    }
+}
+
+impl Circuit<Scalar> for ShieldedCircuit {
+    fn synthesize<CS: ConstraintSystem<Scalar>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+        let constants = &self.constants;
+        let anchor = Num::alloc(cs.namespace(|| "anchor"), self.anchor)?;
+        anchor.inputize(cs.namespace(|| "anchor input"))?;
+        let sk = Num::alloc(cs.namespace(|| "spending key"), self.spending_key)?;
+        let pk = mimc(cs.namespace(|| "address"), &sk, &Num::zero(), constants)?;
+
+        let mut balance = LinearCombination::zero();
+        for (i, spend) in self.spends.iter().enumerate() {
+            let cs = &mut cs.namespace(|| format!("spend {}", i));
+            let amount = amount_bits(cs.namespace(|| "amount"), spend.as_ref().map(|spend| spend.amount))?;
+            let rho = Num::alloc(cs.namespace(|| "rho"), spend.as_ref().map(|spend| spend.rho))?;
+            let blinding = Num::alloc(cs.namespace(|| "blinding"), spend.as_ref().map(|spend| spend.blinding))?;
+            let note_key = mimc(cs.namespace(|| "note key"), &pk, &rho, constants)?;
+            let inner = mimc(cs.namespace(|| "value commitment"), &amount, &blinding, constants)?;
+            let mut node = mimc(cs.namespace(|| "commitment"), &inner, &note_key, constants)?;
+
+            let nullifier = mimc(cs.namespace(|| "nullifier"), &sk, &rho, constants)?;
+            nullifier.inputize(cs.namespace(|| "nullifier input"))?;
+
+            // 从叶子沿认证路径计算根，位置的第 level 位为 1 表示当前节点是右孩子
+            for level in 0..TREE_DEPTH {
+                let cs = &mut cs.namespace(|| format!("level {}", level));
+                let sibling = Num::alloc(cs.namespace(|| "sibling"), spend.as_ref().map(|spend| spend.path[level]))?;
+                let is_right = AllocatedBit::alloc(cs.namespace(|| "position"), spend.as_ref().map(|spend| (spend.position >> level) & 1 == 1))?;
+                let (left, right) = swap_if(cs.namespace(|| "order"), &node, &sibling, &is_right)?;
+                node = mimc(cs.namespace(|| "parent"), &left, &right, constants)?;
+            }
+            // 金额为 0 的票据不检查成员关系：(root - anchor) * amount = 0
+            cs.enforce(
+                || "membership",
+                |lc| lc + &node.lc - &anchor.lc,
+                |lc| lc + &amount.lc,
+                |lc| lc,
+            );
+            balance = balance + &amount.lc;
+        }
+
+        for (i, output) in self.outputs.iter().enumerate() {
+            let cs = &mut cs.namespace(|| format!("output {}", i));
+            let amount = amount_bits(cs.namespace(|| "amount"), output.as_ref().map(|output| output.amount))?;
+            let blinding = Num::alloc(cs.namespace(|| "blinding"), output.as_ref().map(|output| output.blinding))?;
+            let note_key = Num::alloc(cs.namespace(|| "note key"), output.as_ref().map(|output| output.note_key))?;
+            let inner = mimc(cs.namespace(|| "value commitment"), &amount, &blinding, constants)?;
+            let commitment = mimc(cs.namespace(|| "commitment"), &inner, &note_key, constants)?;
+            commitment.inputize(cs.namespace(|| "commitment input"))?;
+            balance = balance - &amount.lc;
+        }
+
+        let public_value = Num::alloc(cs.namespace(|| "public value"), self.public_value)?;
+        public_value.inputize(cs.namespace(|| "public value input"))?;
         cs.enforce(
-            || "amount > 0",
-            |lc| lc + amount,
+            || "balance",
+            |lc| lc + &balance - &public_value.lc,
             |lc| lc + CS::one(),
             |lc| lc,
         );
+
+        // 接收方哈希不参与其它约束，通过平方约束将其绑定到证明中
+        let binding = Num::alloc(cs.namespace(|| "binding"), self.binding)?;
+        binding.inputize(cs.namespace(|| "binding input"))?;
+        let square = Num::alloc(cs.namespace(|| "binding square"), self.binding.map(|binding| binding.square()))?;
+        cs.enforce(
+            || "binding square constraint",
+            |lc| lc + &binding.lc,
+            |lc| lc + &binding.lc,
+            |lc| lc + &square.lc,
+        );
         Ok(())
     }
 }
 
-impl PrivacyTransaction {
-    // 创建隐私交易
-    pub fn new(amount: u64) -> Self {
-        let circuit = PrivacyCircuit {
-            amount: Some(amount),
-        };
-        let rng = &mut OsRng;
-        let params = generate_random_parameters::<Bn256, _, _>(circuit.clone(), rng).unwrap();
-        let proof = create_random_proof(circuit, &params, rng).unwrap();
-        let vk = prepare_verifying_key(&params.vk);
-        PrivacyTransaction { proof, vk }
+// 电路中的数：线性组合及其取值
+#[derive(Clone)]
+struct Num {
+    lc: LinearCombination<Scalar>,
+    value: Option<Scalar>,
+}
+
+impl Num {
+    fn zero() -> Self {
+        Num { lc: LinearCombination::zero(), value: Some(Scalar::ZERO) }
+    }
+
+    fn from_variable(variable: Variable, value: Option<Scalar>) -> Self {
+        Num { lc: LinearCombination::zero() + variable, value }
+    }
+
+    fn alloc<CS: ConstraintSystem<Scalar>>(mut cs: CS, value: Option<Scalar>) -> Result<Self, SynthesisError> {
+        let variable = cs.alloc(|| "value", || value.ok_or(SynthesisError::AssignmentMissing))?;
+        Ok(Num::from_variable(variable, value))
+    }
+
+    // 将该数公开为证明的公开输入
+    fn inputize<CS: ConstraintSystem<Scalar>>(&self, mut cs: CS) -> Result<(), SynthesisError> {
+        let input = cs.alloc_input(|| "input", || self.value.ok_or(SynthesisError::AssignmentMissing))?;
+        cs.enforce(|| "input equals value", |lc| lc + &self.lc, |lc| lc + CS::one(), |lc| lc + input);
+        Ok(())
     }
+}
+
+// 将金额分解为 64 个比特（范围证明），返回金额
+fn amount_bits<CS: ConstraintSystem<Scalar>>(mut cs: CS, amount: Option<u64>) -> Result<Num, SynthesisError> {
+    let mut lc = LinearCombination::zero();
+    let mut coeff = Scalar::ONE;
+    for i in 0..AMOUNT_BITS {
+        let bit = AllocatedBit::alloc(cs.namespace(|| format!("bit {}", i)), amount.map(|amount| (amount >> i) & 1 == 1))?;
+        lc = lc + (coeff, bit.get_variable());
+        coeff = coeff.double();
+    }
+    Ok(Num { lc, value: amount.map(Scalar::from) })
+}
+
+// condition 为 1 时交换 a 和 b：left = a + condition * (b - a)，right = a + b - left
+fn swap_if<CS: ConstraintSystem<Scalar>>(mut cs: CS, a: &Num, b: &Num, condition: &AllocatedBit) -> Result<(Num, Num), SynthesisError> {
+    let swap = condition.get_value();
+    let left_value = a.value.zip(b.value).zip(swap).map(|((a, b), swap)| if swap { b } else { a });
+    let left = Num::alloc(cs.namespace(|| "left"), left_value)?;
+    cs.enforce(
+        || "conditional swap",
+        |lc| lc + &b.lc - &a.lc,
+        |lc| lc + condition.get_variable(),
+        |lc| lc + &left.lc - &a.lc,
+    );
+    let right = Num {
+        lc: a.lc.clone() + &b.lc - &left.lc,
+        value: a.value.zip(b.value).zip(left_value).map(|((a, b), left)| a + b - left),
+    };
+    Ok((left, right))
+}
+
+// MiMC Feistel 电路：xl' = (xl + c)^3 + xr, xr' = xl，输出最后一轮的 xl
+fn mimc<CS: ConstraintSystem<Scalar>>(mut cs: CS, xl: &Num, xr: &Num, constants: &[Scalar]) -> Result<Num, SynthesisError> {
+    let (mut xl, mut xr) = (xl.clone(), xr.clone());
+    for (round, constant) in constants.iter().enumerate() {
+        let cs = &mut cs.namespace(|| format!("round {}", round));
+        let base_value = xl.value.map(|xl| xl + constant);
+        let square = Num::alloc(cs.namespace(|| "square"), base_value.map(|base| base.square()))?;
+        cs.enforce(
+            || "square constraint",
+            |lc| lc + &xl.lc + (*constant, CS::one()),
+            |lc| lc + &xl.lc + (*constant, CS::one()),
+            |lc| lc + &square.lc,
+        );
+        let next_value = square.value.zip(base_value).zip(xr.value).map(|((square, base), xr)| square * base + xr);
+        let next = Num::alloc(cs.namespace(|| "next"), next_value)?;
+        cs.enforce(
+            || "cube constraint",
+            |lc| lc + &square.lc,
+            |lc| lc + &xl.lc + (*constant, CS::one()),
+            |lc| lc + &next.lc - &xr.lc,
+        );
+        xr = xl;
+        xl = next;
+    }
+    Ok(xl)
+}
+
+// MiMC 轮常数：由固定的种子派生，电路和链外计算使用同一组常数
+fn mimc_constants() -> Vec<Scalar> {
+    (0..MIMC_ROUNDS)
+        .map(|round| {
+            let digest: [u8; 64] = Sha512::digest(format!("b3-privacy-mimc-{}", round)).into();
+            Scalar::from_bytes_wide(&digest)
+        })
+        .collect()
+}
+
+// 链外计算 H(a, b)，与电路中的约束一致
+fn hash(mut xl: Scalar, mut xr: Scalar) -> Scalar {
+    for constant in mimc_constants() {
+        let base = xl + constant;
+        let next = base.square() * base + xr;
+        xr = xl;
+        xl = next;
+    }
+    xl
+}
+
+// 公开接收方地址映射为标量
+fn binding(receiver: &str) -> Scalar {
+    let digest: [u8; 64] = Sha512::digest(format!("b3-privacy-receiver-{}", receiver)).into();
+    Scalar::from_bytes_wide(&digest)
+}
+
+fn encode_scalar(value: &Scalar) -> String {
+    hex::encode(value.to_bytes())
+}
+
+// 解析十六进制编码的标量（32 字节小端）
+pub fn parse_scalar(value: &str) -> Result<Scalar, String> {
+    let bytes: [u8; 32] = hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("Invalid scalar: {}", value))?;
+    Option::from(Scalar::from_bytes(&bytes)).ok_or_else(|| format!("Scalar out of range: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+    use crate::blockchain::Blockchain;
+    use crate::consensus::ConsensusConfig;
+    use crate::shielded_pool::SHIELDED_SENDER;
+    use crate::transaction::{Transaction, TxPayload};
+    use crate::utxo::LedgerMode;
+
+    // 可信设置较慢，同一模块的测试共用一组参数
+    fn params() -> &'static Parameters<Bls12> {
+        static PARAMS: OnceLock<Parameters<Bls12>> = OnceLock::new();
+        PARAMS.get_or_init(|| setup().unwrap())
+    }
+
+    // 另一次独立的可信设置，模拟证明者自行生成的参数
+    fn other_params() -> &'static Parameters<Bls12> {
+        static PARAMS: OnceLock<Parameters<Bls12>> = OnceLock::new();
+        PARAMS.get_or_init(|| setup().unwrap())
+    }
+
+    // 不花费也不创建票据的隐私转账
+    fn empty_transfer(params: &Parameters<Bls12>) -> PrivacyTransaction {
+        let anchor = empty_nodes()[TREE_DEPTH].clone();
+        PrivacyTransaction::new(params, &ShieldedKey::generate(), &anchor, &[], &[], 0, "").unwrap()
+    }
+
+    // 只含一个票据的承诺树：返回树根和该票据的认证路径
+    fn single_leaf_tree(commitment: &str) -> (String, Vec<String>) {
+        let path = empty_nodes()[..TREE_DEPTH].to_vec();
+        let root = path.iter().try_fold(commitment.to_string(), |node, empty| merkle_parent(&node, empty)).unwrap();
+        (root, path)
+    }
+
+    #[test]
+    fn notes_commit_to_amount_and_owner() {
+        let key = ShieldedKey::generate();
+        let note = Note::new(&key.address().unwrap(), 10);
+        let commitment = note.commitment().unwrap();
+        assert_eq!(commitment, note_commitment(10, &note.note_key().unwrap(), &note.blinding).unwrap());
+        assert_ne!(commitment, note_commitment(11, &note.note_key().unwrap(), &note.blinding).unwrap());
+        assert_ne!(note.nullifier(&key).unwrap(), note.nullifier(&ShieldedKey::generate()).unwrap());
+        assert_eq!(empty_nodes()[1], merkle_parent(&empty_nodes()[0], &empty_nodes()[0]).unwrap());
+        assert!(parse_scalar("zz").is_err());
+        assert!(parse_scalar(&"ff".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn proofs_bind_the_public_value_and_receiver() {
+        let key = ShieldedKey::generate();
+        let address = key.address().unwrap();
+        let note = Note::new(&address, 10);
+        let (anchor, path) = single_leaf_tree(&note.commitment().unwrap());
+        let spend = SpendInput { note: note.clone(), position: 0, path };
+        let change = Note::new(&address, 7);
+        let transaction = PrivacyTransaction::new(params(), &key, &anchor, std::slice::from_ref(&spend), std::slice::from_ref(&change), 3, "bob").unwrap();
+        assert_eq!(transaction.nullifiers[0], note.nullifier(&key).unwrap());
+        assert_eq!(transaction.commitments[0], change.commitment().unwrap());
+
+        let keys = VerifyingKeys::parse(&[verifying_key(params())]).unwrap();
+        assert!(keys.verify_sequential(&[(&transaction, 3, "bob")]).is_ok());
+        assert_eq!(keys.verify_sequential(&[(&transaction, 4, "bob")]), Err(0));
+        assert_eq!(keys.verify_sequential(&[(&transaction, 3, "mallory")]), Err(0));
+        let mut tampered = transaction.clone();
+        tampered.commitments[0] = Note::new(&address, 7).commitment().unwrap();
+        assert_eq!(keys.verify_sequential(&[(&tampered, 3, "bob")]), Err(0));
+
+        // 金额不平衡或花费他人的票据时无法生成证明
+        assert!(PrivacyTransaction::new(params(), &key, &anchor, std::slice::from_ref(&spend), &[change], 4, "bob").is_err());
+        assert!(PrivacyTransaction::new(params(), &ShieldedKey::generate(), &anchor, &[spend], &[], 10, "bob").is_err());
+    }
+
+    #[test]
+    fn only_registered_verifying_keys_accept_proofs() {
+        let path = std::env::temp_dir().join(format!("privacy-params-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        write_parameters(params(), path).unwrap();
+        assert_eq!(verifying_key(&read_parameters(path).unwrap()), verifying_key(params()));
+        std::fs::remove_file(path).unwrap();
+        assert!(read_parameters(path).is_err());
+
+        assert!(VerifyingKeys::parse(&["zz".to_string()]).is_err());
+        assert!(VerifyingKeys::parse(&["00".repeat(8)]).is_err());
+        assert!(VerifyingKeys::parse(&[]).unwrap().is_empty());
+
+        // 用未登记的参数生成的证明无效，登记多个密钥时任意一个能验证即可
+        let transaction = empty_transfer(other_params());
+        let registered = VerifyingKeys::parse(&[verifying_key(params())]).unwrap();
+        assert_eq!(registered.verify_sequential(&[(&transaction, 0, "")]), Err(0));
+        let both = VerifyingKeys::parse(&[verifying_key(params()), verifying_key(other_params())]).unwrap();
+        assert!(both.verify_sequential(&[(&transaction, 0, "")]).is_ok());
+    }
+
+    #[test]
+    fn genesis_fixes_the_verifying_keys() {
+        assert!(Blockchain::with_genesis(1, LedgerMode::Account, vec!["zz".to_string()], ConsensusConfig::ProofOfWork, Vec::new()).is_err());
+        let mut transfer = Transaction::new_unsigned(SHIELDED_SENDER.to_string(), String::new(), 0);
+        transfer.payload = TxPayload::ShieldedTransfer(empty_transfer(params()));
+
+        // 没有登记验证密钥的链不接受隐私转账
+        let mut blockchain = Blockchain::new(1);
+        assert!(blockchain.add_transaction(transfer.clone()).is_err());
+
+        let vks = vec![verifying_key(other_params())];
+        let mut blockchain = Blockchain::with_genesis(1, LedgerMode::Account, vks, ConsensusConfig::ProofOfWork, Vec::new()).unwrap();
+        assert_ne!(blockchain.state_root(), Blockchain::new(1).state_root());
+        assert!(blockchain.add_transaction(transfer.clone()).is_err());
+
+        let vks = vec![verifying_key(params())];
+        let mut blockchain = Blockchain::with_genesis(1, LedgerMode::Account, vks, ConsensusConfig::ProofOfWork, Vec::new()).unwrap();
+        blockchain.add_transaction(transfer).unwrap();
+    }
+
+    #[test]
+    fn batch_verification_finds_the_first_invalid_proof() {
+        let keys = VerifyingKeys::parse(&[verifying_key(params())]).unwrap();
+        let transactions = [empty_transfer(params()), empty_transfer(params())];
+        let mut statements: Vec<ProofStatement> = transactions.iter().cycle().take(4).map(|transaction| (transaction, 0, "")).collect();
+        assert!(keys.verify_batch(&statements).is_ok());
+        assert!(keys.verify_batch(&[]).is_ok());
+
+        // 合并检查失败后逐个验证，返回第一个无效证明的下标
+        statements[2].2 = "mallory";
+        statements[3].1 = 1;
+        assert_eq!(keys.verify_batch(&statements), Err(2));
+        let mut malformed = transactions[0].clone();
+        malformed.proof = "00".to_string();
+        assert_eq!(keys.verify_batch(&[(&transactions[0], 0, ""), (&malformed, 0, "")]), Err(1));
 
-    // 验证隐私交易
-    pub fn verify(&self) -> bool {
-        verify_proof(&self.vk, &self.proof, &[]).is_ok()
+        // 证明来自不同的登记密钥时，逐个验证仍然接受
+        let other = empty_transfer(other_params());
+        let both = VerifyingKeys::parse(&[verifying_key(params()), verifying_key(other_params())]).unwrap();
+        assert!(both.verify_batch(&[(&transactions[0], 0, ""), (&other, 0, "")]).is_ok());
+        assert_eq!(keys.verify_batch(&[(&transactions[0], 0, ""), (&other, 0, "")]), Err(1));
     }
 }
@@ -0,0 +1,138 @@
+use serde::{Serialize, Deserialize};
+use crate::merkle_tree::MerkleTree;
+use crate::smart_contract::ContractEvent;
+
+// 交易执行状态
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ReceiptStatus {
+    Success,
+    Failed,
+}
+
+// 交易收据：记录交易执行的结果
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Receipt {
+    pub tx_id: String,
+    pub status: ReceiptStatus,
+    pub gas_used: u64,
+    pub fee: u64,            // 支付给出块者的手续费
+    pub return_data: String, // 合约返回值，部署交易为合约地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub logs: Vec<ContractEvent>, // 合约发出的事件
+}
+
+impl Receipt {
+    // 执行成功且不涉及合约的交易的收据
+    pub fn success(tx_id: String) -> Self {
+        Receipt {
+            tx_id,
+            status: ReceiptStatus::Success,
+            gas_used: 0,
+            fee: 0,
+            return_data: String::new(),
+            error: None,
+            logs: Vec::new(),
+        }
+    }
+}
+
+// 区块收据的默克尔根
+pub fn receipts_root(receipts: &[Receipt]) -> String {
+    let leaves = receipts.iter().map(|receipt| serde_json::to_string(receipt).unwrap()).collect();
+    hex::encode(MerkleTree::new(leaves).root_hash().unwrap())
+}
+
+// 事件过滤条件：合约地址为空时匹配所有合约，主题按位置匹配，None 为通配
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    pub contract_id: Option<String>,
+    pub topics: Vec<Option<String>>,
+}
+
+impl LogFilter {
+    // 事件是否满足过滤条件
+    pub fn matches(&self, log: &ContractEvent) -> bool {
+        if self.contract_id.as_ref().is_some_and(|contract_id| *contract_id != log.contract_id) {
+            return false;
+        }
+        self.topics.iter().enumerate().all(|(i, topic)| match topic {
+            Some(topic) => log.topics.get(i) == Some(topic),
+            None => i < log.topics.len(),
+        })
+    }
+}
+
+// 匹配过滤条件的事件及其所在的交易
+#[derive(Serialize, Debug, Clone)]
+pub struct LogEntry<'a> {
+    pub block_index: u64,
+    pub tx_id: &'a str,
+    pub log: &'a ContractEvent,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::smart_contract::{self, ContractKind};
+    use crate::vm;
+    use crate::wallet::Wallet;
+
+    fn log(contract_id: &str, topics: &[&str]) -> ContractEvent {
+        ContractEvent { contract_id: contract_id.to_string(), topics: topics.iter().map(|t| t.to_string()).collect(), data: String::new() }
+    }
+
+    #[test]
+    fn filters_match_contract_and_topics_by_position() {
+        let event = log("c1", &["Transfer", "alice"]);
+        assert!(LogFilter::default().matches(&event));
+        assert!(LogFilter { contract_id: Some("c1".to_string()), topics: vec![Some("Transfer".to_string())] }.matches(&event));
+        assert!(!LogFilter { contract_id: Some("c2".to_string()), topics: Vec::new() }.matches(&event));
+        assert!(LogFilter { contract_id: None, topics: vec![None, Some("alice".to_string())] }.matches(&event));
+        assert!(!LogFilter { contract_id: None, topics: vec![Some("alice".to_string())] }.matches(&event));
+        // 通配符要求该位置存在主题
+        assert!(!LogFilter { contract_id: None, topics: vec![None, None, None] }.matches(&event));
+    }
+
+    #[test]
+    fn receipts_root_commits_to_every_receipt() {
+        let receipts = vec![Receipt::success("a".to_string()), Receipt::success("b".to_string())];
+        let root = receipts_root(&receipts);
+        let mut changed = receipts.clone();
+        changed[1].logs.push(log("c1", &["set"]));
+        assert_ne!(receipts_root(&changed), root);
+        changed[1].logs.clear();
+        changed[1].status = ReceiptStatus::Failed;
+        assert_ne!(receipts_root(&changed), root);
+        assert_eq!(receipts_root(&receipts), root);
+    }
+
+    #[test]
+    fn logs_are_queried_from_committed_receipts() {
+        let alice = Wallet::new();
+        let mut blockchain = Blockchain::new(1);
+        blockchain.add_block(Vec::new(), &alice.address());
+        let code = hex::encode(vm::assemble(include_str!("../contracts/kv_store.asm")).unwrap());
+        let mut deploy = smart_contract::deploy_transaction(&alice.address(), ContractKind::Bytecode, code, 0);
+        deploy.sign(&alice.key_pair);
+        let contract_id = deploy.id();
+        blockchain.produce_block(vec![deploy], "miner", None).unwrap();
+        for key in ["a", "b"] {
+            let mut call = smart_contract::call_transaction(&alice.address(), &contract_id, "set", vec![key.to_string(), "1".to_string()], 0, 5_000);
+            call.sign(&alice.key_pair);
+            blockchain.produce_block(vec![call], "miner", None).unwrap();
+        }
+
+        let filter = LogFilter { contract_id: Some(contract_id.clone()), topics: vec![Some("set".to_string()), Some("b".to_string())] };
+        let logs = blockchain.get_logs(&filter, 0, 10);
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].block_index, 4);
+        assert_eq!(blockchain.get_receipt(logs[0].tx_id).unwrap().logs[0].topics[1], "b");
+        assert_eq!(blockchain.get_logs(&LogFilter::default(), 0, 3).len(), 1);
+
+        // 断开区块后其中的事件不再能查询到
+        blockchain.disconnect_latest_block().unwrap();
+        assert!(blockchain.get_logs(&filter, 0, 10).is_empty());
+    }
+}
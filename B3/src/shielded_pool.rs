@@ -0,0 +1,207 @@
+use std::collections::BTreeSet;
+use serde::{Serialize, Deserialize};
+use crate::privacy::{self, PrivacyTransaction, TREE_DEPTH};
+
+// 隐私转账交易的发送方：资金来自隐私池，由零知识证明授权而不是签名
+pub const SHIELDED_SENDER: &str = "shielded";
+
+// 隐私池：存入的资金以票据承诺的形式保存在增量默克尔树中，花费票据时记录其作废标识
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShieldedPool {
+    pub commitments: Vec<String>,      // 票据承诺（树的叶子，按加入顺序）
+    pub frontier: Vec<Option<String>>, // 每一层最右侧尚未配对的左孩子
+    pub root: String,                  // 当前树根
+    pub anchors: Vec<String>,          // 历史上出现过的树根，隐私转账可以基于其中任意一个
+    pub nullifiers: BTreeSet<String>,  // 已花费票据的作废标识
+    pub value: u64,                    // 池中资金总额
+}
+
+impl Default for ShieldedPool {
+    fn default() -> Self {
+        let root = privacy::empty_nodes()[TREE_DEPTH].clone();
+        ShieldedPool {
+            commitments: Vec::new(),
+            frontier: vec![None; TREE_DEPTH],
+            root: root.clone(),
+            anchors: vec![root],
+            nullifiers: BTreeSet::new(),
+            value: 0,
+        }
+    }
+}
+
+impl ShieldedPool {
+    // 追加票据承诺并更新树根：只需沿新叶子到根的路径计算 TREE_DEPTH 次哈希
+    pub fn append(&mut self, commitment: &str) -> Result<(), String> {
+        let position = self.commitments.len();
+        if position >= 1 << TREE_DEPTH {
+            return Err("Note commitment tree is full".to_string());
+        }
+        let mut node = commitment.to_string();
+        for (level, empty) in privacy::empty_nodes().iter().enumerate().take(TREE_DEPTH) {
+            node = if (position >> level) & 1 == 0 {
+                let parent = privacy::merkle_parent(&node, empty)?;
+                self.frontier[level] = Some(node);
+                parent
+            } else {
+                let left = self.frontier[level].as_deref().unwrap_or(empty);
+                privacy::merkle_parent(left, &node)?
+            };
+        }
+        self.commitments.push(commitment.to_string());
+        self.root = node;
+        self.anchors.push(self.root.clone());
+        Ok(())
+    }
+
+    // 票据承诺在树中的位置
+    pub fn position(&self, commitment: &str) -> Option<u64> {
+        self.commitments.iter().position(|existing| existing == commitment).map(|position| position as u64)
+    }
+
+    // 位置 position 的认证路径（从叶子向上的兄弟节点），基于当前树根
+    pub fn path(&self, position: u64) -> Result<Vec<String>, String> {
+        let mut index = position as usize;
+        if index >= self.commitments.len() {
+            return Err("Note commitment not found".to_string());
+        }
+        let mut nodes = self.commitments.clone();
+        let mut path = Vec::with_capacity(TREE_DEPTH);
+        for empty in privacy::empty_nodes().iter().take(TREE_DEPTH) {
+            path.push(nodes.get(index ^ 1).unwrap_or(empty).clone());
+            nodes = nodes
+                .chunks(2)
+                .map(|pair| privacy::merkle_parent(&pair[0], pair.get(1).unwrap_or(empty)))
+                .collect::<Result<_, _>>()?;
+            index >>= 1;
+        }
+        Ok(path)
+    }
+
+    // 检查隐私转账：基于已知的树根，作废标识互不相同且未被使用，转出金额不超过池中资金
+    pub fn check_transfer(&self, transaction: &PrivacyTransaction, public_value: u64) -> Result<(), String> {
+        if !self.anchors.contains(&transaction.anchor) {
+            return Err("Unknown note commitment tree anchor".to_string());
+        }
+        let nullifiers: BTreeSet<&String> = transaction.nullifiers.iter().collect();
+        if nullifiers.len() != transaction.nullifiers.len() {
+            return Err("Duplicate nullifier in shielded transfer".to_string());
+        }
+        if transaction.nullifiers.iter().any(|nullifier| self.nullifiers.contains(nullifier)) {
+            return Err("Note already spent".to_string());
+        }
+        if public_value > self.value {
+            return Err("Shielded pool has insufficient funds".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::OnceLock;
+    use bellman::groth16::Parameters;
+    use bls12_381::Bls12;
+    use crate::blockchain::Blockchain;
+    use crate::consensus::ConsensusConfig;
+    use crate::privacy::{Note, ShieldedKey, SpendInput};
+    use crate::transaction::{Transaction, TxPayload};
+    use crate::utxo::LedgerMode;
+    use crate::wallet::Wallet;
+
+    fn params() -> &'static Parameters<Bls12> {
+        static PARAMS: OnceLock<Parameters<Bls12>> = OnceLock::new();
+        PARAMS.get_or_init(|| privacy::setup().unwrap())
+    }
+
+    // 从叶子沿认证路径计算树根
+    fn root_from_path(commitment: &str, position: u64, path: &[String]) -> String {
+        path.iter().enumerate().fold(commitment.to_string(), |node, (level, sibling)| {
+            if (position >> level) & 1 == 0 {
+                privacy::merkle_parent(&node, sibling).unwrap()
+            } else {
+                privacy::merkle_parent(sibling, &node).unwrap()
+            }
+        })
+    }
+
+    fn unproven_transfer(anchor: &str, nullifiers: [&str; 2]) -> PrivacyTransaction {
+        PrivacyTransaction {
+            anchor: anchor.to_string(),
+            nullifiers: nullifiers.iter().map(|nullifier| nullifier.to_string()).collect(),
+            commitments: Vec::new(),
+            proof: String::new(),
+        }
+    }
+
+    #[test]
+    fn appended_commitments_have_paths_to_the_current_root() {
+        let mut pool = ShieldedPool::default();
+        let commitments: Vec<String> = (1..=3).map(|amount| Note::new(&ShieldedKey::generate().address().unwrap(), amount).commitment().unwrap()).collect();
+        for commitment in &commitments {
+            pool.append(commitment).unwrap();
+        }
+        assert_eq!(pool.anchors.len(), 4);
+        for commitment in &commitments {
+            let position = pool.position(commitment).unwrap();
+            assert_eq!(root_from_path(commitment, position, &pool.path(position).unwrap()), pool.root);
+        }
+        assert!(pool.path(3).is_err());
+    }
+
+    #[test]
+    fn transfers_need_a_known_anchor_and_unspent_nullifiers() {
+        let mut pool = ShieldedPool { value: 10, ..ShieldedPool::default() };
+        pool.nullifiers.insert("spent".to_string());
+        let anchor = pool.root.clone();
+        assert!(pool.check_transfer(&unproven_transfer(&anchor, ["a", "b"]), 10).is_ok());
+        assert!(pool.check_transfer(&unproven_transfer("unknown", ["a", "b"]), 0).is_err());
+        assert!(pool.check_transfer(&unproven_transfer(&anchor, ["a", "a"]), 0).is_err());
+        assert!(pool.check_transfer(&unproven_transfer(&anchor, ["a", "spent"]), 0).is_err());
+        assert!(pool.check_transfer(&unproven_transfer(&anchor, ["a", "b"]), 11).is_err());
+    }
+
+    #[test]
+    fn shielded_funds_are_spent_once_and_restored_on_disconnect() {
+        let alice = Wallet::new();
+        let vks = vec![privacy::verifying_key(params())];
+        let mut blockchain = Blockchain::with_genesis(1, LedgerMode::Account, vks, ConsensusConfig::ProofOfWork, Vec::new()).unwrap();
+        blockchain.add_block(Vec::new(), &alice.address());
+
+        // 存入隐私池
+        let key = ShieldedKey::generate();
+        let note = Note::new(&key.address().unwrap(), 10);
+        let mut shield = Transaction::new_unsigned(alice.address(), String::new(), 10);
+        shield.payload = TxPayload::Shield { note_key: note.note_key().unwrap(), blinding: note.blinding.clone() };
+        shield.sign(&alice.key_pair);
+        blockchain.produce_block(vec![shield], "miner", None).unwrap();
+        assert_eq!(blockchain.shielded_pool.value, 10);
+        let before = blockchain.shielded_pool.clone();
+
+        // 花费票据：转出 4 给 bob，找零 6 留在池中
+        let pool = &blockchain.shielded_pool;
+        let position = pool.position(&note.commitment().unwrap()).unwrap();
+        let spend = SpendInput { note: note.clone(), position, path: pool.path(position).unwrap() };
+        let change = Note::new(&key.address().unwrap(), 6);
+        let transaction = PrivacyTransaction::new(params(), &key, &pool.root, &[spend], &[change], 4, "bob").unwrap();
+        let mut unshield = Transaction::new_unsigned(SHIELDED_SENDER.to_string(), "bob".to_string(), 4);
+        unshield.payload = TxPayload::ShieldedTransfer(transaction);
+
+        // 改变转出金额或接收方会使证明失效
+        let mut redirected = unshield.clone();
+        redirected.receiver = "mallory".to_string();
+        assert!(blockchain.produce_block(vec![redirected], "miner", None).is_err());
+
+        blockchain.produce_block(vec![unshield.clone()], "miner", None).unwrap();
+        assert_eq!(blockchain.get_balance("bob"), 4);
+        assert_eq!(blockchain.shielded_pool.value, 6);
+        assert!(blockchain.shielded_pool.nullifiers.contains(&note.nullifier(&key).unwrap()));
+        assert!(blockchain.add_transaction(unshield).is_err());
+        assert!(blockchain.is_chain_valid());
+
+        blockchain.disconnect_latest_block().unwrap();
+        assert_eq!(blockchain.shielded_pool, before);
+        assert_eq!(blockchain.get_balance("bob"), 0);
+    }
+}
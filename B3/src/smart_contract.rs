@@ -1,41 +1,181 @@
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
+use crate::vm::{self, CallContext, ExecutionResult, VmError};
+use crate::wasm_runtime;
+use crate::token::{self, TokenMetadata};
+use crate::transaction::{Transaction, TxPayload};
+
+pub const DEPLOY_GAS_PER_BYTE: u64 = 10; // 部署合约时每字节代码消耗的燃料
+pub const GAS_PER_COIN: u64 = 1000;      // 每个币可以购买的燃料
+pub const MAX_GAS_LIMIT: u64 = 10_000_000; // 单笔调用的燃料上限
+
+// 合约执行后产生的事件
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContractEvent {
+    pub contract_id: String,
+    pub topics: Vec<String>, // 事件主题
+    pub data: String,        // 事件数据
+}
+
+// 合约代码的类型
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ContractKind {
+    #[default]
+    Bytecode, // 栈式虚拟机字节码
+    Wasm,     // WebAssembly 模块
+    Token,    // 内置同质化代币，代码为代币元数据（JSON）
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SmartContract {
-    pub code: String, // 合约代码
+    #[serde(default)]
+    pub kind: ContractKind,
+    pub code: String, // 合约代码（十六进制，代币合约为元数据 JSON）
     pub state: HashMap<String, String>, // 合约状态
 }
 
 impl SmartContract {
-    // 创建一个新的智能合约
+    // 创建一个新的智能合约，code 为十六进制字节码
+    #[allow(dead_code)]
     pub fn new(code: String) -> Self {
+        Self::with_kind(ContractKind::Bytecode, code)
+    }
+
+    // 创建指定类型的智能合约
+    pub fn with_kind(kind: ContractKind, code: String) -> Self {
         SmartContract {
+            kind,
             code,
             state: HashMap::new(),
         }
     }
 
-    // 执行合约方法
-    pub fn execute(&mut self, method: &str, args: Vec<String>) -> Result<String, String> {
-        match method {
-            "set" => {
-                if args.len() != 2 {
-                    return Err("Invalid arguments for 'set'".to_string());
-                }
-                self.state.insert(args[0].clone(), args[1].clone());
-                Ok("Value set successfully".to_string())
-            }
-            "get" => {
-                if args.len() != 1 {
-                    return Err("Invalid arguments for 'get'".to_string());
-                }
-                match self.state.get(&args[0]) {
-                    Some(value) => Ok(value.clone()),
-                    None => Err("Key not found".to_string()),
-                }
-            }
-            _ => Err("Unknown method".to_string()),
+    // 校验合约代码
+    pub fn validate(&self) -> Result<(), String> {
+        if self.kind == ContractKind::Token {
+            return TokenMetadata::parse(&self.code).map(|_| ());
+        }
+        let code = hex::decode(&self.code).map_err(|_| "Contract code is not hex-encoded".to_string())?;
+        match self.kind {
+            ContractKind::Bytecode => vm::decode(&code).map(|_| ()),
+            ContractKind::Wasm => wasm_runtime::validate(&code),
+            ContractKind::Token => unreachable!(),
         }
     }
+
+    // 执行合约方法，失败时合约状态保持不变
+    pub fn execute(&mut self, context: &CallContext) -> Result<ExecutionResult, VmError> {
+        let mut state = self.state.clone();
+        let result = if self.kind == ContractKind::Token {
+            token::execute(&self.code, &mut state, context)?
+        } else {
+            let code = hex::decode(&self.code).map_err(|_| VmError { message: "Contract code is not hex-encoded".to_string(), gas_used: 0 })?;
+            match self.kind {
+                ContractKind::Bytecode => vm::execute(&code, &mut state, context)?,
+                ContractKind::Wasm => wasm_runtime::execute(&code, &mut state, context)?,
+                ContractKind::Token => unreachable!(),
+            }
+        };
+        self.state = state;
+        Ok(result)
+    }
+}
+
+// 部署合约消耗的燃料：按代码字节数计算
+pub fn deploy_gas(kind: ContractKind, code: &str) -> u64 {
+    let bytes = match kind {
+        ContractKind::Token => code.len(),
+        _ => code.len() / 2,
+    };
+    bytes as u64 * DEPLOY_GAS_PER_BYTE
+}
+
+// 燃料对应的手续费（向上取整）
+pub fn gas_fee(gas: u64) -> u64 {
+    gas.div_ceil(GAS_PER_COIN)
+}
+
+// 创建部署合约的交易（未签名），合约地址即交易 ID
+pub fn deploy_transaction(sender: &str, kind: ContractKind, code: String, amount: u64) -> Transaction {
+    let mut tx = Transaction::new_unsigned(sender.to_string(), String::new(), amount);
+    tx.payload = TxPayload::Deploy { kind, code };
+    tx
+}
+
+// 创建调用合约的交易（未签名）
+pub fn call_transaction(sender: &str, contract_id: &str, method: &str, args: Vec<String>, amount: u64, gas_limit: u64) -> Transaction {
+    let mut tx = Transaction::new_unsigned(sender.to_string(), contract_id.to_string(), amount);
+    tx.payload = TxPayload::Call { method: method.to_string(), args, gas_limit };
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::receipt::ReceiptStatus;
+    use crate::wallet::Wallet;
+
+    fn signed(mut tx: Transaction, wallet: &Wallet) -> Transaction {
+        tx.sign(&wallet.key_pair);
+        tx
+    }
+
+    #[test]
+    fn fees_follow_deploy_size_and_gas() {
+        assert_eq!(deploy_gas(ContractKind::Bytecode, "00ff"), 2 * DEPLOY_GAS_PER_BYTE);
+        assert_eq!(deploy_gas(ContractKind::Token, "{}"), 2 * DEPLOY_GAS_PER_BYTE);
+        assert_eq!(gas_fee(0), 0);
+        assert_eq!(gas_fee(1), 1);
+        assert_eq!(gas_fee(GAS_PER_COIN + 1), 2);
+        assert!(SmartContract::new("zz".to_string()).validate().is_err());
+        assert!(SmartContract::new("ee".to_string()).validate().is_err());
+    }
+
+    #[test]
+    fn calls_charge_used_gas_and_roll_back_on_failure() {
+        let alice = Wallet::new();
+        let mut blockchain = Blockchain::new(1);
+        blockchain.add_block(Vec::new(), &alice.address());
+        let code = hex::encode(vm::assemble(include_str!("../contracts/kv_store.asm")).unwrap());
+        let deploy = signed(deploy_transaction(&alice.address(), ContractKind::Bytecode, code, 5), &alice);
+        let contract_id = deploy.id();
+        blockchain.produce_block(vec![deploy.clone()], "miner", None).unwrap();
+        assert_eq!(blockchain.get_balance(&contract_id), 5);
+        let receipt = blockchain.get_receipt(&contract_id).unwrap();
+        assert_eq!((&receipt.return_data, receipt.fee), (&contract_id, deploy.max_fee()));
+
+        // 成功的调用：转入金额进入合约，只按实际消耗的燃料收费
+        let args = vec!["color".to_string(), "blue".to_string()];
+        let set = signed(call_transaction(&alice.address(), &contract_id, "set", args, 3, 10_000), &alice);
+        let before = blockchain.get_balance(&alice.address());
+        blockchain.produce_block(vec![set.clone()], "miner", None).unwrap();
+        let receipt = blockchain.get_receipt(&set.id()).unwrap().clone();
+        assert_eq!(receipt.status, ReceiptStatus::Success);
+        assert!(receipt.fee < set.max_fee());
+        assert_eq!(blockchain.get_balance(&alice.address()), before - 3 - receipt.fee);
+        assert_eq!(blockchain.get_balance(&contract_id), 8);
+        assert_eq!(blockchain.contracts[&contract_id].state["color"], "blue");
+
+        // 失败的调用：状态和转入金额回滚，手续费照收
+        let fail = signed(call_transaction(&alice.address(), &contract_id, "other", Vec::new(), 3, 10_000), &alice);
+        let before = blockchain.get_balance(&alice.address());
+        blockchain.produce_block(vec![fail.clone()], "miner", None).unwrap();
+        let receipt = blockchain.get_receipt(&fail.id()).unwrap().clone();
+        assert_eq!(receipt.status, ReceiptStatus::Failed);
+        assert!(receipt.error.unwrap().starts_with("Reverted"));
+        assert_eq!(blockchain.get_balance(&alice.address()), before - receipt.fee);
+        assert_eq!(blockchain.get_balance(&contract_id), 8);
+
+        // 只读查询不修改状态
+        let query = blockchain.query_contract(&contract_id, "bob", "get", vec!["color".to_string()], 10_000).unwrap();
+        assert_eq!(query.return_value, "blue");
+
+        // 断开区块时合约状态随其它状态恢复
+        blockchain.disconnect_latest_block().unwrap();
+        blockchain.disconnect_latest_block().unwrap();
+        assert!(!blockchain.contracts[&contract_id].state.contains_key("color"));
+        assert_eq!(blockchain.get_balance(&contract_id), 5);
+        assert!(blockchain.is_chain_valid());
+    }
 }
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+// 稀疏默克尔树：以键的 SHA-256 作为 256 位路径（高位在前），叶子为 H(0x00 || 路径 || H(值))，
+// 内部节点为 H(0x01 || 左 || 右)，空叶子为全零，空子树的哈希可以预先计算，因此只需保存非空叶子。
+
+const TREE_DEPTH: usize = 256;
+
+type Hash = [u8; 32];
+
+// 状态树：状态键 -> 值
+#[derive(Debug, Clone)]
+pub struct StateTree {
+    leaves: BTreeMap<Hash, Hash>, // 路径 -> 叶子哈希
+    defaults: Vec<Hash>,          // 高度为 h 的空子树的哈希
+}
+
+// 单个状态键的默克尔证明，value 为 None 时证明该键不存在
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateProof {
+    pub key: String,
+    pub value: Option<String>,
+    pub bitmap: String,        // 第 d 位为 1 表示深度 d 的兄弟节点不是空子树（十六进制）
+    pub siblings: Vec<String>, // 非空兄弟节点的哈希，从根向叶子排列
+}
+
+impl StateTree {
+    // 从状态键值构建状态树
+    pub fn new(entries: &BTreeMap<String, String>) -> Self {
+        let leaves = entries
+            .iter()
+            .map(|(key, value)| {
+                let path = key_path(key);
+                (path, hash_leaf(&path, value))
+            })
+            .collect();
+        StateTree { leaves, defaults: default_hashes() }
+    }
+
+    // 状态根（十六进制）
+    pub fn root(&self) -> String {
+        let leaves: Vec<(Hash, Hash)> = self.leaves.iter().map(|(path, leaf)| (*path, *leaf)).collect();
+        hex::encode(self.subtree(&leaves, TREE_DEPTH))
+    }
+
+    // 生成状态键的证明，value 为该键当前的值
+    pub fn prove(&self, key: &str, value: Option<&str>) -> StateProof {
+        let path = key_path(key);
+        let mut leaves: Vec<(Hash, Hash)> = self.leaves.iter().map(|(path, leaf)| (*path, *leaf)).collect();
+        let mut bitmap = [0u8; 32];
+        let mut siblings = Vec::new();
+        for depth in 0..TREE_DEPTH {
+            let split = leaves.partition_point(|(leaf_path, _)| !bit(leaf_path, depth));
+            let (left, right) = leaves.split_at(split);
+            let (sibling, next) = if bit(&path, depth) { (left, right) } else { (right, left) };
+            let sibling = self.subtree(sibling, TREE_DEPTH - depth - 1);
+            if sibling != self.defaults[TREE_DEPTH - depth - 1] {
+                bitmap[depth / 8] |= 0x80 >> (depth % 8);
+                siblings.push(hex::encode(sibling));
+            }
+            leaves = next.to_vec();
+        }
+        StateProof {
+            key: key.to_string(),
+            value: value.map(str::to_string),
+            bitmap: hex::encode(bitmap),
+            siblings,
+        }
+    }
+
+    // 计算高度为 height 的子树的哈希，leaves 按路径排序且都位于该子树中
+    fn subtree(&self, leaves: &[(Hash, Hash)], height: usize) -> Hash {
+        match leaves {
+            [] => self.defaults[height],
+            [(_, leaf)] if height == 0 => *leaf,
+            _ => {
+                let depth = TREE_DEPTH - height;
+                let split = leaves.partition_point(|(path, _)| !bit(path, depth));
+                let left = self.subtree(&leaves[..split], height - 1);
+                let right = self.subtree(&leaves[split..], height - 1);
+                hash_node(&left, &right)
+            }
+        }
+    }
+}
+
+impl StateProof {
+    // 根据证明重新计算状态根，并与给定的状态根比较
+    pub fn verify(&self, root: &str) -> bool {
+        let Ok(bitmap) = hex::decode(&self.bitmap) else {
+            return false;
+        };
+        if bitmap.len() != 32 {
+            return false;
+        }
+        let defaults = default_hashes();
+        let path = key_path(&self.key);
+        let mut node = match &self.value {
+            Some(value) => hash_leaf(&path, value),
+            None => defaults[0],
+        };
+        let mut siblings = self.siblings.iter().rev();
+        for depth in (0..TREE_DEPTH).rev() {
+            let sibling = if bit(&bitmap, depth) {
+                match siblings.next().and_then(|sibling| hex::decode(sibling).ok()).and_then(|bytes| Hash::try_from(bytes).ok()) {
+                    Some(sibling) => sibling,
+                    None => return false,
+                }
+            } else {
+                defaults[TREE_DEPTH - depth - 1]
+            };
+            node = if bit(&path, depth) { hash_node(&sibling, &node) } else { hash_node(&node, &sibling) };
+        }
+        siblings.next().is_none() && hex::encode(node) == root
+    }
+}
+
+// 空子树的哈希：高度 0 为空叶子，之后逐层向上计算
+fn default_hashes() -> Vec<Hash> {
+    let mut defaults = vec![[0u8; 32]];
+    for height in 0..TREE_DEPTH {
+        defaults.push(hash_node(&defaults[height], &defaults[height]));
+    }
+    defaults
+}
+
+fn key_path(key: &str) -> Hash {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+// 路径中第 depth 位（高位在前）
+fn bit(path: &[u8], depth: usize) -> bool {
+    path[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+fn hash_leaf(path: &Hash, value: &str) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8]);
+    hasher.update(path);
+    hasher.update(Sha256::digest(value.as_bytes()));
+    hasher.finalize().into()
+}
+
+fn hash_node(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+
+    fn entries(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn root_depends_only_on_the_entries() {
+        let empty = StateTree::new(&BTreeMap::new()).root();
+        assert_eq!(empty, hex::encode(default_hashes()[TREE_DEPTH]));
+        let one = StateTree::new(&entries(&[("a", "1")])).root();
+        let two = StateTree::new(&entries(&[("a", "1"), ("b", "2")])).root();
+        assert_ne!(empty, one);
+        assert_ne!(one, two);
+        assert_ne!(one, StateTree::new(&entries(&[("a", "2")])).root());
+        assert_eq!(two, StateTree::new(&entries(&[("b", "2"), ("a", "1")])).root());
+    }
+
+    #[test]
+    fn inclusion_and_exclusion_proofs_verify_against_the_root() {
+        let state = entries(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        let tree = StateTree::new(&state);
+        let root = tree.root();
+        for (key, value) in &state {
+            assert!(tree.prove(key, Some(value)).verify(&root));
+        }
+        assert!(tree.prove("missing", None).verify(&root));
+
+        // 错误的值、伪造的不存在证明和被篡改的兄弟节点都无法通过验证
+        assert!(!tree.prove("a", Some("9")).verify(&root));
+        assert!(!tree.prove("a", None).verify(&root));
+        assert!(!tree.prove("missing", Some("1")).verify(&root));
+        let mut proof = tree.prove("b", Some("2"));
+        proof.siblings.pop();
+        assert!(!proof.verify(&root));
+        let mut proof = tree.prove("b", Some("2"));
+        proof.bitmap = "00".to_string();
+        assert!(!proof.verify(&root));
+    }
+
+    #[test]
+    fn blocks_commit_to_the_state_after_execution() {
+        let mut blockchain = Blockchain::new(1);
+        blockchain.add_block(Vec::new(), "alice");
+        let root = blockchain.state_root();
+        assert_eq!(blockchain.chain.last().unwrap().header.state_root, root);
+
+        let proof = blockchain.get_state_proof("account:alice");
+        assert_eq!(proof.value.as_deref(), Some("50"));
+        assert!(proof.verify(&root));
+        let proof = blockchain.get_state_proof("account:bob");
+        assert_eq!(proof.value, None);
+        assert!(proof.verify(&root));
+
+        // 状态根不匹配的区块使链无效
+        blockchain.chain.last_mut().unwrap().header.state_root = StateTree::new(&BTreeMap::new()).root();
+        assert!(!blockchain.is_chain_valid());
+    }
+}
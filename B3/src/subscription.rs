@@ -0,0 +1,133 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::block::Block;
+use crate::transaction::Transaction;
+use crate::smart_contract::ContractEvent;
+use crate::receipt::LogEntry;
+
+// 区块链对外推送的事件
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ChainEvent {
+    NewHead(Block),                   // 新区块上链
+    PendingTransaction(Transaction),  // 交易进入交易池
+    // 已上链区块的收据中的合约事件，附带所在区块高度和交易 ID
+    ContractEvent { block_index: u64, tx_id: String, log: ContractEvent },
+}
+
+// 事件总线：区块链在关键位置发布事件，订阅者通过通道接收
+#[derive(Debug, Default, Clone)]
+pub struct EventBus {
+    listeners: Arc<Mutex<Vec<Sender<ChainEvent>>>>,
+}
+
+impl EventBus {
+    // 注册一个新的监听者
+    pub fn subscribe(&self) -> Receiver<ChainEvent> {
+        let (sender, receiver) = channel();
+        self.listeners.lock().unwrap().push(sender);
+        receiver
+    }
+
+    // 发布事件，同时清理已经断开的监听者
+    pub fn publish(&self, event: ChainEvent) {
+        let mut listeners = self.listeners.lock().unwrap();
+        listeners.retain(|listener| listener.send(event.clone()).is_ok());
+    }
+}
+
+// WebSocket 客户端可以订阅的频道
+#[derive(Debug, Clone, PartialEq)]
+pub enum Subscription {
+    NewHeads,
+    PendingTransactions,
+    Address(String),
+    ContractEvents(String),
+}
+
+impl Subscription {
+    // 从 subscribe 请求的参数解析订阅频道
+    pub fn from_params(params: &[Value]) -> Result<Self, String> {
+        let channel = params.first().and_then(Value::as_str).ok_or("Missing subscription channel")?;
+        let argument = params.get(1).and_then(Value::as_str).map(str::to_string);
+        match (channel, argument) {
+            ("newHeads", _) => Ok(Subscription::NewHeads),
+            ("pendingTransactions", _) => Ok(Subscription::PendingTransactions),
+            ("address", Some(address)) => Ok(Subscription::Address(address)),
+            ("contractEvents", Some(contract_id)) => Ok(Subscription::ContractEvents(contract_id)),
+            ("address", None) | ("contractEvents", None) => Err(format!("Channel '{}' requires an argument", channel)),
+            _ => Err(format!("Unknown subscription channel '{}'", channel)),
+        }
+    }
+
+    // 如果事件属于该订阅，返回需要推送给客户端的内容
+    pub fn filter(&self, event: &ChainEvent) -> Option<Value> {
+        match (self, event) {
+            (Subscription::NewHeads, ChainEvent::NewHead(block)) => serde_json::to_value(block).ok(),
+            (Subscription::PendingTransactions, ChainEvent::PendingTransaction(tx)) => serde_json::to_value(tx).ok(),
+            (Subscription::Address(address), ChainEvent::PendingTransaction(tx)) => {
+                if tx.involves(address) {
+                    serde_json::to_value(tx).ok()
+                } else {
+                    None
+                }
+            }
+            (Subscription::Address(address), ChainEvent::NewHead(block)) => {
+                let transactions: Vec<&Transaction> = block.transactions.iter().filter(|tx| tx.involves(address)).collect();
+                if transactions.is_empty() {
+                    None
+                } else {
+                    serde_json::to_value(transactions).ok()
+                }
+            }
+            // 推送内容与 get_logs 的结果格式相同
+            (Subscription::ContractEvents(contract_id), ChainEvent::ContractEvent { block_index, tx_id, log }) => {
+                if &log.contract_id == contract_id {
+                    serde_json::to_value(LogEntry { block_index: *block_index, tx_id, log }).ok()
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn channels_are_parsed_from_params() {
+        assert_eq!(Subscription::from_params(&[json!("newHeads")]), Ok(Subscription::NewHeads));
+        assert_eq!(Subscription::from_params(&[json!("address"), json!("alice")]), Ok(Subscription::Address("alice".to_string())));
+        assert!(Subscription::from_params(&[json!("contractEvents")]).is_err());
+        assert!(Subscription::from_params(&[json!("other")]).is_err());
+        assert!(Subscription::from_params(&[]).is_err());
+    }
+
+    #[test]
+    fn contract_events_carry_their_receipt_context() {
+        let log = ContractEvent { contract_id: "c1".to_string(), topics: vec!["set".to_string()], data: "v".to_string() };
+        let event = ChainEvent::ContractEvent { block_index: 7, tx_id: "t1".to_string(), log };
+        let payload = Subscription::ContractEvents("c1".to_string()).filter(&event).unwrap();
+        assert_eq!(payload, json!({
+            "block_index": 7,
+            "tx_id": "t1",
+            "log": { "contract_id": "c1", "topics": ["set"], "data": "v" },
+        }));
+        assert_eq!(Subscription::ContractEvents("c2".to_string()).filter(&event), None);
+        assert_eq!(Subscription::NewHeads.filter(&event), None);
+    }
+
+    #[test]
+    fn address_subscriptions_match_pending_transactions() {
+        let tx = Transaction::new_unsigned("alice".to_string(), "bob".to_string(), 5);
+        let event = ChainEvent::PendingTransaction(tx);
+        assert!(Subscription::Address("bob".to_string()).filter(&event).is_some());
+        assert!(Subscription::Address("carol".to_string()).filter(&event).is_none());
+        assert!(Subscription::PendingTransactions.filter(&event).is_some());
+    }
+}
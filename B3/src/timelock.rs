@@ -0,0 +1,122 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+// 锁定条件：在指定区块高度或时间戳之前资金不可花费
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum LockTime {
+    Height(u64),
+    Time(i64),
+}
+
+impl LockTime {
+    // 在给定高度和时间的区块中是否已解锁
+    pub fn is_unlocked(&self, height: u64, timestamp: i64) -> bool {
+        match *self {
+            LockTime::Height(lock_height) => height >= lock_height,
+            LockTime::Time(lock_time) => timestamp >= lock_time,
+        }
+    }
+}
+
+impl fmt::Display for LockTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockTime::Height(height) => write!(f, "height:{}", height),
+            LockTime::Time(timestamp) => write!(f, "time:{}", timestamp),
+        }
+    }
+}
+
+// 解析 "height:<高度>" 或 "time:<时间戳>"
+impl FromStr for LockTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid lock '{}', expected 'height:<n>' or 'time:<unix timestamp>'", s);
+        match s.split_once(':') {
+            Some(("height", value)) => value.parse().map(LockTime::Height).map_err(|_| invalid()),
+            Some(("time", value)) => value.parse().map(LockTime::Time).map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// 账户模式下被锁定的一笔资金
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LockedFunds {
+    pub tx_id: String,
+    pub amount: u64,
+    pub lock_until: LockTime,
+}
+
+// 归属计划：将总额平均分成若干期，第 i 期在 start + i * interval 解锁，余数计入最后一期
+pub fn vesting_schedule(total: u64, installments: u64, start: LockTime, interval: u64) -> Vec<(u64, LockTime)> {
+    if installments == 0 {
+        return Vec::new();
+    }
+    let per_installment = total / installments;
+    (0..installments)
+        .map(|i| {
+            let amount = if i + 1 == installments { total - per_installment * (installments - 1) } else { per_installment };
+            let lock_until = match start {
+                LockTime::Height(height) => LockTime::Height(height + i * interval),
+                LockTime::Time(timestamp) => LockTime::Time(timestamp + (i * interval) as i64),
+            };
+            (amount, lock_until)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::transaction::Transaction;
+    use crate::wallet::Wallet;
+
+    #[test]
+    fn locks_parse_and_display() {
+        for lock in [LockTime::Height(10), LockTime::Time(1_700_000_000)] {
+            assert_eq!(lock.to_string().parse::<LockTime>(), Ok(lock));
+        }
+        assert!("height:abc".parse::<LockTime>().is_err());
+        assert!("block:5".parse::<LockTime>().is_err());
+        assert!(LockTime::Height(5).is_unlocked(5, 0));
+        assert!(!LockTime::Height(5).is_unlocked(4, i64::MAX));
+        assert!(!LockTime::Time(100).is_unlocked(u64::MAX, 99));
+    }
+
+    #[test]
+    fn vesting_puts_the_remainder_in_the_last_installment() {
+        let schedule = vesting_schedule(100, 3, LockTime::Height(10), 5);
+        assert_eq!(schedule, vec![(33, LockTime::Height(10)), (33, LockTime::Height(15)), (34, LockTime::Height(20))]);
+        assert_eq!(vesting_schedule(10, 2, LockTime::Time(1000), 60)[1], (5, LockTime::Time(1060)));
+        assert!(vesting_schedule(10, 0, LockTime::Height(1), 1).is_empty());
+    }
+
+    #[test]
+    fn locked_funds_are_spendable_only_after_the_lock() {
+        let alice = Wallet::new();
+        let bob = Wallet::new();
+        let mut blockchain = Blockchain::new(1);
+        blockchain.add_block(Vec::new(), &alice.address());
+
+        let mut grant = Transaction::new_unsigned(alice.address(), bob.address(), 30);
+        grant.lock_until = Some(LockTime::Height(4));
+        grant.sign(&alice.key_pair);
+        blockchain.produce_block(vec![grant], "miner", None).unwrap();
+        assert_eq!(blockchain.get_balance(&bob.address()), 30);
+        assert_eq!(blockchain.get_locked_funds(&bob.address()).len(), 1);
+
+        // 下一个区块的高度为 3，资金仍被锁定
+        let spend = Transaction::new(bob.address(), "carol".to_string(), 30, &bob.key_pair);
+        assert!(blockchain.add_transaction(spend.clone()).is_err());
+        assert!(blockchain.produce_block(vec![spend.clone()], "miner", None).is_err());
+
+        blockchain.add_block(Vec::new(), "miner");
+        blockchain.produce_block(vec![spend], "miner", None).unwrap();
+        assert_eq!(blockchain.get_balance("carol"), 30);
+        assert!(blockchain.is_chain_valid());
+    }
+}
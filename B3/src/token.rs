@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::smart_contract::ContractEvent;
+use crate::vm::{CallContext, ExecutionResult, VmError};
+
+// 内置同质化代币合约：合约代码为代币元数据（JSON），余额和授权保存在合约状态中
+//
+// 状态键：
+//   total_supply                   总供应量
+//   balance:<地址>                 余额
+//   allowance:<持有者>:<被授权者>  授权额度
+//
+// 事件：
+//   ["Transfer", from, to]         数据为金额，铸造时 from 为 "0"
+//   ["Approval", owner, spender]   数据为额度
+
+const READ_GAS: u64 = 100;
+const WRITE_GAS: u64 = 500;
+
+// 代币元数据
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TokenMetadata {
+    pub name: String,
+    pub symbol: String,
+    #[serde(default)]
+    pub decimals: u8,
+    #[serde(default)]
+    pub owner: String, // 唯一有权铸造的地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_supply: Option<u64>, // 供应量上限
+}
+
+impl TokenMetadata {
+    // 从合约代码解析并校验元数据
+    pub fn parse(code: &str) -> Result<Self, String> {
+        let metadata: TokenMetadata = serde_json::from_str(code).map_err(|err| format!("Invalid token metadata: {}", err))?;
+        if metadata.name.is_empty() || metadata.symbol.is_empty() {
+            return Err("Token name and symbol must not be empty".to_string());
+        }
+        if metadata.owner.is_empty() {
+            return Err("Token owner must not be empty".to_string());
+        }
+        Ok(metadata)
+    }
+}
+
+// 执行代币方法，状态修改直接作用于 storage，调用方负责在失败时丢弃
+pub fn execute(code: &str, storage: &mut HashMap<String, String>, context: &CallContext) -> Result<ExecutionResult, VmError> {
+    let metadata = TokenMetadata::parse(code).map_err(|message| VmError { message, gas_used: 0 })?;
+    let gas = match context.method.as_str() {
+        "mint" | "transfer" | "approve" | "transferFrom" => WRITE_GAS,
+        _ => READ_GAS,
+    };
+    if gas > context.gas_limit {
+        return Err(VmError { message: "Out of gas".to_string(), gas_used: context.gas_limit });
+    }
+    let mut token = Token { storage, context, events: Vec::new() };
+    match token.call(&metadata) {
+        Ok(return_value) => Ok(ExecutionResult {
+            return_value,
+            gas_used: gas,
+            transfers: Vec::new(),
+            events: token.events,
+        }),
+        Err(message) => Err(VmError { message, gas_used: gas }),
+    }
+}
+
+struct Token<'a, 'b> {
+    storage: &'a mut HashMap<String, String>,
+    context: &'a CallContext<'b>,
+    events: Vec<ContractEvent>,
+}
+
+impl Token<'_, '_> {
+    fn call(&mut self, metadata: &TokenMetadata) -> Result<String, String> {
+        let caller = self.context.caller.clone();
+        match self.context.method.as_str() {
+            "name" => Ok(metadata.name.clone()),
+            "symbol" => Ok(metadata.symbol.clone()),
+            "decimals" => Ok(metadata.decimals.to_string()),
+            "owner" => Ok(metadata.owner.clone()),
+            "metadata" => Ok(serde_json::to_string(metadata).unwrap()),
+            "totalSupply" => Ok(self.read("total_supply").to_string()),
+            "balanceOf" => Ok(self.read(&balance_key(self.arg(0)?)).to_string()),
+            "allowance" => Ok(self.read(&allowance_key(self.arg(0)?, self.arg(1)?)).to_string()),
+            "mint" => {
+                let (to, amount) = (self.arg(0)?.to_string(), self.amount(1)?);
+                if caller != metadata.owner {
+                    return Err("Only the token owner can mint".to_string());
+                }
+                let supply = self.read("total_supply").checked_add(amount).ok_or("Total supply overflow")?;
+                if metadata.max_supply.is_some_and(|max_supply| supply > max_supply) {
+                    return Err("Mint would exceed the maximum supply".to_string());
+                }
+                self.write("total_supply", supply);
+                let balance = self.read(&balance_key(&to)) + amount;
+                self.write(&balance_key(&to), balance);
+                self.emit("Transfer", "0", &to, amount);
+                Ok("true".to_string())
+            }
+            "transfer" => {
+                let (to, amount) = (self.arg(0)?.to_string(), self.amount(1)?);
+                self.move_balance(&caller, &to, amount)?;
+                Ok("true".to_string())
+            }
+            "approve" => {
+                let (spender, amount) = (self.arg(0)?.to_string(), self.amount(1)?);
+                self.write(&allowance_key(&caller, &spender), amount);
+                self.emit("Approval", &caller, &spender, amount);
+                Ok("true".to_string())
+            }
+            "transferFrom" => {
+                let (from, to, amount) = (self.arg(0)?.to_string(), self.arg(1)?.to_string(), self.amount(2)?);
+                let key = allowance_key(&from, &caller);
+                let allowance = self.read(&key);
+                if allowance < amount {
+                    return Err("Insufficient allowance".to_string());
+                }
+                self.write(&key, allowance - amount);
+                self.move_balance(&from, &to, amount)?;
+                Ok("true".to_string())
+            }
+            _ => Err("Unknown method".to_string()),
+        }
+    }
+
+    fn arg(&self, index: usize) -> Result<&str, String> {
+        self.context.args.get(index).map(String::as_str).ok_or_else(|| format!("Missing argument {}", index))
+    }
+
+    fn amount(&self, index: usize) -> Result<u64, String> {
+        self.arg(index)?.parse().map_err(|_| format!("Argument {} must be an unsigned integer", index))
+    }
+
+    fn read(&self, key: &str) -> u64 {
+        self.storage.get(key).and_then(|value| value.parse().ok()).unwrap_or(0)
+    }
+
+    fn write(&mut self, key: &str, value: u64) {
+        self.storage.insert(key.to_string(), value.to_string());
+    }
+
+    fn move_balance(&mut self, from: &str, to: &str, amount: u64) -> Result<(), String> {
+        let from_balance = self.read(&balance_key(from));
+        if from_balance < amount {
+            return Err("Insufficient token balance".to_string());
+        }
+        self.write(&balance_key(from), from_balance - amount);
+        let to_balance = self.read(&balance_key(to));
+        self.write(&balance_key(to), to_balance + amount);
+        self.emit("Transfer", from, to, amount);
+        Ok(())
+    }
+
+    fn emit(&mut self, name: &str, from: &str, to: &str, amount: u64) {
+        self.events.push(ContractEvent {
+            contract_id: self.context.address.clone(),
+            topics: vec![name.to_string(), from.to_string(), to.to_string()],
+            data: amount.to_string(),
+        });
+    }
+}
+
+fn balance_key(address: &str) -> String {
+    format!("balance:{}", address)
+}
+
+fn allowance_key(owner: &str, spender: &str) -> String {
+    format!("allowance:{}:{}", owner, spender)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &str = r#"{"name":"Demo Coin","symbol":"DMC","decimals":2,"owner":"alice","max_supply":1000}"#;
+
+    fn call(storage: &mut HashMap<String, String>, caller: &str, method: &str, args: &[&str]) -> Result<ExecutionResult, VmError> {
+        let balance_of = |_: &str| 0;
+        let context = CallContext {
+            caller: caller.to_string(),
+            address: "token".to_string(),
+            value: 0,
+            method: method.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            gas_limit: 1000,
+            balance_of: &balance_of,
+        };
+        execute(METADATA, storage, &context)
+    }
+
+    fn balance(storage: &mut HashMap<String, String>, address: &str) -> String {
+        call(storage, "anyone", "balanceOf", &[address]).unwrap().return_value
+    }
+
+    #[test]
+    fn metadata_is_validated() {
+        assert_eq!(TokenMetadata::parse(METADATA).unwrap().symbol, "DMC");
+        assert!(TokenMetadata::parse(r#"{"name":"","symbol":"X","owner":"a"}"#).is_err());
+        assert!(TokenMetadata::parse(r#"{"name":"X","symbol":"X"}"#).is_err());
+        assert!(TokenMetadata::parse("not json").is_err());
+    }
+
+    #[test]
+    fn only_the_owner_mints_up_to_the_maximum_supply() {
+        let mut storage = HashMap::new();
+        let minted = call(&mut storage, "alice", "mint", &["bob", "600"]).unwrap();
+        assert_eq!(minted.events[0].topics, vec!["Transfer", "0", "bob"]);
+        assert_eq!(minted.gas_used, WRITE_GAS);
+        assert!(call(&mut storage, "bob", "mint", &["bob", "1"]).is_err());
+        assert!(call(&mut storage, "alice", "mint", &["bob", "401"]).is_err());
+        assert!(call(&mut storage, "alice", "mint", &["bob", &u64::MAX.to_string()]).is_err());
+        assert_eq!(call(&mut storage, "x", "totalSupply", &[]).unwrap().return_value, "600");
+        assert_eq!(balance(&mut storage, "bob"), "600");
+    }
+
+    #[test]
+    fn transfers_and_allowances_move_balances() {
+        let mut storage = HashMap::new();
+        call(&mut storage, "alice", "mint", &["bob", "100"]).unwrap();
+        call(&mut storage, "bob", "transfer", &["carol", "30"]).unwrap();
+        assert!(call(&mut storage, "bob", "transfer", &["carol", "71"]).is_err());
+
+        call(&mut storage, "bob", "approve", &["dave", "50"]).unwrap();
+        assert_eq!(call(&mut storage, "x", "allowance", &["bob", "dave"]).unwrap().return_value, "50");
+        call(&mut storage, "dave", "transferFrom", &["bob", "erin", "40"]).unwrap();
+        let error = call(&mut storage, "dave", "transferFrom", &["bob", "erin", "20"]).unwrap_err();
+        assert_eq!(error.message, "Insufficient allowance");
+
+        assert_eq!(balance(&mut storage, "bob"), "30");
+        assert_eq!(balance(&mut storage, "carol"), "30");
+        assert_eq!(balance(&mut storage, "erin"), "40");
+        assert_eq!(call(&mut storage, "x", "allowance", &["bob", "dave"]).unwrap().return_value, "10");
+    }
+
+    #[test]
+    fn calls_fail_without_gas_or_valid_arguments() {
+        let mut storage = HashMap::new();
+        let balance_of = |_: &str| 0;
+        let context = CallContext {
+            caller: "alice".to_string(),
+            address: "token".to_string(),
+            value: 0,
+            method: "mint".to_string(),
+            args: vec!["bob".to_string(), "5".to_string()],
+            gas_limit: WRITE_GAS - 1,
+            balance_of: &balance_of,
+        };
+        assert_eq!(execute(METADATA, &mut storage, &context).unwrap_err().message, "Out of gas");
+        assert!(call(&mut storage, "alice", "mint", &["bob", "-5"]).is_err());
+        assert!(call(&mut storage, "alice", "mint", &["bob"]).is_err());
+        assert_eq!(call(&mut storage, "alice", "burn", &[]).unwrap_err().message, "Unknown method");
+        assert!(storage.is_empty());
+    }
+}
@@ -1,30 +1,180 @@
 use serde::{Serialize, Deserialize};
-use ring::signature::{self, Ed25519KeyPair};
+use std::fmt;
+use ring::signature::{Ed25519KeyPair, KeyPair};
 use ring::rand::SystemRandom;
-use hex;
-use crate::wallet::Wallet;
+use crate::multisig::{MultisigAccount, PartialSignature};
+use crate::utxo::{OutPoint, TxInput, TxOutput};
+use crate::timelock::LockTime;
+use crate::smart_contract::{self, ContractKind};
+use crate::privacy::PrivacyTransaction;
+use crate::confidential::ConfidentialTransfer;
+use sha2::{Sha256, Digest};
+
+// 交易类型：普通转账之外的交易在 payload 中携带额外数据
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum TxPayload {
+    #[default]
+    Transfer,
+    // 哈希时间锁：发送方锁定 amount，接收方在超时前提供原像即可领取
+    HtlcLock { hashlock: String, timeout: LockTime },
+    // 提供原像领取哈希时间锁中的资金
+    HtlcClaim { htlc_id: String, preimage: String },
+    // 超时后发送方取回资金
+    HtlcRefund { htlc_id: String },
+    // 部署合约：合约地址为本交易的 ID，amount 为转入合约的初始金额
+    Deploy { kind: ContractKind, code: String },
+    // 调用合约：receiver 为合约地址，amount 随调用转入合约，gas_limit 为燃料上限
+    Call { method: String, args: Vec<String>, gas_limit: u64 },
+    // 发行资产：资产 ID 为本交易的 ID，amount 为发给 receiver 的初始发行量
+    IssueAsset { name: String, symbol: String, decimals: u8, max_supply: Option<u64> },
+    // 增发资产：由发行者向 receiver 增发 asset 指定的资产
+    MintAsset,
+    // 存入隐私池：发送方转出 amount，新票据承诺由 amount、票据密钥和盲化因子计算
+    Shield { note_key: String, blinding: String },
+    // 隐私转账：由隐私池发出，花费票据、创建新票据，并把 amount 转出给 receiver（为 0 时不转出）
+    ShieldedTransfer(PrivacyTransaction),
+    // 机密转账：花费发送方的机密输出、创建新的机密输出，amount 为从发送方余额存入的公开金额
+    Confidential(ConfidentialTransfer),
+    // 质押：发送方把 amount 从余额转入质押，按质押参与权益证明的出块者选择
+    Stake,
+    // 取回质押：把 amount 从发送方的质押转回余额
+    Unstake,
+    // 权威证明的签名者投票：authorize 为 true 时提议增加 candidate 为签名者，为 false 时提议移除
+    Vote { candidate: String, authorize: bool },
+}
+
+impl TxPayload {
+    pub fn is_transfer(&self) -> bool {
+        *self == TxPayload::Transfer
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Transaction {
     pub sender: String,
     pub receiver: String,
     pub amount: u64,
+    #[serde(default)]
+    pub nonce: u64, // 用于区分内容相同的交易
     pub signature: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signatures: Vec<PartialSignature>, // 多签账户发出的交易携带的多个签名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multisig: Option<MultisigAccount>, // 多签账户发出的交易携带账户定义，其地址必须等于发送方（不参与签名，由发送方地址约束）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<TxInput>, // UTXO 模式：花费的输出
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<TxOutput>, // UTXO 模式：新产生的输出
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock_until: Option<LockTime>, // 账户模式：接收方在此之前不能花费这笔资金
+    #[serde(default, skip_serializing_if = "TxPayload::is_transfer")]
+    pub payload: TxPayload, // 交易类型及附加数据
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub asset: String, // 资产 ID，为空表示原生币
 }
 
 impl Transaction {
     // 创建一笔新交易
     pub fn new(sender: String, receiver: String, amount: u64, key_pair: &Ed25519KeyPair) -> Self {
-        let mut transaction = Transaction {
+        let mut transaction = Self::new_unsigned(sender, receiver, amount);
+        transaction.sign(key_pair);
+        transaction
+    }
+
+    // 创建一笔未签名的交易（用于离线签名）
+    pub fn new_unsigned(sender: String, receiver: String, amount: u64) -> Self {
+        Transaction {
             sender,
             receiver,
             amount,
+            nonce: rand::random(),
             signature: String::new(),
-        };
-        transaction.sign(key_pair);
+            signatures: Vec::new(),
+            multisig: None,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            lock_until: None,
+            payload: TxPayload::Transfer,
+            asset: String::new(),
+        }
+    }
+
+    // 创建一笔未签名的 UTXO 交易，receiver 和 amount 取第一个输出
+    pub fn new_utxo_unsigned(sender: String, inputs: Vec<OutPoint>, outputs: Vec<TxOutput>) -> Self {
+        let (receiver, amount) = outputs
+            .first()
+            .map(|output| (output.address.clone(), output.amount))
+            .unwrap_or_default();
+        let mut transaction = Self::new_unsigned(sender, receiver, amount);
+        transaction.inputs = inputs.into_iter().map(|previous_output| TxInput { previous_output }).collect();
+        transaction.outputs = outputs;
         transaction
     }
 
+    // 编码为可在机器间传递的原始交易（JSON 的十六进制）
+    pub fn to_raw(&self) -> String {
+        hex::encode(serde_json::to_vec(self).unwrap())
+    }
+
+    // 从原始交易解码
+    pub fn from_raw(raw: &str) -> Result<Self, String> {
+        let bytes = hex::decode(raw.trim()).map_err(|e| format!("Invalid raw transaction hex: {}", e))?;
+        serde_json::from_slice(&bytes).map_err(|e| format!("Invalid raw transaction: {}", e))
+    }
+
+    // 是否已签名（隐私转账由零知识证明授权，不需要签名）
+    pub fn is_signed(&self) -> bool {
+        !self.signature.is_empty() || !self.signatures.is_empty() || matches!(self.payload, TxPayload::ShieldedTransfer(_))
+    }
+
+    // 创建挖矿奖励交易（由系统地址 "0" 发出，以区块高度作为 nonce）
+    pub fn coinbase(miner: &str, amount: u64, height: u64) -> Self {
+        Transaction {
+            sender: "0".to_string(),
+            receiver: miner.to_string(),
+            amount,
+            nonce: height,
+            signature: "mining_reward".to_string(), // 挖矿奖励不需要签名
+            signatures: Vec::new(),
+            multisig: None,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            lock_until: None,
+            payload: TxPayload::Transfer,
+            asset: String::new(),
+        }
+    }
+
+    // 从发送方 asset 余额中扣除的金额（领取和退回哈希时间锁、发行和增发资产、隐私转账、取回质押不扣除发送方余额，合约交易包含最高手续费），溢出时返回 None
+    pub fn debit_amount(&self) -> Option<u64> {
+        match self.payload {
+            TxPayload::Transfer | TxPayload::HtlcLock { .. } | TxPayload::Shield { .. } | TxPayload::Confidential(_) | TxPayload::Stake => Some(self.amount),
+            TxPayload::HtlcClaim { .. } | TxPayload::HtlcRefund { .. } | TxPayload::Unstake | TxPayload::Vote { .. } => Some(0),
+            TxPayload::IssueAsset { .. } | TxPayload::MintAsset => Some(0),
+            TxPayload::ShieldedTransfer(_) => Some(0),
+            TxPayload::Deploy { .. } | TxPayload::Call { .. } => self.amount.checked_add(self.max_fee()),
+        }
+    }
+
+    // 合约交易可能收取的最高手续费
+    pub fn max_fee(&self) -> u64 {
+        match &self.payload {
+            TxPayload::Deploy { kind, code } => smart_contract::gas_fee(smart_contract::deploy_gas(*kind, code)),
+            TxPayload::Call { gas_limit, .. } => smart_contract::gas_fee(*gas_limit),
+            _ => 0,
+        }
+    }
+
+    // 是否为挖矿奖励交易
+    pub fn is_coinbase(&self) -> bool {
+        self.sender == "0"
+    }
+
+    // 交易 ID：对交易规范编码（不含签名）的 SHA-256 哈希
+    pub fn id(&self) -> String {
+        hex::encode(Sha256::digest(self.to_message()))
+    }
+
     // 对交易进行签名
     pub fn sign(&mut self, key_pair: &Ed25519KeyPair) {
         let message = self.to_message();
@@ -34,33 +184,170 @@ impl Transaction {
 
     // 验证交易的签名
     pub fn verify(&self) -> bool {
-        let message = self.to_message();
-        let public_key_bytes = hex::decode(&self.sender).unwrap();
-        let signature_bytes = hex::decode(&self.signature).unwrap();
-        let public_key = ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &public_key_bytes);
-        public_key.verify(&message, &signature_bytes).is_ok()
+        crate::multisig::verify_signature(&self.sender, &self.to_message(), &self.signature)
+    }
+
+    // 为多签交易追加一个参与者的签名（同一公钥重复签名时覆盖）
+    pub fn add_partial_signature(&mut self, key_pair: &Ed25519KeyPair) {
+        let public_key = hex::encode(key_pair.public_key().as_ref());
+        let signature = hex::encode(key_pair.sign(&self.to_message()).as_ref());
+        self.signatures.retain(|partial| partial.public_key != public_key);
+        self.signatures.push(PartialSignature { public_key, signature });
+    }
+
+    // 合并另一份副本上收集到的多签签名
+    pub fn merge_signatures(&mut self, other: &Transaction) -> Result<(), String> {
+        if self.id() != other.id() {
+            return Err("Cannot merge signatures of different transactions".to_string());
+        }
+        for partial in &other.signatures {
+            if !self.signatures.iter().any(|existing| existing.public_key == partial.public_key) {
+                self.signatures.push(partial.clone());
+            }
+        }
+        if self.multisig.is_none() {
+            self.multisig = other.multisig.clone();
+        }
+        Ok(())
+    }
+
+    // 验证多签交易：携带的账户定义必须是规范形式且地址等于发送方，有效签名数达到门限
+    pub fn verify_multisig(&self) -> bool {
+        let Some(account) = &self.multisig else {
+            return false;
+        };
+        MultisigAccount::new(account.public_keys.clone(), account.threshold).as_ref() == Ok(account)
+            && self.sender == account.address()
+            && account.is_authorized(&self.to_message(), &self.signatures)
+    }
+
+    // 交易涉及的地址：发送方、接收方和全部 UTXO 输出的地址（包括找零），去重后按首次出现的顺序
+    pub fn addresses(&self) -> Vec<&str> {
+        let mut addresses: Vec<&str> = Vec::new();
+        let all = [self.sender.as_str(), self.receiver.as_str()].into_iter().chain(self.outputs.iter().map(|output| output.address.as_str()));
+        for address in all {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+        addresses
+    }
+
+    // 判断交易是否与某个地址相关
+    pub fn involves(&self, address: &str) -> bool {
+        self.addresses().contains(&address)
     }
 
-    // 将交易数据转换为消息（用于签名和验证）
+    // 交易的规范编码（用于交易 ID、签名和验证）：除签名外的全部字段按固定顺序编码为 JSON，
+    // 字符串带引号和转义、数字有明确的边界，不同的交易不会得到相同的编码
     fn to_message(&self) -> Vec<u8> {
-        let data = format!("{}{}{}", self.sender, self.receiver, self.amount);
-        data.into_bytes()
+        let message = SigningMessage {
+            sender: &self.sender,
+            receiver: &self.receiver,
+            amount: self.amount,
+            nonce: self.nonce,
+            inputs: &self.inputs,
+            outputs: &self.outputs,
+            lock_until: self.lock_until,
+            payload: &self.payload,
+            asset: &self.asset,
+        };
+        serde_json::to_vec(&message).unwrap()
     }
 }
 
+// 交易中被签名的字段
+#[derive(Serialize)]
+struct SigningMessage<'a> {
+    sender: &'a str,
+    receiver: &'a str,
+    amount: u64,
+    nonce: u64,
+    inputs: &'a [TxInput],
+    outputs: &'a [TxOutput],
+    lock_until: Option<LockTime>,
+    payload: &'a TxPayload,
+    asset: &'a str,
+}
+
 // 实现交易的字符串表示
-impl ToString for Transaction {
-    fn to_string(&self) -> String {
-        format!(
-            "sender: {}, receiver: {}, amount: {}, signature: {}",
-            self.sender, self.receiver, self.amount, self.signature
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sender: {}, receiver: {}, amount: {}, nonce: {}, signature: {}",
+            self.sender, self.receiver, self.amount, self.nonce, self.signature
         )
     }
 }
 
 // 生成一个新的密钥对
+#[allow(dead_code)]
 pub fn generate_key_pair() -> Ed25519KeyPair {
     let rng = SystemRandom::new();
     let pkcs8_bytes = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
     Ed25519KeyPair::from_pkcs8(pkcs8_bytes.as_ref()).unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::Wallet;
+
+    #[test]
+    fn shifting_digits_between_amount_and_nonce_changes_the_id() {
+        let wallet = Wallet::new();
+        let mut tx = Transaction::new_unsigned(wallet.address(), "bob".to_string(), 1);
+        tx.nonce = 23;
+        tx.sign(&wallet.key_pair);
+
+        // 旧编码中 amount=1, nonce=23 与 amount=12, nonce=3 的消息相同
+        let mut forged = tx.clone();
+        forged.amount = 12;
+        forged.nonce = 3;
+        assert_ne!(tx.id(), forged.id());
+        assert!(tx.verify());
+        assert!(!forged.verify());
+    }
+
+    #[test]
+    fn id_covers_every_field_except_signatures() {
+        let wallet = Wallet::new();
+        let tx = Transaction::new(wallet.address(), "bob".to_string(), 5, &wallet.key_pair);
+        let id = tx.id();
+
+        let mut resigned = tx.clone();
+        resigned.signature.clear();
+        assert_eq!(resigned.id(), id);
+
+        let mut changes: Vec<Transaction> = Vec::new();
+        let mut locked = tx.clone();
+        locked.lock_until = Some(LockTime::Height(10));
+        changes.push(locked);
+        let mut asset = tx.clone();
+        asset.asset = "gold".to_string();
+        changes.push(asset);
+        let mut payload = tx.clone();
+        payload.payload = TxPayload::Stake;
+        changes.push(payload);
+        let mut outputs = tx.clone();
+        outputs.outputs = vec![TxOutput::new("bob".to_string(), 5)];
+        changes.push(outputs);
+        for changed in changes {
+            assert_ne!(changed.id(), id);
+            assert!(!changed.verify());
+        }
+    }
+
+    #[test]
+    fn raw_encoding_round_trips() {
+        let wallet = Wallet::new();
+        let mut tx = Transaction::new_unsigned(wallet.address(), "bob".to_string(), 7);
+        tx.lock_until = Some(LockTime::Time(1_700_000_000));
+        tx.sign(&wallet.key_pair);
+        let decoded = Transaction::from_raw(&tx.to_raw()).unwrap();
+        assert_eq!(decoded.id(), tx.id());
+        assert!(decoded.verify());
+        assert!(Transaction::from_raw("zz").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::block::Block;
+
+// 交易在链上的位置
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TxLocation {
+    pub block_index: u64,
+    pub block_hash: String,
+    pub position: usize, // 交易在区块中的序号
+}
+
+// 交易索引：交易 ID -> 位置，地址 -> 相关交易 ID 列表
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TxIndex {
+    pub locations: HashMap<String, TxLocation>,
+    pub address_history: HashMap<String, Vec<String>>,
+}
+
+impl TxIndex {
+    // 从已有的区块构建索引
+    pub fn build(chain: &[Block]) -> Self {
+        let mut index = TxIndex::default();
+        for block in chain {
+            index.connect_block(block);
+        }
+        index
+    }
+
+    // 区块上链时更新索引
+    pub fn connect_block(&mut self, block: &Block) {
+        for (position, tx) in block.transactions.iter().enumerate() {
+            let id = tx.id();
+            self.locations.insert(id.clone(), TxLocation {
+                block_index: block.header.height,
+                block_hash: block.hash.clone(),
+                position,
+            });
+            for address in tx.addresses() {
+                self.address_history.entry(address.to_string()).or_default().push(id.clone());
+            }
+        }
+    }
+
+    // 区块从链上断开时回滚索引
+    pub fn disconnect_block(&mut self, block: &Block) {
+        for tx in block.transactions.iter().rev() {
+            let id = tx.id();
+            self.locations.remove(&id);
+            for address in tx.addresses() {
+                if let Some(history) = self.address_history.get_mut(address) {
+                    history.retain(|tx_id| tx_id != &id);
+                    if history.is_empty() {
+                        self.address_history.remove(address);
+                    }
+                }
+            }
+        }
+    }
+
+    // 查询交易位置
+    pub fn get_location(&self, tx_id: &str) -> Option<&TxLocation> {
+        self.locations.get(tx_id)
+    }
+
+    // 查询地址的历史交易 ID（按上链顺序）
+    pub fn get_history(&self, address: &str) -> &[String] {
+        self.address_history.get(address).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::transaction::Transaction;
+    use crate::utxo::{LedgerMode, TxOutput};
+    use crate::wallet::Wallet;
+
+    #[test]
+    fn index_matches_a_chain_scan_and_follows_disconnects() {
+        let alice = Wallet::new();
+        let mut indexed = Blockchain::new(1);
+        indexed.add_block(Vec::new(), &alice.address());
+        indexed.enable_tx_index();
+        let tx = Transaction::new(alice.address(), "bob".to_string(), 20, &alice.key_pair);
+        indexed.produce_block(vec![tx.clone()], "miner", None).unwrap();
+
+        let (_, location) = indexed.get_transaction(&tx.id()).unwrap();
+        assert_eq!((location.block_index, location.position), (2, 1));
+        // 地址历史按上链顺序：先是挖矿奖励，然后是转账
+        let history: Vec<String> = indexed.get_address_history(&alice.address()).iter().map(|(tx, _)| tx.id()).collect();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1], tx.id());
+
+        // 未启用索引时遍历整条链，结果相同
+        let index = indexed.tx_index.take();
+        assert_eq!(indexed.get_transaction(&tx.id()).unwrap().1, location);
+        let scanned: Vec<String> = indexed.get_address_history(&alice.address()).iter().map(|(tx, _)| tx.id()).collect();
+        assert_eq!(scanned, history);
+        indexed.tx_index = index;
+
+        indexed.disconnect_latest_block().unwrap();
+        assert!(indexed.get_transaction(&tx.id()).is_none());
+        assert!(indexed.tx_index.as_ref().unwrap().get_history("bob").is_empty());
+        assert_eq!(indexed.get_address_history(&alice.address()).len(), 1);
+    }
+
+    #[test]
+    fn self_transfers_are_recorded_once() {
+        let alice = Wallet::new();
+        let mut blockchain = Blockchain::new(1);
+        blockchain.add_block(Vec::new(), &alice.address());
+        let tx = Transaction::new(alice.address(), alice.address(), 5, &alice.key_pair);
+        blockchain.produce_block(vec![tx.clone()], "miner", None).unwrap();
+
+        let index = TxIndex::build(&blockchain.chain);
+        assert_eq!(index.get_history(&alice.address()).iter().filter(|id| **id == tx.id()).count(), 1);
+    }
+
+    #[test]
+    fn every_utxo_output_address_is_indexed() {
+        let alice = Wallet::new();
+        let mut blockchain = Blockchain::with_ledger_mode(1, LedgerMode::Utxo);
+        blockchain.add_block(Vec::new(), &alice.address());
+        blockchain.enable_tx_index();
+        let payments = vec![TxOutput::new("bob".to_string(), 10), TxOutput::new("carol".to_string(), 5)];
+        let mut tx = blockchain.create_utxo_transaction(&alice.address(), payments).unwrap();
+        tx.sign(&alice.key_pair);
+        assert_eq!(tx.outputs.len(), 3);
+        assert_eq!(tx.addresses(), [alice.address().as_str(), "bob", "carol"]);
+        blockchain.produce_block(vec![tx.clone()], "miner", None).unwrap();
+
+        // 第二个收款方和找零都出现在历史中，找零地址即发送方，只记录一次
+        let index = blockchain.tx_index.as_ref().unwrap();
+        assert_eq!(index.get_history("carol"), [tx.id()]);
+        assert_eq!(index.get_history(&alice.address()).iter().filter(|id| **id == tx.id()).count(), 1);
+        let index = blockchain.tx_index.take();
+        assert_eq!(blockchain.get_address_history("carol").len(), 1);
+        blockchain.tx_index = index;
+    }
+}
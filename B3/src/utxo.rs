@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use crate::timelock::LockTime;
+
+// 账本模式：账户余额模型或 UTXO 模型，在创世时确定
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum LedgerMode {
+    #[default]
+    Account,
+    Utxo,
+}
+
+impl FromStr for LedgerMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "account" => Ok(LedgerMode::Account),
+            "utxo" => Ok(LedgerMode::Utxo),
+            _ => Err(format!("Unknown ledger mode '{}', expected 'account' or 'utxo'", s)),
+        }
+    }
+}
+
+// 指向某笔交易的某个输出
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OutPoint {
+    pub tx_id: String,
+    pub index: u32,
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.tx_id, self.index)
+    }
+}
+
+// 交易输入：引用一个未花费的输出
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TxInput {
+    pub previous_output: OutPoint,
+}
+
+// 交易输出：向地址支付一定金额
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TxOutput {
+    pub address: String,
+    pub amount: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock_until: Option<LockTime>, // 在此之前该输出不可花费
+}
+
+impl TxOutput {
+    // 创建普通输出
+    pub fn new(address: String, amount: u64) -> Self {
+        TxOutput { address, amount, lock_until: None }
+    }
+
+    // 创建带时间锁的输出
+    pub fn locked(address: String, amount: u64, lock_until: LockTime) -> Self {
+        TxOutput { address, amount, lock_until: Some(lock_until) }
+    }
+
+    // 在给定高度和时间的区块中是否可以花费
+    pub fn is_spendable(&self, height: u64, timestamp: i64) -> bool {
+        self.lock_until.is_none_or(|lock| lock.is_unlocked(height, timestamp))
+    }
+}
+
+// UTXO 集合：以 "交易ID:输出序号" 为键
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UtxoSet {
+    pub outputs: HashMap<String, TxOutput>,
+}
+
+impl UtxoSet {
+    // 查询未花费输出
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&TxOutput> {
+        self.outputs.get(&outpoint.to_string())
+    }
+
+    // 地址拥有的全部未花费输出，按键排序以保证结果确定
+    pub fn unspent_for(&self, address: &str) -> Vec<(OutPoint, TxOutput)> {
+        let mut unspent: Vec<(OutPoint, TxOutput)> = self.outputs
+            .iter()
+            .filter(|(_, output)| output.address == address)
+            .filter_map(|(key, output)| parse_outpoint(key).map(|outpoint| (outpoint, output.clone())))
+            .collect();
+        unspent.sort_by_key(|(outpoint, _)| outpoint.to_string());
+        unspent
+    }
+
+    // 地址余额：未花费输出金额之和
+    pub fn balance_of(&self, address: &str) -> u64 {
+        self.outputs.values().filter(|output| output.address == address).map(|output| output.amount).sum()
+    }
+
+    // 总流通量，溢出 u64 时为 None
+    pub fn total(&self) -> Option<u64> {
+        self.outputs.values().try_fold(0u64, |total, output| total.checked_add(output.amount))
+    }
+}
+
+// 解析 "交易ID:输出序号" 形式的键
+pub fn parse_outpoint(key: &str) -> Option<OutPoint> {
+    let (tx_id, index) = key.rsplit_once(':')?;
+    Some(OutPoint {
+        tx_id: tx_id.to_string(),
+        index: index.parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::transaction::Transaction;
+    use crate::wallet::Wallet;
+
+    #[test]
+    fn outpoints_round_trip_through_keys() {
+        let outpoint = OutPoint { tx_id: "ab:cd".to_string(), index: 3 };
+        assert_eq!(parse_outpoint(&outpoint.to_string()), Some(outpoint));
+        assert_eq!(parse_outpoint("no-index"), None);
+        assert_eq!("utxo".parse::<LedgerMode>(), Ok(LedgerMode::Utxo));
+        assert!("other".parse::<LedgerMode>().is_err());
+    }
+
+    #[test]
+    fn payments_spend_inputs_and_return_change() {
+        let alice = Wallet::new();
+        let mut blockchain = Blockchain::with_ledger_mode(1, LedgerMode::Utxo);
+        blockchain.add_block(Vec::new(), &alice.address());
+        blockchain.add_block(Vec::new(), &alice.address());
+        let total = blockchain.utxo_set.total().unwrap();
+
+        // 70 需要两个输出，找零返还给发送方
+        let mut tx = blockchain.create_utxo_transaction(&alice.address(), vec![TxOutput::new("bob".to_string(), 70)]).unwrap();
+        assert_eq!(tx.inputs.len(), 2);
+        assert_eq!(tx.outputs[1].address, alice.address());
+        tx.sign(&alice.key_pair);
+        blockchain.add_transaction(tx.clone()).unwrap();
+
+        // 交易池中已花费的输出不能再次选用
+        assert!(blockchain.spendable_outputs(&alice.address()).is_empty());
+        assert!(blockchain.create_utxo_transaction(&alice.address(), vec![TxOutput::new("carol".to_string(), 1)]).is_err());
+
+        let transactions = std::mem::take(&mut blockchain.pending_transactions);
+        blockchain.produce_block(transactions, "miner", None).unwrap();
+        assert_eq!(blockchain.get_balance("bob"), 70);
+        assert_eq!(blockchain.get_balance(&alice.address()), total - 70);
+        assert!(blockchain.is_chain_valid());
+
+        // 断开区块后被花费的输出恢复
+        blockchain.disconnect_latest_block().unwrap();
+        assert_eq!(blockchain.get_balance("bob"), 0);
+        assert_eq!(blockchain.get_balance(&alice.address()), total);
+    }
+
+    #[test]
+    fn inputs_must_belong_to_the_sender_and_match_the_outputs() {
+        let alice = Wallet::new();
+        let mallory = Wallet::new();
+        let mut blockchain = Blockchain::with_ledger_mode(1, LedgerMode::Utxo);
+        blockchain.add_block(Vec::new(), &alice.address());
+        let inputs: Vec<OutPoint> = blockchain.spendable_outputs(&alice.address()).into_iter().map(|(outpoint, _)| outpoint).collect();
+        let total = blockchain.utxo_set.total().unwrap();
+
+        // 花费别人的输出
+        let mut stolen = Transaction::new_utxo_unsigned(mallory.address(), inputs.clone(), vec![TxOutput::new(mallory.address(), total)]);
+        stolen.sign(&mallory.key_pair);
+        assert!(blockchain.add_transaction(stolen).is_err());
+
+        // 输出多于输入
+        let mut inflated = Transaction::new_utxo_unsigned(alice.address(), inputs, vec![TxOutput::new("bob".to_string(), total + 1)]);
+        inflated.sign(&alice.key_pair);
+        assert!(blockchain.add_transaction(inflated.clone()).is_err());
+        assert!(blockchain.produce_block(vec![inflated], "miner", None).is_err());
+        assert_eq!(blockchain.utxo_set.total(), Some(total));
+    }
+}
@@ -0,0 +1,669 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::smart_contract::ContractEvent;
+
+// 栈式虚拟机：执行合约字节码，按指令计费，燃料耗尽时中止
+//
+// 字节码格式：每条指令以一个字节的操作码开头，部分指令带立即数（大端序）：
+//   PUSH <u64>      0x60 + 8 字节
+//   PUSHSTR "<s>"   0x61 + 2 字节长度 + UTF-8 字节
+//   JUMP/JUMPI <l>  0x56/0x57 + 4 字节目标偏移
+//   ARG/DUP/SWAP/LOG <n> 操作码 + 1 字节
+
+const MAX_STACK_DEPTH: usize = 1024;
+const MAX_LOG_TOPICS: u8 = 4;
+const MAX_STRING_LEN: usize = 4096;        // 栈上字符串的最大字节数
+pub const MAX_STORAGE_VALUE_LEN: usize = 1024; // 存储值的最大字节数
+pub const GAS_PER_BYTE: u64 = 1;            // 字符串和存储按字节额外计费
+
+// 栈上的值：整数或字符串（地址、存储键等）
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(u64),
+    Str(String),
+}
+
+impl Value {
+    // 从字符串解析：十进制整数解析为 Int，其余为 Str
+    pub fn parse(s: &str) -> Value {
+        match s.parse::<u64>() {
+            Ok(n) if n.to_string() == s => Value::Int(n),
+            _ => Value::Str(s.to_string()),
+        }
+    }
+
+    fn as_int(&self) -> Result<u64, String> {
+        match self {
+            Value::Int(n) => Ok(*n),
+            Value::Str(s) => Err(format!("Type error: expected integer, got '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+// 指令集
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Stop,
+    // 算术（溢出、除零时报错）
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    // 比较与逻辑（结果为 0 或 1）
+    Lt,
+    Gt,
+    Eq,
+    IsZero,
+    And,
+    Or,
+    Not,
+    // 字符串拼接，用于构造存储键
+    Concat,
+    // 调用环境
+    Caller,
+    CallValue,
+    Address,
+    Method,
+    Arg(u8),
+    ArgCount,
+    Balance,
+    Transfer,
+    // 发出事件：弹出数据（栈顶）和 n 个主题
+    Log(u8),
+    // 栈操作
+    Pop,
+    Dup(u8),
+    Swap(u8),
+    // 存储
+    SLoad,
+    SStore,
+    // 控制流
+    Jump(u32),
+    JumpI(u32),
+    Push(u64),
+    PushStr(String),
+    Return,
+    Revert,
+}
+
+impl Instruction {
+    // 助记符与操作码对照表（不含立即数的指令）
+    const SIMPLE: [(&'static str, u8, Instruction); 26] = [
+        ("STOP", 0x00, Instruction::Stop),
+        ("ADD", 0x01, Instruction::Add),
+        ("SUB", 0x02, Instruction::Sub),
+        ("MUL", 0x03, Instruction::Mul),
+        ("DIV", 0x04, Instruction::Div),
+        ("MOD", 0x05, Instruction::Mod),
+        ("LT", 0x10, Instruction::Lt),
+        ("GT", 0x11, Instruction::Gt),
+        ("EQ", 0x12, Instruction::Eq),
+        ("ISZERO", 0x13, Instruction::IsZero),
+        ("AND", 0x14, Instruction::And),
+        ("OR", 0x15, Instruction::Or),
+        ("NOT", 0x16, Instruction::Not),
+        ("CONCAT", 0x20, Instruction::Concat),
+        ("CALLER", 0x30, Instruction::Caller),
+        ("CALLVALUE", 0x31, Instruction::CallValue),
+        ("ADDRESS", 0x32, Instruction::Address),
+        ("METHOD", 0x33, Instruction::Method),
+        ("ARGCOUNT", 0x35, Instruction::ArgCount),
+        ("BALANCE", 0x36, Instruction::Balance),
+        ("TRANSFER", 0x37, Instruction::Transfer),
+        ("POP", 0x40, Instruction::Pop),
+        ("SLOAD", 0x50, Instruction::SLoad),
+        ("SSTORE", 0x51, Instruction::SStore),
+        ("RETURN", 0xf3, Instruction::Return),
+        ("REVERT", 0xfd, Instruction::Revert),
+    ];
+
+    // 每条指令消耗的燃料（拼接和写存储另按结果的字节数计费）
+    pub fn gas_cost(&self) -> u64 {
+        match self {
+            Instruction::Stop | Instruction::Return | Instruction::Revert => 0,
+            Instruction::Push(_) | Instruction::Pop | Instruction::Dup(_) | Instruction::Swap(_) => 1,
+            Instruction::PushStr(s) => 1 + s.len() as u64 * GAS_PER_BYTE,
+            Instruction::Caller | Instruction::CallValue | Instruction::Address
+            | Instruction::Method | Instruction::Arg(_) | Instruction::ArgCount => 2,
+            Instruction::Add | Instruction::Sub | Instruction::Lt | Instruction::Gt | Instruction::Eq
+            | Instruction::IsZero | Instruction::And | Instruction::Or | Instruction::Not => 3,
+            Instruction::Mul | Instruction::Div | Instruction::Mod | Instruction::Concat => 5,
+            Instruction::Jump(_) => 8,
+            Instruction::JumpI(_) => 10,
+            Instruction::Balance => 20,
+            Instruction::SLoad => 50,
+            Instruction::SStore => 100,
+            Instruction::Transfer => 200,
+            Instruction::Log(topics) => 50 + 25 * *topics as u64,
+        }
+    }
+
+    // 编码为字节码
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Instruction::Push(n) => {
+                out.push(0x60);
+                out.extend_from_slice(&n.to_be_bytes());
+            }
+            Instruction::PushStr(s) => {
+                out.push(0x61);
+                out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+                out.extend_from_slice(s.as_bytes());
+            }
+            Instruction::Jump(target) => {
+                out.push(0x56);
+                out.extend_from_slice(&target.to_be_bytes());
+            }
+            Instruction::JumpI(target) => {
+                out.push(0x57);
+                out.extend_from_slice(&target.to_be_bytes());
+            }
+            Instruction::Arg(n) => out.extend_from_slice(&[0x34, *n]),
+            Instruction::Dup(n) => out.extend_from_slice(&[0x41, *n]),
+            Instruction::Swap(n) => out.extend_from_slice(&[0x42, *n]),
+            Instruction::Log(n) => out.extend_from_slice(&[0xa0, *n]),
+            simple => {
+                let (_, opcode, _) = Self::SIMPLE.iter().find(|(_, _, i)| i == simple).unwrap();
+                out.push(*opcode);
+            }
+        }
+    }
+
+    // 编码后的字节长度
+    fn encoded_len(&self) -> usize {
+        match self {
+            Instruction::Push(_) => 9,
+            Instruction::PushStr(s) => 3 + s.len(),
+            Instruction::Jump(_) | Instruction::JumpI(_) => 5,
+            Instruction::Arg(_) | Instruction::Dup(_) | Instruction::Swap(_) | Instruction::Log(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+// 将字节码解码为 (字节偏移, 指令) 列表
+pub fn decode(code: &[u8]) -> Result<Vec<(usize, Instruction)>, String> {
+    fn take(code: &[u8], pc: usize, n: usize) -> Result<&[u8], String> {
+        code.get(pc..pc + n).ok_or_else(|| format!("Truncated instruction at offset {}", pc - 1))
+    }
+
+    let mut instructions = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let offset = pc;
+        let opcode = code[pc];
+        pc += 1;
+        let instruction = match opcode {
+            0x60 => {
+                let bytes = take(code, pc, 8)?;
+                pc += 8;
+                Instruction::Push(u64::from_be_bytes(bytes.try_into().unwrap()))
+            }
+            0x61 => {
+                let len = u16::from_be_bytes(take(code, pc, 2)?.try_into().unwrap()) as usize;
+                pc += 2;
+                let bytes = take(code, pc, len)?;
+                pc += len;
+                let s = String::from_utf8(bytes.to_vec()).map_err(|_| format!("Invalid UTF-8 string at offset {}", offset))?;
+                Instruction::PushStr(s)
+            }
+            0x56 | 0x57 => {
+                let target = u32::from_be_bytes(take(code, pc, 4)?.try_into().unwrap());
+                pc += 4;
+                if opcode == 0x56 { Instruction::Jump(target) } else { Instruction::JumpI(target) }
+            }
+            0x34 | 0x41 | 0x42 | 0xa0 => {
+                let n = take(code, pc, 1)?[0];
+                pc += 1;
+                match opcode {
+                    0x34 => Instruction::Arg(n),
+                    0x41 => Instruction::Dup(n),
+                    0x42 => Instruction::Swap(n),
+                    _ if n > MAX_LOG_TOPICS => return Err(format!("Too many log topics at offset {}", offset)),
+                    _ => Instruction::Log(n),
+                }
+            }
+            _ => match Instruction::SIMPLE.iter().find(|(_, op, _)| *op == opcode) {
+                Some((_, _, instruction)) => instruction.clone(),
+                None => return Err(format!("Invalid opcode 0x{:02x} at offset {}", opcode, offset)),
+            },
+        };
+        instructions.push((offset, instruction));
+    }
+    Ok(instructions)
+}
+
+// 汇编器：每行一条指令，支持 "标签:" 定义、";" 注释，JUMP/JUMPI 以标签为目标
+//
+//     METHOD
+//     PUSHSTR "get"
+//     EQ
+//     JUMPI get
+//     ...
+// get:
+//     ARG 0
+//     SLOAD
+//     RETURN
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    enum Item {
+        Instruction(Instruction),
+        Jump(bool, String, usize), // (是否条件跳转, 标签, 行号)
+    }
+
+    let mut items = Vec::new();
+    let mut labels: HashMap<String, u32> = HashMap::new();
+    let mut offset = 0usize;
+    for (line_no, raw_line) in source.lines().enumerate() {
+        let line_no = line_no + 1;
+        let line = strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(label) = line.strip_suffix(':') {
+            if labels.insert(label.trim().to_string(), offset as u32).is_some() {
+                return Err(format!("Line {}: duplicate label '{}'", line_no, label.trim()));
+            }
+            continue;
+        }
+        let (mnemonic, operand) = match line.split_once(char::is_whitespace) {
+            Some((mnemonic, operand)) => (mnemonic.to_uppercase(), operand.trim()),
+            None => (line.to_uppercase(), ""),
+        };
+        let err = |message: &str| format!("Line {}: {}", line_no, message);
+        let small_operand = || operand.parse::<u8>().map_err(|_| err("expected operand 0-255"));
+        let item = match mnemonic.as_str() {
+            "PUSH" => Item::Instruction(Instruction::Push(operand.parse().map_err(|_| err("expected unsigned integer"))?)),
+            "PUSHSTR" => {
+                let s = operand
+                    .strip_prefix('"')
+                    .and_then(|s| s.strip_suffix('"'))
+                    .ok_or_else(|| err("expected quoted string"))?;
+                if s.len() > u16::MAX as usize {
+                    return Err(err("string too long"));
+                }
+                Item::Instruction(Instruction::PushStr(s.to_string()))
+            }
+            "JUMP" | "JUMPI" => {
+                if operand.is_empty() {
+                    return Err(err("expected label"));
+                }
+                Item::Jump(mnemonic == "JUMPI", operand.to_string(), line_no)
+            }
+            "ARG" => Item::Instruction(Instruction::Arg(small_operand()?)),
+            "DUP" => Item::Instruction(Instruction::Dup(small_operand()?)),
+            "SWAP" => Item::Instruction(Instruction::Swap(small_operand()?)),
+            "LOG" => match small_operand()? {
+                n if n <= MAX_LOG_TOPICS => Item::Instruction(Instruction::Log(n)),
+                _ => return Err(err("at most 4 log topics")),
+            },
+            _ => match Instruction::SIMPLE.iter().find(|(name, _, _)| *name == mnemonic) {
+                Some((_, _, instruction)) if operand.is_empty() => Item::Instruction(instruction.clone()),
+                Some(_) => return Err(err("unexpected operand")),
+                None => return Err(err(&format!("unknown instruction '{}'", mnemonic))),
+            },
+        };
+        offset += match &item {
+            Item::Instruction(instruction) => instruction.encoded_len(),
+            Item::Jump(..) => 5,
+        };
+        items.push(item);
+    }
+
+    let mut code = Vec::with_capacity(offset);
+    for item in items {
+        let instruction = match item {
+            Item::Instruction(instruction) => instruction,
+            Item::Jump(conditional, label, line_no) => {
+                let target = *labels.get(&label).ok_or_else(|| format!("Line {}: unknown label '{}'", line_no, label))?;
+                if conditional { Instruction::JumpI(target) } else { Instruction::Jump(target) }
+            }
+        };
+        instruction.encode(&mut code);
+    }
+    Ok(code)
+}
+
+// 去掉行尾注释（忽略字符串中的 ';'）
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// 合约调用环境
+pub struct CallContext<'a> {
+    pub caller: String,
+    pub address: String, // 合约自身的地址
+    pub value: u64,      // 随调用转入合约的金额
+    pub method: String,
+    pub args: Vec<String>,
+    pub gas_limit: u64,
+    pub balance_of: &'a dyn Fn(&str) -> u64,
+}
+
+// 执行成功的结果
+#[derive(Debug, Clone)]
+pub struct ExecutionResult {
+    pub return_value: String,
+    pub gas_used: u64,
+    pub transfers: Vec<(String, u64)>, // 合约发出的转账 (接收方, 金额)，由调用方在成功后执行
+    pub events: Vec<ContractEvent>,    // 合约发出的事件
+}
+
+// 执行失败：回滚所有状态修改，但已消耗的燃料仍然计入
+#[derive(Debug, Clone)]
+pub struct VmError {
+    pub message: String,
+    pub gas_used: u64,
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (gas used: {})", self.message, self.gas_used)
+    }
+}
+
+// 执行字节码，存储修改直接作用于 storage，调用方负责在失败时丢弃
+pub fn execute(code: &[u8], storage: &mut HashMap<String, String>, context: &CallContext) -> Result<ExecutionResult, VmError> {
+    let mut machine = Machine {
+        storage,
+        context,
+        stack: Vec::new(),
+        gas_used: 0,
+        transfers: Vec::new(),
+        events: Vec::new(),
+    };
+    match machine.run(code) {
+        Ok(return_value) => Ok(ExecutionResult {
+            return_value,
+            gas_used: machine.gas_used,
+            transfers: machine.transfers,
+            events: machine.events,
+        }),
+        Err(message) => Err(VmError { message, gas_used: machine.gas_used }),
+    }
+}
+
+struct Machine<'a, 'b> {
+    storage: &'a mut HashMap<String, String>,
+    context: &'a CallContext<'b>,
+    stack: Vec<Value>,
+    gas_used: u64,
+    transfers: Vec<(String, u64)>,
+    events: Vec<ContractEvent>,
+}
+
+impl Machine<'_, '_> {
+    fn run(&mut self, code: &[u8]) -> Result<String, String> {
+        let instructions = decode(code)?;
+        let targets: HashMap<usize, usize> = instructions.iter().enumerate().map(|(i, (offset, _))| (*offset, i)).collect();
+        let jump_to = |target: u32| {
+            targets.get(&(target as usize)).copied().ok_or_else(|| format!("Invalid jump target {}", target))
+        };
+
+        let mut pc = 0;
+        while let Some((_, instruction)) = instructions.get(pc) {
+            self.charge(instruction.gas_cost())?;
+            pc += 1;
+
+            match instruction {
+                Instruction::Stop => return Ok(String::new()),
+                Instruction::Add => self.arithmetic(u64::checked_add)?,
+                Instruction::Sub => self.arithmetic(u64::checked_sub)?,
+                Instruction::Mul => self.arithmetic(u64::checked_mul)?,
+                Instruction::Div => self.arithmetic(u64::checked_div)?,
+                Instruction::Mod => self.arithmetic(u64::checked_rem)?,
+                Instruction::Lt => self.arithmetic(|a, b| Some((a < b) as u64))?,
+                Instruction::Gt => self.arithmetic(|a, b| Some((a > b) as u64))?,
+                Instruction::And => self.arithmetic(|a, b| Some((a != 0 && b != 0) as u64))?,
+                Instruction::Or => self.arithmetic(|a, b| Some((a != 0 || b != 0) as u64))?,
+                Instruction::Eq => {
+                    let (a, b) = (self.pop()?, self.pop()?);
+                    self.push(Value::Int((a.to_string() == b.to_string()) as u64))?;
+                }
+                Instruction::IsZero | Instruction::Not => {
+                    let a = self.pop()?.as_int()?;
+                    self.push(Value::Int((a == 0) as u64))?;
+                }
+                Instruction::Concat => {
+                    let (a, b) = (self.pop()?.to_string(), self.pop()?.to_string());
+                    // 先检查长度并按结果的字节数计费，再分配结果
+                    let len = a.len() + b.len();
+                    if len > MAX_STRING_LEN {
+                        return Err(format!("String too long: {} bytes (max {})", len, MAX_STRING_LEN));
+                    }
+                    self.charge(len as u64 * GAS_PER_BYTE)?;
+                    self.push(Value::Str(b + &a))?;
+                }
+                Instruction::Caller => self.push(Value::Str(self.context.caller.clone()))?,
+                Instruction::CallValue => self.push(Value::Int(self.context.value))?,
+                Instruction::Address => self.push(Value::Str(self.context.address.clone()))?,
+                Instruction::Method => self.push(Value::Str(self.context.method.clone()))?,
+                Instruction::Arg(n) => {
+                    let arg = self.context.args.get(*n as usize).ok_or_else(|| format!("Missing argument {}", n))?;
+                    self.push(Value::parse(arg))?;
+                }
+                Instruction::ArgCount => self.push(Value::Int(self.context.args.len() as u64))?,
+                Instruction::Balance => {
+                    let address = self.pop()?.to_string();
+                    let balance = self.balance(&address);
+                    self.push(Value::Int(balance))?;
+                }
+                Instruction::Transfer => {
+                    let receiver = self.pop()?.to_string();
+                    let amount = self.pop()?.as_int()?;
+                    if self.balance(&self.context.address) < amount {
+                        return Err("Insufficient contract balance for transfer".to_string());
+                    }
+                    self.transfers.push((receiver, amount));
+                }
+                Instruction::Log(n) => {
+                    let data = self.pop()?.to_string();
+                    let mut topics = Vec::with_capacity(*n as usize);
+                    for _ in 0..*n {
+                        topics.push(self.pop()?.to_string());
+                    }
+                    topics.reverse();
+                    self.events.push(ContractEvent { contract_id: self.context.address.clone(), topics, data });
+                }
+                Instruction::Pop => {
+                    self.pop()?;
+                }
+                Instruction::Dup(n) => {
+                    let value = self.peek(*n)?.clone();
+                    self.push(value)?;
+                }
+                Instruction::Swap(n) => {
+                    let top = self.stack.len().checked_sub(1).ok_or("Stack underflow")?;
+                    let other = top.checked_sub(*n as usize + 1).ok_or("Stack underflow")?;
+                    self.stack.swap(top, other);
+                }
+                Instruction::SLoad => {
+                    let key = self.pop()?.to_string();
+                    let value = self.storage.get(&key).map_or(Value::Int(0), |v| Value::parse(v));
+                    self.push(value)?;
+                }
+                Instruction::SStore => {
+                    let key = self.pop()?.to_string();
+                    let value = self.pop()?.to_string();
+                    if value.len() > MAX_STORAGE_VALUE_LEN {
+                        return Err(format!("Storage value too long: {} bytes (max {})", value.len(), MAX_STORAGE_VALUE_LEN));
+                    }
+                    self.charge((key.len() + value.len()) as u64 * GAS_PER_BYTE)?;
+                    self.storage.insert(key, value);
+                }
+                Instruction::Jump(target) => pc = jump_to(*target)?,
+                Instruction::JumpI(target) => {
+                    if self.pop()?.as_int()? != 0 {
+                        pc = jump_to(*target)?;
+                    }
+                }
+                Instruction::Push(n) => self.push(Value::Int(*n))?,
+                Instruction::PushStr(s) => self.push(Value::Str(s.clone()))?,
+                Instruction::Return => return Ok(self.pop()?.to_string()),
+                Instruction::Revert => {
+                    let reason = self.pop().map(|v| v.to_string()).unwrap_or_default();
+                    return Err(format!("Reverted: {}", reason));
+                }
+            }
+        }
+        Ok(String::new())
+    }
+
+    // 消耗燃料，超过上限时把已用燃料记为上限并中止
+    fn charge(&mut self, cost: u64) -> Result<(), String> {
+        match self.gas_used.checked_add(cost) {
+            Some(total) if total <= self.context.gas_limit => {
+                self.gas_used = total;
+                Ok(())
+            }
+            _ => {
+                self.gas_used = self.context.gas_limit;
+                Err("Out of gas".to_string())
+            }
+        }
+    }
+
+    fn push(&mut self, value: Value) -> Result<(), String> {
+        if self.stack.len() >= MAX_STACK_DEPTH {
+            return Err("Stack overflow".to_string());
+        }
+        if let Value::Str(s) = &value {
+            if s.len() > MAX_STRING_LEN {
+                return Err(format!("String too long: {} bytes (max {})", s.len(), MAX_STRING_LEN));
+            }
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Value, String> {
+        self.stack.pop().ok_or_else(|| "Stack underflow".to_string())
+    }
+
+    fn peek(&self, depth: u8) -> Result<&Value, String> {
+        let index = self.stack.len().checked_sub(depth as usize + 1).ok_or("Stack underflow")?;
+        Ok(&self.stack[index])
+    }
+
+    // 弹出 b（栈顶）和 a，压入 op(a, b)，即按压栈顺序计算
+    fn arithmetic(&mut self, op: impl Fn(u64, u64) -> Option<u64>) -> Result<(), String> {
+        let b = self.pop()?.as_int()?;
+        let a = self.pop()?.as_int()?;
+        let result = op(a, b).ok_or("Arithmetic overflow or division by zero")?;
+        self.push(Value::Int(result))
+    }
+
+    // 计入本次执行中尚未生效的转账后的余额
+    fn balance(&self, address: &str) -> u64 {
+        let mut balance = (self.context.balance_of)(address);
+        for (receiver, amount) in &self.transfers {
+            if receiver == address {
+                balance = balance.saturating_add(*amount);
+            }
+            if self.context.address == address {
+                balance = balance.saturating_sub(*amount);
+            }
+        }
+        balance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smart_contract;
+
+    fn run(source: &str, method: &str, args: &[&str], gas_limit: u64, storage: &mut HashMap<String, String>) -> Result<ExecutionResult, VmError> {
+        let code = assemble(source).unwrap();
+        let balance_of = |_: &str| 0;
+        let context = CallContext {
+            caller: "alice".to_string(),
+            address: "contract".to_string(),
+            value: 0,
+            method: method.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            gas_limit,
+            balance_of: &balance_of,
+        };
+        execute(&code, storage, &context)
+    }
+
+    #[test]
+    fn kv_store_sets_and_gets_values() {
+        let source = include_str!("../contracts/kv_store.asm");
+        let mut storage = HashMap::new();
+        let result = run(source, "set", &["color", "blue"], 10_000, &mut storage).unwrap();
+        assert_eq!(result.events.len(), 1);
+        assert_eq!(result.events[0].topics, vec!["set", "color"]);
+        assert_eq!(storage["color"], "blue");
+        assert_eq!(run(source, "get", &["color"], 10_000, &mut storage).unwrap().return_value, "blue");
+        assert!(run(source, "other", &[], 10_000, &mut storage).unwrap_err().message.starts_with("Reverted"));
+    }
+
+    #[test]
+    fn doubling_strings_runs_out_of_gas_before_allocating() {
+        // 每轮把栈顶字符串复制后拼接，长度翻倍
+        let source = "PUSHSTR \"ab\"\nloop:\nDUP 0\nCONCAT\nJUMP loop";
+        let error = run(source, "", &[], 2000, &mut HashMap::new()).unwrap_err();
+        assert_eq!(error.message, "Out of gas");
+        assert_eq!(error.gas_used, 2000);
+
+        let error = run(source, "", &[], smart_contract::MAX_GAS_LIMIT, &mut HashMap::new()).unwrap_err();
+        assert!(error.message.starts_with("String too long"), "{}", error.message);
+    }
+
+    #[test]
+    fn concat_and_push_str_are_charged_per_byte() {
+        let short = run("PUSHSTR \"a\"\nPUSHSTR \"b\"\nCONCAT\nRETURN", "", &[], 1000, &mut HashMap::new()).unwrap();
+        let long = run("PUSHSTR \"aaaa\"\nPUSHSTR \"bbbb\"\nCONCAT\nRETURN", "", &[], 1000, &mut HashMap::new()).unwrap();
+        assert_eq!(long.return_value, "aaaabbbb");
+        // 多出的 6 字节在压栈和拼接时各计费一次
+        assert_eq!(long.gas_used - short.gas_used, 12);
+    }
+
+    #[test]
+    fn storage_values_are_capped() {
+        let mut storage = HashMap::new();
+        let source = "ARG 0\nPUSHSTR \"key\"\nSSTORE\nSTOP";
+        let value = "x".repeat(MAX_STORAGE_VALUE_LEN);
+        run(source, "", &[&value], 10_000, &mut storage).unwrap();
+        let error = run(source, "", &[&format!("{}x", value)], 10_000, &mut storage).unwrap_err();
+        assert!(error.message.starts_with("Storage value too long"), "{}", error.message);
+    }
+
+    #[test]
+    fn arithmetic_overflow_is_an_error() {
+        let error = run(&format!("PUSH {}\nPUSH 1\nADD", u64::MAX), "", &[], 100, &mut HashMap::new()).unwrap_err();
+        assert_eq!(error.message, "Arithmetic overflow or division by zero");
+        assert_eq!(error.gas_used, 5);
+    }
+
+    #[test]
+    fn bytecode_round_trips_through_decode() {
+        let code = assemble(include_str!("../contracts/vault.asm")).unwrap();
+        let mut encoded = Vec::new();
+        for (_, instruction) in decode(&code).unwrap() {
+            instruction.encode(&mut encoded);
+        }
+        assert_eq!(encoded, code);
+        assert!(decode(&[0x60, 0x01]).is_err());
+        assert!(decode(&[0xee]).is_err());
+    }
+}
@@ -1,10 +1,28 @@
+use std::fs;
+use std::io;
 use ring::signature::{Ed25519KeyPair, KeyPair};
 use ring::rand::SystemRandom;
-use hex;
+use serde::{Serialize, Deserialize};
+use crate::utxo::{OutPoint, TxOutput};
 
 #[derive(Debug)]
 pub struct Wallet {
     pub key_pair: Ed25519KeyPair,
+    pkcs8_bytes: Vec<u8>, // 私钥的 PKCS#8 编码，用于导出到密钥文件
+}
+
+// 密钥文件格式（私钥以明文保存，应存放在离线环境中）
+#[derive(Serialize, Deserialize, Debug)]
+struct Keystore {
+    address: String,
+    private_key: String, // PKCS#8 编码的十六进制
+}
+
+// Ed25519KeyPair 不能直接复制，通过私钥重新构造
+impl Clone for Wallet {
+    fn clone(&self) -> Self {
+        Self::from_pkcs8(&self.pkcs8_bytes).unwrap()
+    }
 }
 
 impl Wallet {
@@ -12,8 +30,34 @@ impl Wallet {
     pub fn new() -> Self {
         let rng = SystemRandom::new();
         let pkcs8_bytes = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
-        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8_bytes.as_ref()).unwrap();
-        Wallet { key_pair }
+        Self::from_pkcs8(pkcs8_bytes.as_ref()).unwrap()
+    }
+
+    // 从 PKCS#8 编码的私钥恢复钱包
+    pub fn from_pkcs8(pkcs8_bytes: &[u8]) -> Result<Self, String> {
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8_bytes).map_err(|_| "Invalid private key".to_string())?;
+        Ok(Wallet { key_pair, pkcs8_bytes: pkcs8_bytes.to_vec() })
+    }
+
+    // 将钱包保存为密钥文件
+    pub fn save_to_file(&self, filename: &str) -> io::Result<()> {
+        let keystore = Keystore {
+            address: self.address(),
+            private_key: hex::encode(&self.pkcs8_bytes),
+        };
+        fs::write(filename, serde_json::to_string_pretty(&keystore)?)
+    }
+
+    // 从密钥文件加载钱包
+    pub fn load_from_file(filename: &str) -> io::Result<Self> {
+        let keystore: Keystore = serde_json::from_str(&fs::read_to_string(filename)?)?;
+        let invalid = |err: String| io::Error::new(io::ErrorKind::InvalidData, err);
+        let pkcs8_bytes = hex::decode(&keystore.private_key).map_err(|e| invalid(e.to_string()))?;
+        let wallet = Self::from_pkcs8(&pkcs8_bytes).map_err(invalid)?;
+        if wallet.address() != keystore.address {
+            return Err(invalid("Keystore address does not match its private key".to_string()));
+        }
+        Ok(wallet)
     }
 
     // 获取钱包地址（公钥的十六进制表示）
@@ -21,16 +65,42 @@ impl Wallet {
         hex::encode(self.key_pair.public_key().as_ref())
     }
 
+    // Ed25519 私钥种子（PKCS#8 文档中第 16 到 48 字节），用于解密发给该地址的机密输出
+    pub fn seed(&self) -> [u8; 32] {
+        self.pkcs8_bytes[16..48].try_into().unwrap()
+    }
+
     // 签名数据
+    #[allow(dead_code)]
     pub fn sign(&self, data: &[u8]) -> Vec<u8> {
         self.key_pair.sign(data).as_ref().to_vec()
     }
 
     // 验证签名
+    #[allow(dead_code)]
     pub fn verify(&self, data: &[u8], signature: &[u8]) -> bool {
         let public_key = self.key_pair.public_key();
         let public_key_bytes = public_key.as_ref();
         let public_key = ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, public_key_bytes);
         public_key.verify(data, signature).is_ok()
     }
+
+    // 选币：按金额从大到小选取未花费输出直到覆盖目标金额，返回选中的输出和找零
+    pub fn select_coins(unspent: &[(OutPoint, TxOutput)], target: u64) -> Result<(Vec<OutPoint>, u64), String> {
+        let mut candidates: Vec<&(OutPoint, TxOutput)> = unspent.iter().collect();
+        candidates.sort_by_key(|(_, output)| std::cmp::Reverse(output.amount));
+        let mut selected = Vec::new();
+        let mut total = 0u64;
+        for (outpoint, output) in candidates {
+            if total >= target {
+                break;
+            }
+            selected.push(outpoint.clone());
+            total = total.checked_add(output.amount).ok_or("Coin total overflow")?;
+        }
+        if total < target {
+            return Err(format!("Insufficient funds: need {}, available {}", target, total));
+        }
+        Ok((selected, total - target))
+    }
 }
@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use wasmi::{Caller, Config, Engine, Error, ExternType, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+use wasmi::core::TrapCode;
+use crate::smart_contract::ContractEvent;
+use crate::vm::{CallContext, ExecutionResult, VmError, GAS_PER_BYTE, MAX_STORAGE_VALUE_LEN};
+
+// WebAssembly 合约运行时：在 wasmi 解释器中沙箱执行，按燃料计费
+//
+// 合约导出 `memory` 和以方法名命名的无参数函数，通过 "env" 模块中的宿主函数访问链上环境：
+//   storage_read(key_ptr, key_len, val_ptr, val_cap) -> i32   值的长度，不存在时为 -1
+//   storage_write(key_ptr, key_len, val_ptr, val_len)         值最多 MAX_STORAGE_VALUE_LEN 字节，按字节额外计费
+//   caller(ptr, cap) -> i32 / address(ptr, cap) -> i32        调用者 / 合约地址
+//   arg_count() -> i32 / arg(index, ptr, cap) -> i32         调用参数，不存在时为 -1
+//   value() -> i64 / self_balance() -> i64                   随调用转入的金额 / 合约余额
+//   transfer(to_ptr, to_len, amount: i64) -> i32             成功为 0，余额不足为 -1
+//   emit_event(topic_ptr, topic_len, data_ptr, data_len)
+//   set_return(ptr, len) / revert(ptr, len)
+// 写入缓冲区的函数返回完整长度，缓冲区不足时只写入 cap 字节。
+
+const MAX_MEMORY_BYTES: usize = 16 * 64 * 1024; // 最多 16 页线性内存
+
+// 宿主函数额外收取的燃料
+const STORAGE_READ_COST: u64 = 50;
+const STORAGE_WRITE_COST: u64 = 100;
+const TRANSFER_COST: u64 = 200;
+const EVENT_COST: u64 = 50;
+
+// 执行期间宿主一侧的状态
+struct HostState {
+    storage: HashMap<String, String>,
+    caller: String,
+    address: String,
+    value: u64,
+    args: Vec<String>,
+    balance: u64, // 合约余额（已扣除本次执行中的转账）
+    transfers: Vec<(String, u64)>,
+    events: Vec<ContractEvent>,
+    return_value: String,
+    limits: StoreLimits,
+}
+
+// 确定性配置：禁用浮点指令，开启燃料计量
+fn engine() -> Engine {
+    let mut config = Config::default();
+    config.floats(false).consume_fuel(true);
+    Engine::new(&config)
+}
+
+// 校验 WASM 模块：可以编译、导出内存且只导入已知的宿主函数
+pub fn validate(code: &[u8]) -> Result<(), String> {
+    let engine = engine();
+    let module = Module::new(&engine, code).map_err(|err| format!("Invalid WASM module: {}", err))?;
+    for import in module.imports() {
+        if import.module() != "env" || !is_host_function(import.name()) {
+            return Err(format!("Unknown import {}::{}", import.module(), import.name()));
+        }
+    }
+    match module.exports().find(|export| export.name() == "memory").map(|export| export.ty().clone()) {
+        Some(ExternType::Memory(ty)) if ty.initial_pages().to_bytes().is_some_and(|bytes| bytes <= MAX_MEMORY_BYTES) => Ok(()),
+        Some(ExternType::Memory(_)) => Err(format!("Initial WASM memory exceeds {} bytes", MAX_MEMORY_BYTES)),
+        _ => Err("WASM module must export its memory as 'memory'".to_string()),
+    }
+}
+
+fn is_host_function(name: &str) -> bool {
+    matches!(
+        name,
+        "storage_read" | "storage_write" | "caller" | "address" | "arg_count" | "arg"
+            | "value" | "self_balance" | "transfer" | "emit_event" | "set_return" | "revert"
+    )
+}
+
+// 执行合约方法，存储修改直接作用于 storage，调用方负责在失败时丢弃
+pub fn execute(code: &[u8], storage: &mut HashMap<String, String>, context: &CallContext) -> Result<ExecutionResult, VmError> {
+    let engine = engine();
+    let fail = |message: String, gas_used: u64| VmError { message, gas_used };
+    let module = Module::new(&engine, code).map_err(|err| fail(format!("Invalid WASM module: {}", err), 0))?;
+    let linker = linker(&engine).map_err(|err| fail(err.to_string(), 0))?;
+
+    let state = HostState {
+        storage: storage.clone(),
+        caller: context.caller.clone(),
+        address: context.address.clone(),
+        value: context.value,
+        args: context.args.clone(),
+        balance: (context.balance_of)(&context.address),
+        transfers: Vec::new(),
+        events: Vec::new(),
+        return_value: String::new(),
+        limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY_BYTES).instances(1).build(),
+    };
+    let mut store = Store::new(&engine, state);
+    store.limiter(|state| &mut state.limits);
+    store.set_fuel(context.gas_limit).map_err(|err| fail(err.to_string(), 0))?;
+    let gas_used = |store: &Store<HostState>| context.gas_limit - store.get_fuel().unwrap_or(0);
+
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .and_then(|pre| pre.ensure_no_start(&mut store).map_err(Error::from))
+        .map_err(|err| fail(format!("WASM instantiation failed: {}", err), gas_used(&store)))?;
+    let entry = instance
+        .get_typed_func::<(), ()>(&store, &context.method)
+        .map_err(|_| fail("Unknown method".to_string(), 0))?;
+
+    if let Err(err) = entry.call(&mut store, ()) {
+        let message = match err.as_trap_code() {
+            Some(TrapCode::OutOfFuel) => return Err(fail("Out of gas".to_string(), context.gas_limit)),
+            _ => err.to_string(),
+        };
+        return Err(fail(message, gas_used(&store)));
+    }
+
+    let gas_used = gas_used(&store);
+    let state = store.into_data();
+    *storage = state.storage;
+    Ok(ExecutionResult {
+        return_value: state.return_value,
+        gas_used,
+        transfers: state.transfers,
+        events: state.events,
+    })
+}
+
+// 注册宿主函数
+fn linker(engine: &Engine) -> Result<Linker<HostState>, wasmi::errors::LinkerError> {
+    let mut linker = Linker::new(engine);
+    linker.func_wrap("env", "storage_read", |mut caller: Caller<HostState>, key_ptr: i32, key_len: i32, val_ptr: i32, val_cap: i32| -> Result<i32, Error> {
+        charge(&mut caller, STORAGE_READ_COST)?;
+        let key = read_string(&caller, key_ptr, key_len)?;
+        match caller.data().storage.get(&key).cloned() {
+            Some(value) => write_buffer(&mut caller, val_ptr, val_cap, value.as_bytes()),
+            None => Ok(-1),
+        }
+    })?;
+    linker.func_wrap("env", "storage_write", |mut caller: Caller<HostState>, key_ptr: i32, key_len: i32, val_ptr: i32, val_len: i32| -> Result<(), Error> {
+        charge(&mut caller, STORAGE_WRITE_COST)?;
+        let key = read_string(&caller, key_ptr, key_len)?;
+        let value = read_string(&caller, val_ptr, val_len)?;
+        if value.len() > MAX_STORAGE_VALUE_LEN {
+            return Err(Error::new(format!("Storage value too long: {} bytes (max {})", value.len(), MAX_STORAGE_VALUE_LEN)));
+        }
+        charge(&mut caller, (key.len() + value.len()) as u64 * GAS_PER_BYTE)?;
+        caller.data_mut().storage.insert(key, value);
+        Ok(())
+    })?;
+    linker.func_wrap("env", "caller", |mut caller: Caller<HostState>, ptr: i32, cap: i32| -> Result<i32, Error> {
+        let address = caller.data().caller.clone();
+        write_buffer(&mut caller, ptr, cap, address.as_bytes())
+    })?;
+    linker.func_wrap("env", "address", |mut caller: Caller<HostState>, ptr: i32, cap: i32| -> Result<i32, Error> {
+        let address = caller.data().address.clone();
+        write_buffer(&mut caller, ptr, cap, address.as_bytes())
+    })?;
+    linker.func_wrap("env", "arg_count", |caller: Caller<HostState>| -> i32 {
+        caller.data().args.len() as i32
+    })?;
+    linker.func_wrap("env", "arg", |mut caller: Caller<HostState>, index: i32, ptr: i32, cap: i32| -> Result<i32, Error> {
+        match caller.data().args.get(index as usize).cloned() {
+            Some(arg) => write_buffer(&mut caller, ptr, cap, arg.as_bytes()),
+            None => Ok(-1),
+        }
+    })?;
+    linker.func_wrap("env", "value", |caller: Caller<HostState>| -> i64 {
+        caller.data().value as i64
+    })?;
+    linker.func_wrap("env", "self_balance", |caller: Caller<HostState>| -> i64 {
+        caller.data().balance as i64
+    })?;
+    linker.func_wrap("env", "transfer", |mut caller: Caller<HostState>, to_ptr: i32, to_len: i32, amount: i64| -> Result<i32, Error> {
+        charge(&mut caller, TRANSFER_COST)?;
+        let receiver = read_string(&caller, to_ptr, to_len)?;
+        let amount = u64::try_from(amount).map_err(|_| Error::new("Negative transfer amount"))?;
+        let state = caller.data_mut();
+        if state.balance < amount {
+            return Ok(-1);
+        }
+        state.balance -= amount;
+        state.transfers.push((receiver, amount));
+        Ok(0)
+    })?;
+    linker.func_wrap("env", "emit_event", |mut caller: Caller<HostState>, topic_ptr: i32, topic_len: i32, data_ptr: i32, data_len: i32| -> Result<(), Error> {
+        charge(&mut caller, EVENT_COST)?;
+        let topic = read_string(&caller, topic_ptr, topic_len)?;
+        let data = read_string(&caller, data_ptr, data_len)?;
+        let state = caller.data_mut();
+        let event = ContractEvent { contract_id: state.address.clone(), topics: vec![topic], data };
+        state.events.push(event);
+        Ok(())
+    })?;
+    linker.func_wrap("env", "set_return", |mut caller: Caller<HostState>, ptr: i32, len: i32| -> Result<(), Error> {
+        caller.data_mut().return_value = read_string(&caller, ptr, len)?;
+        Ok(())
+    })?;
+    linker.func_wrap("env", "revert", |caller: Caller<HostState>, ptr: i32, len: i32| -> Result<(), Error> {
+        let reason = read_string(&caller, ptr, len)?;
+        Err(Error::new(format!("Reverted: {}", reason)))
+    })?;
+    Ok(linker)
+}
+
+// 从剩余燃料中扣除宿主函数的费用
+fn charge(caller: &mut Caller<HostState>, cost: u64) -> Result<(), Error> {
+    let fuel = caller.get_fuel()?;
+    if fuel < cost {
+        caller.set_fuel(0)?;
+        return Err(Error::from(TrapCode::OutOfFuel));
+    }
+    caller.set_fuel(fuel - cost)?;
+    Ok(())
+}
+
+fn memory(caller: &Caller<HostState>) -> Result<wasmi::Memory, Error> {
+    caller
+        .get_export("memory")
+        .and_then(|export| export.into_memory())
+        .ok_or_else(|| Error::new("WASM module must export its memory as 'memory'"))
+}
+
+// 读取合约内存中的 UTF-8 字符串，先检查范围再复制，越界的长度不会导致分配
+fn read_string(caller: &Caller<HostState>, ptr: i32, len: i32) -> Result<String, Error> {
+    let data = memory(caller)?.data(caller);
+    let start = ptr as u32 as usize;
+    let bytes = start
+        .checked_add(len.max(0) as usize)
+        .and_then(|end| data.get(start..end))
+        .ok_or_else(|| Error::from(TrapCode::MemoryOutOfBounds))?;
+    String::from_utf8(bytes.to_vec()).map_err(|_| Error::new("Strings passed to the host must be UTF-8"))
+}
+
+// 向合约内存写入最多 cap 字节，返回数据的完整长度
+fn write_buffer(caller: &mut Caller<HostState>, ptr: i32, cap: i32, bytes: &[u8]) -> Result<i32, Error> {
+    let len = bytes.len().min(cap.max(0) as usize);
+    memory(caller)?
+        .write(caller, ptr as u32 as usize, &bytes[..len])
+        .map_err(|_| Error::from(TrapCode::MemoryOutOfBounds))?;
+    Ok(bytes.len() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kv_store() -> Vec<u8> {
+        wat::parse_str(include_str!("../contracts/kv_store.wat")).unwrap()
+    }
+
+    fn call(code: &[u8], storage: &mut HashMap<String, String>, caller: &str, method: &str, args: &[&str], gas_limit: u64) -> Result<ExecutionResult, VmError> {
+        let balance_of = |_: &str| 0;
+        let context = CallContext {
+            caller: caller.to_string(),
+            address: "contract".to_string(),
+            value: 0,
+            method: method.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            gas_limit,
+            balance_of: &balance_of,
+        };
+        execute(code, storage, &context)
+    }
+
+    #[test]
+    fn kv_store_runs_in_the_sandbox() {
+        let code = kv_store();
+        validate(&code).unwrap();
+        let mut storage = HashMap::new();
+        let result = call(&code, &mut storage, "alice", "set", &["color", "blue"], 100_000).unwrap();
+        assert_eq!(result.events[0].topics, vec!["set"]);
+        assert_eq!(result.events[0].data, "color");
+        assert!(result.gas_used > STORAGE_WRITE_COST);
+        call(&code, &mut storage, "bob", "set", &["size", "xl"], 100_000).unwrap();
+
+        assert_eq!(call(&code, &mut storage, "bob", "get", &["color"], 100_000).unwrap().return_value, "blue");
+        assert_eq!(call(&code, &mut storage, "bob", "owner", &[], 100_000).unwrap().return_value, "alice");
+        let error = call(&code, &mut storage, "bob", "get", &["missing"], 100_000).unwrap_err();
+        assert!(error.message.contains("Key not found"), "{}", error.message);
+        assert_eq!(call(&code, &mut storage, "bob", "other", &[], 100_000).unwrap_err().message, "Unknown method");
+    }
+
+    #[test]
+    fn infinite_loops_run_out_of_gas() {
+        let error = call(&kv_store(), &mut HashMap::new(), "alice", "spin", &[], 10_000).unwrap_err();
+        assert_eq!(error.message, "Out of gas");
+        assert_eq!(error.gas_used, 10_000);
+    }
+
+    #[test]
+    fn storage_writes_are_charged_per_byte_and_capped() {
+        // 直接从内存写入：值长度由参数给出
+        let writer = wat::parse_str(r#"
+            (module
+              (import "env" "storage_write" (func $storage_write (param i32 i32 i32 i32)))
+              (memory (export "memory") 1)
+              (func (export "short") (call $storage_write (i32.const 0) (i32.const 1) (i32.const 16) (i32.const 10)))
+              (func (export "long") (call $storage_write (i32.const 0) (i32.const 1) (i32.const 16) (i32.const 1025)))
+              (func (export "huge") (call $storage_write (i32.const 0) (i32.const 1) (i32.const 16) (i32.const 0x7fffffff))))
+        "#).unwrap();
+        let mut storage = HashMap::new();
+        let short = call(&writer, &mut storage, "alice", "short", &[], 100_000).unwrap();
+        assert!(short.gas_used >= STORAGE_WRITE_COST + 11 * GAS_PER_BYTE);
+        assert_eq!(storage.len(), 1);
+
+        let error = call(&writer, &mut storage, "alice", "long", &[], 100_000).unwrap_err();
+        assert!(error.message.contains("Storage value too long"), "{}", error.message);
+        // 超出内存的长度在分配之前就被拒绝
+        let error = call(&writer, &mut storage, "alice", "huge", &[], 100_000).unwrap_err();
+        assert!(error.message.contains("out of bounds"), "{}", error.message);
+    }
+
+    #[test]
+    fn modules_must_use_known_imports_and_export_memory() {
+        let unknown_import = wat::parse_str(r#"(module (import "env" "clock" (func)) (memory (export "memory") 1))"#).unwrap();
+        assert!(validate(&unknown_import).unwrap_err().starts_with("Unknown import"));
+        let no_memory = wat::parse_str(r#"(module (func (export "run")))"#).unwrap();
+        assert!(validate(&no_memory).is_err());
+        let huge_memory = wat::parse_str(r#"(module (memory (export "memory") 17))"#).unwrap();
+        assert!(validate(&huge_memory).is_err());
+        let floats = wat::parse_str(r#"(module (memory (export "memory") 1) (func (export "f") (drop (f32.const 1))))"#).unwrap();
+        assert!(validate(&floats).is_err());
+        assert!(validate(b"not wasm").is_err());
+    }
+}